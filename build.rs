@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     env,
     fs::File,
     io::{Error, Write},
@@ -8,7 +8,6 @@ use std::{
 
 use clap::{CommandFactory, ValueEnum};
 use clap_complete::generate_to;
-use clap_mangen::Man;
 use quote::quote;
 use serde::{Deserialize, Serialize};
 
@@ -93,6 +92,59 @@ fn collect_detectors(out_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn collect_fallback(out_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = env::current_dir().unwrap().join("data").join("fallback");
+    let mut templates = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("gitignore") {
+            continue;
+        }
+
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let contents = std::fs::read_to_string(&path)?;
+        templates.push((name, contents));
+    }
+    templates.sort();
+
+    let entries = templates
+        .iter()
+        .map(|(name, contents)| quote! { (#name, #contents) });
+    let fallback_code = quote! {
+        fn fallback_templates() -> Vec<(&'static str, &'static str)> {
+            vec![#(#entries),*]
+        }
+    };
+
+    let mut output = File::create(out_dir.join("fallback.rs"))?;
+    writeln!(output, "{}", fallback_code)?;
+
+    Ok(())
+}
+
+fn collect_builtin_aliases(out_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let json = env::current_dir()
+        .unwrap()
+        .join("data")
+        .join("builtin-aliases.json");
+    let content = std::fs::read_to_string(json)?;
+    let aliases: BTreeMap<String, Vec<String>> = serde_json::from_str(&content)?;
+
+    let entries = aliases
+        .iter()
+        .map(|(name, members)| quote! { (#name, &[#(#members),*]) });
+    let aliases_code = quote! {
+        fn builtin_aliases() -> Vec<(&'static str, &'static [&'static str])> {
+            vec![#(#entries),*]
+        }
+    };
+
+    let mut output = File::create(out_dir.join("builtin_aliases.rs"))?;
+    writeln!(output, "{}", aliases_code)?;
+
+    Ok(())
+}
+
 fn build_shell_completion(outdir: &Path) -> Result<(), Error> {
     let mut app = Cli::command();
     let shells = Shell::value_variants();
@@ -105,12 +157,10 @@ fn build_shell_completion(outdir: &Path) -> Result<(), Error> {
 }
 
 fn build_manpages(outdir: &Path) -> Result<(), Error> {
-    let app = Cli::command();
-
-    let file = outdir.join("git-ignore.1");
-    let mut file = File::create(file)?;
-
-    Man::new(app).render(&mut file)?;
+    for (name, page) in man_pages(Cli::command()) {
+        let mut file = File::create(outdir.join(name))?;
+        page.render(&mut file)?;
+    }
 
     Ok(())
 }
@@ -118,6 +168,8 @@ fn build_manpages(outdir: &Path) -> Result<(), Error> {
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rerun-if-changed=src/cli.rs");
     println!("cargo:rerun-if-changed=man");
+    println!("cargo:rerun-if-changed=data/fallback");
+    println!("cargo:rerun-if-changed=data/builtin-aliases.json");
 
     let outdir = match env::var_os("OUT_DIR") {
         None => return Ok(()),
@@ -133,6 +185,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_manpages(&path)?;
 
     collect_detectors(&out_path)?;
+    collect_fallback(&out_path)?;
+    collect_builtin_aliases(&out_path)?;
 
     Ok(())
 }
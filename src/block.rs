@@ -0,0 +1,154 @@
+use std::ops::Range;
+
+use anyhow::{bail, Result};
+
+/// Generated templates written to a file are wrapped in these markers, so they
+/// can be replaced on the next run without touching anything around them.
+pub const BLOCK_START: &str = "# >>> git-ignore >>>";
+pub const BLOCK_END: &str = "# <<< git-ignore <<<";
+
+/// Wraps generated templates in the block markers, using CRLF line endings if
+/// the contents do.
+pub fn wrap(contents: &str) -> String {
+    let newline = if contents.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let contents = contents.trim_matches(['\r', '\n']);
+
+    format!(
+        "{start}{nl}{contents}{nl}{end}{nl}",
+        start = BLOCK_START,
+        end = BLOCK_END,
+        nl = newline
+    )
+}
+
+/// Finds the byte range of the generated block in `contents`, including the
+/// markers. Fails if the markers are unbalanced or there is more than one
+/// block, since we can't know what to replace.
+pub fn find(contents: &str) -> Result<Option<Range<usize>>> {
+    let mut start: Option<(usize, usize)> = None;
+    let mut block = None;
+    let mut offset = 0;
+
+    for (number, line) in contents.split_inclusive('\n').enumerate() {
+        let number = number + 1;
+        match line.trim_end() {
+            BLOCK_START if start.is_some() => bail!(
+                "Found '{}' on line {} inside another generated block, remove one of them",
+                BLOCK_START,
+                number
+            ),
+            BLOCK_START if block.is_some() => bail!(
+                "Found a second generated block on line {}, remove one of them",
+                number
+            ),
+            BLOCK_START => start = Some((offset, number)),
+            BLOCK_END => match start.take() {
+                Some((begin, _)) => block = Some(begin..offset + line.len()),
+                None => bail!(
+                    "Found '{}' on line {} without a '{}' before it",
+                    BLOCK_END,
+                    number,
+                    BLOCK_START
+                ),
+            },
+            _ => {}
+        }
+        offset += line.len();
+    }
+
+    if let Some((_, number)) = start {
+        bail!(
+            "The generated block starting on line {} has no '{}'",
+            number,
+            BLOCK_END
+        );
+    }
+
+    Ok(block)
+}
+
+/// Replaces the generated block in `contents` with `generated`, returning
+/// `None` if there is no block.
+pub fn replace(contents: &str, generated: &str) -> Result<Option<String>> {
+    let Some(range) = find(contents)? else {
+        return Ok(None);
+    };
+
+    let mut result = String::with_capacity(contents.len() + generated.len());
+    result.push_str(&contents[..range.start]);
+    result.push_str(&wrap(generated));
+    result.push_str(&contents[range.end..]);
+
+    Ok(Some(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, replace, wrap};
+
+    const GENERATED: &str = "\n\n### Created by https://www.gitignore.io\n\ntarget/\n";
+
+    #[test]
+    fn wraps_in_markers() {
+        assert_eq!(
+            wrap(GENERATED),
+            "# >>> git-ignore >>>\n### Created by https://www.gitignore.io\n\ntarget/\n# <<< git-ignore <<<\n"
+        );
+        assert_eq!(
+            wrap("\r\n\r\ntarget/\r\n"),
+            "# >>> git-ignore >>>\r\ntarget/\r\n# <<< git-ignore <<<\r\n"
+        );
+    }
+
+    #[test]
+    fn replaces_only_the_block() {
+        let contents = "# Hand written\n*.log\n\n# >>> git-ignore >>>\nold/\n# <<< git-ignore <<<\n\n# More\n.env\n";
+        let replaced = replace(contents, "target/\n").unwrap().unwrap();
+        assert_eq!(
+            replaced,
+            "# Hand written\n*.log\n\n# >>> git-ignore >>>\ntarget/\n# <<< git-ignore <<<\n\n# More\n.env\n"
+        );
+        assert_eq!(replace(&replaced, "target/\n").unwrap().unwrap(), replaced);
+    }
+
+    #[test]
+    fn replaces_blocks_at_the_edges() {
+        let contents = "# >>> git-ignore >>>\nold/\n# <<< git-ignore <<<";
+        assert_eq!(
+            replace(contents, "new/").unwrap().unwrap(),
+            "# >>> git-ignore >>>\nnew/\n# <<< git-ignore <<<\n"
+        );
+
+        let contents = "*.log\r\n# >>> git-ignore >>>\r\nold/\r\n# <<< git-ignore <<<\r\n";
+        assert_eq!(
+            replace(contents, "new/\r\n").unwrap().unwrap(),
+            "*.log\r\n# >>> git-ignore >>>\r\nnew/\r\n# <<< git-ignore <<<\r\n"
+        );
+    }
+
+    #[test]
+    fn no_block_is_not_an_error() {
+        assert_eq!(find("*.log\n").unwrap(), None);
+        assert_eq!(replace("*.log\n", "target/").unwrap(), None);
+    }
+
+    #[test]
+    fn unbalanced_markers_are_errors() {
+        let err = find("*.log\n# >>> git-ignore >>>\ntarget/\n").unwrap_err();
+        assert!(err.to_string().contains("starting on line 2"));
+
+        let err = find("target/\n# <<< git-ignore <<<\n").unwrap_err();
+        assert!(err.to_string().contains("on line 2 without"));
+
+        let nested = "# >>> git-ignore >>>\n# >>> git-ignore >>>\n# <<< git-ignore <<<\n";
+        assert!(find(nested).unwrap_err().to_string().contains("line 2"));
+
+        let twice = "# >>> git-ignore >>>\n# <<< git-ignore <<<\n# >>> git-ignore >>>\n# <<< git-ignore <<<\n";
+        let err = find(twice).unwrap_err();
+        assert!(err.to_string().contains("second generated block on line 3"));
+    }
+}
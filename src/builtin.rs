@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+include!(concat!(env!("OUT_DIR"), "/builtin_aliases.rs"));
+
+/// How built-in aliases are labeled when listed.
+pub const BUILTIN_SOURCE: &str = "built-in";
+
+/// Aliases for templates that are commonly used together from
+/// `data/builtin-aliases.json`, baked into the binary. They work like the
+/// aliases in the config, which replace any with the same name.
+pub fn aliases() -> HashMap<String, Vec<String>> {
+    builtin_aliases()
+        .into_iter()
+        .map(|(name, members)| {
+            let members = members.iter().map(ToString::to_string).collect();
+            (name.to_string(), members)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::aliases;
+    use crate::config::validate_name;
+
+    #[test]
+    fn embeds_valid_aliases() {
+        let aliases = aliases();
+        for name in ["editors", "jsruntime", "jvm", "oses"] {
+            assert!(aliases.contains_key(name), "missing {}", name);
+        }
+        assert_eq!(aliases["oses"], ["macos", "windows", "linux"]);
+
+        for (name, members) in &aliases {
+            assert_eq!(&validate_name("alias", name).unwrap(), name);
+            assert!(!members.is_empty(), "{} has no members", name);
+            for member in members {
+                assert_eq!(&validate_name("template", member).unwrap(), member);
+            }
+        }
+    }
+}
@@ -11,26 +11,303 @@ pub struct Cli {
     /// List <templates> or all available templates.
     #[arg(short, long)]
     pub list: bool,
+    /// Show which source each template was downloaded from when listing
+    #[arg(long, requires = "list")]
+    pub origin: bool,
+    /// List bare template names without indentation or colors, the default
+    /// when stdout is not a terminal
+    #[arg(long, requires = "list", conflicts_with = "format")]
+    pub plain: bool,
+    /// List the kind of each template and what an alias expands to
+    #[arg(long, requires = "list", conflicts_with = "plain")]
+    pub long: bool,
+    /// Only list templates named exactly <templates>, ignoring case, instead
+    /// of every template containing them. Exits with 1 if none are found
+    #[arg(long, requires = "list")]
+    pub exact: bool,
+    /// Only list templates whose name starts with <templates>
+    #[arg(long, requires = "list", conflicts_with = "exact")]
+    pub prefix: bool,
+    /// Only list templates whose name or display name matches one of
+    /// <templates> as a regular expression, e.g. `^c` or `lang$`. Start a
+    /// pattern with `(?i)` to ignore case
+    #[arg(long, requires = "list", conflicts_with_all = ["exact", "prefix"])]
+    pub regex: bool,
+    /// Only list templates of this type, can be given more than once
+    #[arg(long = "type", value_name = "TYPE", value_enum, requires = "list")]
+    pub types: Vec<EntryType>,
     /// Update templates by fetching them from gitignore.io
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "offline")]
     pub update: bool,
+    /// Never fetch templates, only use the cache
+    #[arg(long, global = true)]
+    pub offline: bool,
+    /// Explain how templates are resolved and the cache is used, pass twice
+    /// to also show every download and file written. Lists every changed
+    /// template after updating.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// Only print warnings and errors to stderr
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
     /// Ignore all user defined aliases and templates
-    #[arg(short, long)]
+    ///
+    /// Names are otherwise resolved to a user template, then an alias and
+    /// finally a downloaded template, so an alias named `node` hides the `node`
+    /// template unless this is passed. Also limits `--list` to downloaded
+    /// templates.
+    #[arg(short, long, global = true, visible_alias = "raw")]
     pub simple: bool,
+    #[command(flatten)]
+    pub generate: GenerateArgs,
+    /// When to use colors, `auto` disables them when printing to a pipe or file
+    /// or when `NO_COLOR` is set
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// Cache templates in <PATH> instead of the default cache directory,
+    /// can also be set with `GIT_IGNORE_CACHE_DIR`
+    #[arg(long, global = true, value_name = "PATH")]
+    pub cache_dir: Option<std::path::PathBuf>,
+    /// Read the config from <PATH> instead of the default config file, can
+    /// also be set with `GIT_IGNORE_CONFIG`. User templates are kept in a
+    /// `templates` directory next to it
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<std::path::PathBuf>,
+    /// Configuration management
+    #[command(subcommand)]
+    pub cmd: Option<Cmds>,
+    /// Names of templates to show/search for
+    pub templates: Vec<String>,
+}
+
+/// The options that can be given before a subcommand as well as after it.
+const SHARED_OPTIONS: [&str; 7] = [
+    "cache_dir",
+    "config",
+    "color",
+    "offline",
+    "quiet",
+    "simple",
+    "verbose",
+];
+
+impl Cli {
+    /// Parses the command line like `Cli::parse`, exiting on errors.
+    pub fn parse_args() -> Self {
+        Cli::try_parse_args(std::env::args_os()).unwrap_or_else(|err| err.exit())
+    }
+
+    /// Parses `args`, letting the shared options come before a subcommand.
+    /// `args_conflicts_with_subcommands` stops looking for a subcommand after
+    /// any option, so it's turned off for the shared ones and the conflict is
+    /// checked for the rest instead.
+    pub fn try_parse_args<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        use clap::{parser::ValueSource, CommandFactory, FromArgMatches};
+
+        let mut cmd = Cli::command().args_conflicts_with_subcommands(false);
+        let matches = cmd.try_get_matches_from_mut(args)?;
+        if let Some((name, _)) = matches.subcommand() {
+            let given = cmd.get_arguments().find(|arg| {
+                let id = arg.get_id().as_str();
+                !SHARED_OPTIONS.contains(&id)
+                    && matches.value_source(id) == Some(ValueSource::CommandLine)
+            });
+            if let Some(arg) = given {
+                let arg = match arg.get_long() {
+                    Some(long) => format!("--{}", long),
+                    None => format!("<{}>", arg.get_id()),
+                };
+                let message = format!("the argument '{}' cannot be used with '{}'", arg, name);
+                return Err(cmd.error(clap::error::ErrorKind::ArgumentConflict, message));
+            }
+        }
+
+        Cli::from_arg_matches(&matches)
+    }
+}
+
+/// Options for generating templates, shared by `git ignore <templates>` and
+/// `git ignore generate <templates>`.
+#[derive(clap::Args, Debug)]
+pub struct GenerateArgs {
     /// Autodetect templates based on the existing files
     #[arg(short, long)]
     pub auto: bool,
     /// Write to `.gitignore` file instead of stdout
     #[arg(short, long)]
     pub write: bool,
-    /// Forcefully overwrite existing `.gitignore` file
-    #[arg(short, long, requires = "write")]
+    /// Write `.gitignore` in the current directory instead of at the root of
+    /// the repository
+    #[arg(long)]
+    pub here: bool,
+    /// Write to the repository's `.git/info/exclude` instead of stdout, to
+    /// ignore files without committing a `.gitignore`
+    #[arg(long, conflicts_with_all = ["write", "here", "output"])]
+    pub local_exclude: bool,
+    /// Write to your global excludes file, `core.excludesFile` or
+    /// `~/.config/git/ignore`, instead of stdout
+    #[arg(long, conflicts_with_all = ["write", "here", "output", "local_exclude"])]
+    pub global: bool,
+    /// Write to <PATH> instead of stdout, `-` means stdout
+    #[arg(short, long, value_name = "PATH", conflicts_with = "write")]
+    pub output: Option<std::path::PathBuf>,
+    /// Append to the `.gitignore` or --output file if it already exists
+    #[arg(long, conflicts_with = "overwrite")]
+    pub append: bool,
+    /// Replace the `.gitignore` or --output file if it already exists
+    #[arg(long, conflicts_with = "merge")]
+    pub overwrite: bool,
+    /// Append to an existing file without asking first, otherwise we ask when
+    /// run in a terminal and refuse when not
+    #[arg(short, long)]
+    pub yes: bool,
+    /// Deprecated, the same as --append
+    ///
+    /// This used to be described as forcefully overwriting the file, but it
+    /// has always appended to it. Use --append or --overwrite instead.
+    #[arg(short, long, conflicts_with = "overwrite")]
     pub force: bool,
-    /// Configuration management
-    #[command(subcommand)]
-    pub cmd: Option<Cmds>,
-    /// Names of templates to show/search for
-    pub templates: Vec<String>,
+    /// Append templates the file already has again instead of skipping them
+    #[arg(long, conflicts_with_all = ["overwrite", "merge", "remove"])]
+    pub force_duplicate: bool,
+    /// Only append the patterns the `.gitignore` or --output file doesn't
+    /// already have, implies `--write` unless --output is passed
+    #[arg(long)]
+    pub merge: bool,
+    /// Remove the patterns of <templates> from the `.gitignore` or --output
+    /// file instead of adding them, implies `--write` unless --output is passed
+    #[arg(long, conflicts_with_all = ["merge", "append", "overwrite", "force"])]
+    pub remove: bool,
+    /// Show what writing would change to the `.gitignore` or --output file as
+    /// a diff instead, exits with 1 if anything would change
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Copy the file to `<file>.bak` before changing it, defaults to `backup`
+    /// in the config
+    #[arg(long)]
+    pub backup: bool,
+    /// Don't wrap each template in `### <name> ###` comments
+    #[arg(long)]
+    pub no_section_headers: bool,
+    /// Don't end the output with a comment listing the templates in it
+    #[arg(long)]
+    pub no_footer: bool,
+    /// Leave <NAME> out even if an alias or `--auto` includes it, the same as
+    /// passing `'!<NAME>'`
+    #[arg(long, value_name = "NAME")]
+    pub exclude: Vec<String>,
+    /// Don't add `default_templates` from the config
+    #[arg(long)]
+    pub no_defaults: bool,
+    /// Add the templates and flags of the profile <NAME> from the config,
+    /// flags given on the command line take precedence
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Don't print or write anything if any of <templates> can't be found,
+    /// otherwise the ones that are found are and we exit with an error
+    #[arg(long)]
+    pub strict: bool,
+    /// Strip comments and blank lines from the generated templates
+    #[arg(long)]
+    pub compact: bool,
+    /// Line endings of the generated templates, defaults to `line_endings` in
+    /// the config or `lf`
+    #[arg(long, value_enum)]
+    pub line_endings: Option<LineEndings>,
+    /// The kind of ignore file to generate, `--write` writes to its file name
+    #[arg(long, value_enum, default_value_t = Kind::Git)]
+    pub kind: Kind,
+    /// How to print the generated or listed templates
+    #[arg(long, value_enum, default_value_t = Format::Plain, conflicts_with_all = ["write", "merge", "remove"])]
+    pub format: Format,
+    /// Never page the output, by default long output is shown with `GIT_PAGER`,
+    /// `core.pager` or `PAGER` when printing to a terminal
+    #[arg(long)]
+    pub no_pager: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The contents of a `.gitignore` file
+    Plain,
+    /// Every resolved template and the combined result as JSON
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryType {
+    /// A downloaded template
+    Template,
+    /// An alias from the config
+    Alias,
+    /// A user template from the config
+    User,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// A `.gitignore`
+    Git,
+    /// A `.dockerignore`, patterns match from the root of the build context
+    Docker,
+    /// An `.npmignore`, which can't exclude files npm always publishes
+    Npm,
+}
+
+impl Kind {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Kind::Git => ".gitignore",
+            Kind::Docker => ".dockerignore",
+            Kind::Npm => ".npmignore",
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Use colors when printing to a terminal
+    Auto,
+    /// Always use colors
+    Always,
+    /// Never use colors
+    Never,
+}
+
+#[derive(
+    clap::ValueEnum,
+    serde::Deserialize,
+    serde::Serialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+    /// Unix line endings
+    #[default]
+    Lf,
+    /// Windows line endings
+    Crlf,
+    /// The line endings of the current platform
+    Native,
+}
+
+impl LineEndings {
+    pub fn is_crlf(self) -> bool {
+        match self {
+            LineEndings::Lf => false,
+            LineEndings::Crlf => true,
+            LineEndings::Native => cfg!(windows),
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -39,20 +316,196 @@ pub enum Cmds {
     Alias(AliasCmd),
     #[command(subcommand)]
     Template(TemplateCmd),
+    #[command(subcommand)]
+    Cache(CacheCmd),
+    #[command(subcommand)]
+    Profile(ProfileCmd),
+    /// Generate <TEMPLATES>, the same as passing them without a subcommand
+    Generate {
+        #[command(flatten)]
+        args: GenerateArgs,
+        /// Names of templates or aliases, `'!<NAME>'` excludes one
+        templates: Vec<String>,
+    },
     /// Initialize user configuration
     Init {
         /// Forcefully create config, possibly overwrite existing
         #[clap(long)]
         force: bool,
-        /// Migrate from old config path to new
+        /// Move the config and user templates from the old path to the new one
         #[clap(long)]
         migrate: bool,
+        /// Register `git ignore` as an alias in the global git config instead
+        /// of creating the config, `--force` replaces a different alias
+        #[clap(long, conflicts_with = "migrate")]
+        git_alias: bool,
+        /// Install completions for <SHELL> along with the alias
+        #[clap(long, value_enum, value_name = "SHELL", requires = "git_alias")]
+        completions: Option<Shell>,
+        /// Print what would be changed without changing it
+        #[clap(long, requires = "git_alias")]
+        dry_run: bool,
+    },
+    /// Regenerate the templates in a file written with --write from the
+    /// current cache
+    Sync {
+        /// Only check whether the file is up to date, exits with 1 if it isn't
+        #[clap(long)]
+        check: bool,
+        /// Sync `.gitignore` in the current directory instead of at the root of
+        /// the repository
+        #[clap(long)]
+        here: bool,
+        /// Sync <FILE> instead of `.gitignore`
+        #[clap(conflicts_with = "here")]
+        file: Option<std::path::PathBuf>,
+    },
+    /// Find templates with names like <QUERY>, even with typos
+    Search {
+        /// What to search for, e.g. `pyhton` or `vscode`
+        query: String,
+        /// Show at most this many matches
+        #[clap(long, default_value_t = 10)]
+        limit: usize,
+        /// Only show matches scoring at least this, from 0 to 100
+        #[clap(long, default_value_t = 50)]
+        threshold: u32,
+        /// Treat <QUERY> as a regular expression and show the templates it
+        /// matches by name instead of the closest ones
+        #[clap(long)]
+        regex: bool,
+    },
+    /// Search the contents of the cached and user templates, printing every
+    /// matching line after the name of its template. Exits with 1 if nothing
+    /// matches
+    Grep {
+        /// What to search for, e.g. `*.tfstate` or `node_modules/`
+        pattern: String,
+        /// Treat <PATTERN> as a regular expression instead of text to find
+        #[clap(long)]
+        regex: bool,
+        /// Only print the names of the matching templates
+        #[clap(short = 'n', long)]
+        names_only: bool,
+    },
+    /// Print which templates a line of a `.gitignore` comes from, exits with
+    /// 1 if none do
+    Explain {
+        /// The pattern or line to look for, e.g. `pip-wheel-metadata/`
+        #[clap(required_unless_present = "line")]
+        pattern: Option<String>,
+        /// The file to read --line from and check for the pattern, the
+        /// `.gitignore` in the repository root by default
+        #[clap(long)]
+        file: Option<std::path::PathBuf>,
+        /// Explain this line of the file instead, counting from 1
+        #[clap(long, conflicts_with = "pattern")]
+        line: Option<usize>,
+        /// How to print the templates
+        #[clap(long, value_enum, default_value_t = Format::Plain)]
+        format: Format,
+    },
+    /// Check that the `.gitignore` covers the templates detected in the
+    /// current directory, exits with 1 listing the ones it doesn't
+    Check {
+        /// Detect templates in this directory instead, e.g. a project in a
+        /// monorepo. Every `.gitignore` from it up to the repository root is
+        /// checked
+        #[clap(long)]
+        path: Option<std::path::PathBuf>,
+        /// How to print the result
+        #[clap(long, value_enum, default_value_t = Format::Plain)]
+        format: Format,
+    },
+    /// Print what <NAMES> expand to without generating anything
+    Resolve {
+        /// Names of templates or aliases, `'!<NAME>'` excludes one
+        names: Vec<String>,
+        /// Include the templates detected in the current directory
+        #[clap(short, long)]
+        auto: bool,
+        /// Don't add `default_templates` from the config
+        #[clap(long)]
+        no_defaults: bool,
+        /// Include the templates of the profile <NAME> from the config
+        #[clap(long, value_name = "NAME")]
+        profile: Option<String>,
+        /// How to print what they expand to
+        #[clap(long, value_enum, default_value_t = Format::Plain)]
+        format: Format,
+    },
+    /// Print the contents of a single template, alias or user template
+    Show {
+        /// The name to show, e.g. `node`
+        name: String,
+    },
+    /// Print what a name refers to and what it shadows, user templates take
+    /// precedence over aliases, which take precedence over templates
+    Which {
+        /// The name to look up, e.g. `node`
+        name: String,
+    },
+    /// Remove duplicate patterns and extra blank lines from a `.gitignore`
+    Tidy {
+        /// Sort the patterns between comments and blank lines, negations are
+        /// never moved above the patterns they negate
+        #[clap(long)]
+        sort: bool,
+        /// Only show what would change as a diff, exits with 1 if anything
+        /// would
+        #[clap(long)]
+        dry_run: bool,
+        /// Tidy <FILE> instead of the `.gitignore` at the root of the
+        /// repository
+        file: Option<std::path::PathBuf>,
+    },
+    /// Check the config, user templates, cache and sources for problems, exits
+    /// with 1 if there are warnings and 2 if anything failed
+    Doctor {
+        /// Don't check if the sources can be reached
+        #[clap(long)]
+        offline: bool,
+    },
+    /// Count the cached templates, aliases and user templates
+    Stats {
+        /// How to print the counts
+        #[clap(long, value_enum, default_value_t = Format::Plain)]
+        format: Format,
+    },
+    /// Remove the cache, config and user templates
+    Purge {
+        /// Don't ask for confirmation
+        #[clap(short, long)]
+        yes: bool,
+        /// Only show what would be removed
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Show the manual page, or the one for <COMMAND>
+    Man {
+        /// A subcommand to show the manual page for, e.g. `alias`
+        command: Option<String>,
+        /// Print the page as roff instead of showing it with `man`, e.g. when
+        /// `man` isn't installed
+        #[clap(long)]
+        roff: bool,
     },
     /// Generate shell completion
     Completion {
-        /// Shell to generate completion for
-        #[clap(value_enum)]
-        shell: Shell,
+        /// Shell to generate completion for, every shell if writing to --out
+        #[clap(value_enum, required_unless_present = "out")]
+        shell: Option<Shell>,
+        /// Write the completions to files in <DIR> named like each shell
+        /// expects instead of printing them
+        #[clap(long, value_name = "DIR")]
+        out: Option<std::path::PathBuf>,
+        /// Write the completions to where the shell looks for them, like
+        /// `~/.config/fish/completions`
+        #[clap(long, conflicts_with = "out")]
+        install: bool,
+        /// Replace completion files that already exist
+        #[clap(long)]
+        force: bool,
     },
 }
 
@@ -63,25 +516,303 @@ pub enum AliasCmd {
     #[command(visible_alias = "ls")]
     List,
     /// Add a new alias
-    Add { name: String, aliases: Vec<String> },
+    Add {
+        name: String,
+        aliases: Vec<String>,
+        /// Replace an existing alias with the same name
+        #[clap(long)]
+        force: bool,
+    },
     /// Remove an alias
     #[command(visible_alias = "rm")]
-    Remove { name: String },
+    Remove {
+        name: String,
+        /// Remove it from the aliases that include it too, instead of only
+        /// warning about them
+        #[clap(long)]
+        cascade: bool,
+    },
+    /// Show an alias and the templates it expands to
+    Show {
+        name: String,
+        /// How to print the alias
+        #[clap(long, value_enum, default_value_t = Format::Plain)]
+        format: Format,
+    },
+    /// Rename an alias, keeping its members
+    #[command(visible_alias = "mv")]
+    Rename {
+        old: String,
+        new: String,
+        /// Replace an alias that already has the new name
+        #[clap(long)]
+        force: bool,
+    },
+    /// Print the aliases as TOML, or write them to <FILE>
+    Export { file: Option<std::path::PathBuf> },
+    /// Add the aliases from a file written by `alias export`
+    Import {
+        file: std::path::PathBuf,
+        /// Keep existing aliases with the same name as an imported one, the
+        /// default
+        #[clap(long, conflicts_with = "replace")]
+        merge: bool,
+        /// Replace existing aliases with the same name as an imported one
+        #[clap(long)]
+        replace: bool,
+    },
+    /// Add members to an existing alias
+    Append {
+        name: String,
+        #[clap(required = true)]
+        members: Vec<String>,
+    },
+    /// Remove members from an alias
+    RemoveMember {
+        name: String,
+        #[clap(required = true)]
+        members: Vec<String>,
+        /// Remove the alias if no members are left
+        #[clap(long)]
+        delete_empty: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 /// Manage local aliases
 pub enum TemplateCmd {
-    /// List available templates
+    /// List available templates and the paths of their files
     #[command(visible_alias = "ls")]
-    List,
-    /// Add a new template
-    Add { name: String, file_name: String },
+    List {
+        /// Print the contents of each template too
+        #[clap(long)]
+        content: bool,
+    },
+    /// Print the contents of a user template, its path goes to stderr
+    Show { name: String },
+    /// Add a new template, an existing file is used as it is
+    Add {
+        name: String,
+        file_name: String,
+        /// Replace an existing template with the same name
+        #[clap(long)]
+        force: bool,
+        /// Copy the contents of <PATH> into the template's file, `-` reads
+        /// them from stdin
+        #[clap(long, value_name = "PATH")]
+        from_file: Option<std::path::PathBuf>,
+        /// Download the contents from <URL> into the template's file, the URL
+        /// is kept in the config
+        #[clap(long, value_name = "URL", conflicts_with = "from_file")]
+        from_url: Option<String>,
+        /// Copy the patterns of the `.gitignore` in the current directory, or
+        /// <PATH>, into the template's file, without what `git ignore` added
+        #[clap(
+            long,
+            value_name = "PATH",
+            num_args = 0..=1,
+            default_missing_value = ".gitignore",
+            conflicts_with_all = ["from_file", "from_url"]
+        )]
+        from_gitignore: Option<std::path::PathBuf>,
+    },
     /// Remove a template
     #[command(visible_alias = "rm")]
-    Remove { name: String },
+    Remove {
+        name: String,
+        /// Remove it from the aliases that include it too, instead of only
+        /// warning about them
+        #[clap(long)]
+        cascade: bool,
+    },
+    /// Open a template's file in `VISUAL` or `EDITOR`
+    Edit { name: String },
+    /// Rename a template along with its file, keeping what it contains
+    #[command(visible_alias = "mv")]
+    Rename {
+        old: String,
+        new: String,
+        /// Replace a template that already has the new name
+        #[clap(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+/// Inspect the profiles in the config
+pub enum ProfileCmd {
+    /// List the profiles with their templates and flags
+    #[command(visible_alias = "ls")]
+    List,
+    /// Print a profile like it's written in the config
+    Show { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+/// Manage the template cache
+pub enum CacheCmd {
+    /// Show the location, size and age of the cache
+    Info,
+    /// Remove all cached templates
+    Clear,
+    /// Print the path to the cache directory
+    Path,
+    /// Check whether newer templates are available without downloading them,
+    /// exits with 1 if they are and 2 if the check failed
+    Check,
+    /// Bundle the cached templates into <FILE> for use on another machine
+    Export { file: std::path::PathBuf },
+    /// Replace the cached templates with those exported to <FILE>
+    Import { file: std::path::PathBuf },
 }
 
 pub fn print_completion<G: Generator>(gen: G, app: &mut Command) {
     generate(gen, app, app.get_name().to_string(), &mut io::stdout());
 }
+
+/// Every manual page with its file name, `git-ignore.1` followed by one for
+/// each subcommand like `git-ignore-alias.1`.
+pub fn man_pages(mut app: Command) -> Vec<(String, clap_mangen::Man)> {
+    app.build();
+    let source = format!(
+        "{} {}",
+        app.get_name(),
+        app.get_version().unwrap_or_default()
+    );
+    let mut pages = vec![clap_mangen::Man::new(app.clone())];
+    for cmd in app.get_subcommands().filter(|cmd| cmd.get_name() != "help") {
+        pages.push(clap_mangen::Man::new(cmd.clone()).source(&source));
+    }
+
+    pages
+        .into_iter()
+        .map(|page| (page.get_filename(), page))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use clap::Parser;
+
+    use super::{AliasCmd, CacheCmd, Cli, Cmds};
+    use crate::config::Config;
+
+    #[test]
+    fn global_options_come_before_subcommands() {
+        let cli =
+            Cli::try_parse_args(["git-ignore", "--config", "x.toml", "alias", "list"]).unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("x.toml")));
+        assert!(matches!(cli.cmd, Some(Cmds::Alias(AliasCmd::List))));
+        assert!(cli.templates.is_empty());
+
+        let cli =
+            Cli::try_parse_args(["git-ignore", "alias", "list", "--config", "x.toml"]).unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("x.toml")));
+        assert!(matches!(cli.cmd, Some(Cmds::Alias(AliasCmd::List))));
+
+        for args in [
+            &["--offline", "show", "rust"][..],
+            &["show", "rust", "--offline"],
+        ] {
+            let cli = Cli::try_parse_args(["git-ignore"].iter().chain(args)).unwrap();
+            assert!(cli.offline);
+            assert!(matches!(cli.cmd, Some(Cmds::Show { name }) if name == "rust"));
+        }
+
+        for args in [&["--raw", "which", "rust"][..], &["which", "rust", "-s"]] {
+            let cli = Cli::try_parse_args(["git-ignore"].iter().chain(args)).unwrap();
+            assert!(cli.simple);
+            assert!(matches!(cli.cmd, Some(Cmds::Which { name }) if name == "rust"));
+        }
+
+        for args in [&["-vv", "which", "rust"][..], &["which", "rust", "-vv"]] {
+            let cli = Cli::try_parse_args(["git-ignore"].iter().chain(args)).unwrap();
+            assert_eq!(cli.verbose, 2);
+        }
+
+        // The other options still can't be combined with a subcommand
+        for args in [&["-l", "cache", "path"][..], &["-u", "alias", "list"]] {
+            let err = Cli::try_parse_args(["git-ignore"].iter().chain(args)).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn cache_dir_comes_before_cache_commands() {
+        let parse = |args: &[&str]| Cli::try_parse_args(["git-ignore"].iter().chain(args)).unwrap();
+        let cli = parse(&["--cache-dir", "/tmp/c", "cache", "path"]);
+        assert_eq!(cli.cache_dir, Some(PathBuf::from("/tmp/c")));
+        assert!(matches!(cli.cmd, Some(Cmds::Cache(CacheCmd::Path))));
+
+        let cli = parse(&["--cache-dir", "/tmp/c", "--quiet", "cache", "import", "f"]);
+        assert_eq!(cli.cache_dir, Some(PathBuf::from("/tmp/c")));
+        assert!(cli.quiet && cli.templates.is_empty());
+        assert!(
+            matches!(cli.cmd, Some(Cmds::Cache(CacheCmd::Import { file })) if file == Path::new("f"))
+        );
+    }
+
+    #[test]
+    fn profiles_fill_in_flags_not_given() {
+        let config: Config = toml::from_str(
+            r#"
+[aliases]
+[templates]
+
+[profiles.Rust]
+templates = ["rust", "macos"]
+exclude = ["jetbrains"]
+write = true
+append = true
+compact = true
+
+[profiles.broken]
+append = true
+overwrite = true
+"#,
+        )
+        .unwrap();
+        let parse = |profile: &str| {
+            toml::from_str::<Config>(&format!(
+                "[aliases]\n[templates]\n[profiles.x]\n{}",
+                profile
+            ))
+        };
+        assert!(parse("write = 1").is_err());
+        assert!(parse("unknown = true").is_err());
+
+        let profile = config.profile("rust").unwrap();
+        assert_eq!(profile.templates, ["rust", "macos"]);
+        assert_eq!(profile.flags(), ["--write", "--append", "--compact"]);
+
+        let args = |flags: &[&str]| {
+            let cli = Cli::try_parse_from(["git-ignore"].iter().chain(flags));
+            let mut args = cli.unwrap().generate;
+            profile.apply(&mut args);
+            args
+        };
+        let applied = args(&[]);
+        assert!(applied.write && applied.append && applied.compact);
+        assert_eq!(applied.exclude, ["jetbrains"]);
+
+        // Where and how to write given on the command line win
+        let applied = args(&["--output", "out", "--overwrite", "--exclude", "node"]);
+        assert!(!applied.write && !applied.append && applied.overwrite);
+        assert_eq!(applied.output, Some(PathBuf::from("out")));
+        assert_eq!(applied.exclude, ["node", "jetbrains"]);
+        assert!(!args(&["--local-exclude", "--merge"]).write);
+
+        let err = config.profile("broken").unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Profile 'broken' is invalid: sets both 'append' and 'overwrite'"
+        );
+        let err = config.profile("go").unwrap_err().to_string();
+        assert_eq!(err, "No profile named 'go', the config has Rust, broken");
+        let empty: Config = toml::from_str("[aliases]\n[templates]\n").unwrap();
+        let err = empty.profile("go").unwrap_err().to_string();
+        assert_eq!(err, "No profile named 'go', the config has none");
+    }
+}
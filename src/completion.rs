@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::CommandFactory;
+use clap_complete::{generate_to, Generator, Shell};
+
+use crate::cli::Cli;
+
+/// The command completions are generated for.
+const BIN: &str = "git-ignore";
+
+/// Where completions for `shell` are conventionally installed for the current
+/// user, reading environment variables with `env`. Bash and fish load them
+/// from there on their own, zsh and elvish need to be told to.
+pub fn install_dir(shell: Shell, env: impl Fn(&str) -> Option<String>) -> Result<PathBuf> {
+    let var = |name: &str| {
+        env(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let home = || {
+        var("HOME").context("HOME isn't set, pass '--out' to choose where to write the completions")
+    };
+    let xdg = |name: &str, default: &str| match var(name) {
+        Some(dir) => Ok(dir),
+        None => home().map(|home| home.join(default)),
+    };
+
+    let dir = match shell {
+        Shell::Bash => xdg("XDG_DATA_HOME", ".local/share")?
+            .join("bash-completion")
+            .join("completions"),
+        Shell::Fish => xdg("XDG_CONFIG_HOME", ".config")?
+            .join("fish")
+            .join("completions"),
+        Shell::Zsh => match var("ZDOTDIR") {
+            Some(dir) => dir,
+            None => home()?,
+        }
+        .join(".zfunc"),
+        Shell::Elvish => xdg("XDG_CONFIG_HOME", ".config")?.join("elvish").join("lib"),
+        shell => bail!(
+            "{} has no conventional place for completions, pass '--out' to choose where to write them",
+            shell
+        ),
+    };
+
+    Ok(dir)
+}
+
+/// What else is needed for `shell` to load completions from `dir`, if
+/// anything.
+pub fn setup_hint(shell: Shell, dir: &Path) -> Option<String> {
+    match shell {
+        Shell::Zsh => Some(format!(
+            "Add 'fpath=({} $fpath)' before 'compinit' in your .zshrc to load them",
+            dir.display()
+        )),
+        Shell::Elvish => Some(format!("Add 'use {}' to your rc.elv to load them", BIN)),
+        _ => None,
+    }
+}
+
+/// The file in `dir` the completions for `shell` are written to.
+pub fn file(shell: Shell, dir: &Path) -> PathBuf {
+    dir.join(shell.file_name(BIN))
+}
+
+/// Writes the completions for each of `shells` to `dir`, named like each
+/// shell expects. Nothing is written if any of the files exist, unless
+/// `force`. Returns the files written.
+pub fn write(shells: &[Shell], dir: &Path, force: bool) -> Result<Vec<PathBuf>> {
+    let files: Vec<PathBuf> = shells.iter().map(|&shell| file(shell, dir)).collect();
+    if let Some(file) = files.iter().find(|file| file.exists() && !force) {
+        bail!(
+            "'{}' already exists, pass '--force' to replace it",
+            file.display()
+        );
+    }
+
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Could not create '{}'", dir.display()))?;
+    let mut app = Cli::command();
+    for (shell, file) in shells.iter().zip(&files) {
+        generate_to(*shell, &mut app, BIN, dir)
+            .with_context(|| format!("Could not write '{}'", file.display()))?;
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use clap_complete::Shell;
+
+    use super::{install_dir, setup_hint, write};
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("git-ignore-completion-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn finds_install_dirs_in_home() {
+        let home = [("HOME", "/home/me")];
+        let dir = |shell| install_dir(shell, env(&home)).unwrap();
+
+        assert_eq!(
+            dir(Shell::Bash),
+            Path::new("/home/me/.local/share/bash-completion/completions")
+        );
+        assert_eq!(
+            dir(Shell::Fish),
+            Path::new("/home/me/.config/fish/completions")
+        );
+        assert_eq!(dir(Shell::Zsh), Path::new("/home/me/.zfunc"));
+        assert_eq!(dir(Shell::Elvish), Path::new("/home/me/.config/elvish/lib"));
+        assert!(install_dir(Shell::PowerShell, env(&home)).is_err());
+    }
+
+    #[test]
+    fn prefers_xdg_dirs() {
+        let vars = [
+            ("HOME", "/home/me"),
+            ("XDG_DATA_HOME", "/data"),
+            ("XDG_CONFIG_HOME", "/config"),
+            ("ZDOTDIR", "/zsh"),
+        ];
+        let dir = |shell| install_dir(shell, env(&vars)).unwrap();
+
+        assert_eq!(
+            dir(Shell::Bash),
+            Path::new("/data/bash-completion/completions")
+        );
+        assert_eq!(dir(Shell::Fish), Path::new("/config/fish/completions"));
+        assert_eq!(dir(Shell::Zsh), Path::new("/zsh/.zfunc"));
+
+        // Empty variables are the same as unset ones
+        let vars = [("HOME", "/home/me"), ("XDG_CONFIG_HOME", "")];
+        assert_eq!(
+            install_dir(Shell::Fish, env(&vars)).unwrap(),
+            Path::new("/home/me/.config/fish/completions")
+        );
+        assert!(install_dir(Shell::Bash, env(&[])).is_err());
+    }
+
+    #[test]
+    fn writes_files_named_for_each_shell() {
+        let dir = temp_dir("names");
+        let files = write(&[Shell::Bash, Shell::Zsh, Shell::Fish], &dir, false).unwrap();
+
+        let names: Vec<_> = files
+            .iter()
+            .map(|file| file.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["git-ignore.bash", "_git-ignore", "git-ignore.fish"]);
+        assert!(files
+            .iter()
+            .all(|file| file.starts_with(&dir) && file.exists()));
+        assert!(std::fs::read_to_string(&files[1])
+            .unwrap()
+            .starts_with("#compdef git-ignore"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn installs_into_a_temp_home() {
+        let home = temp_dir("home");
+        let vars = [("HOME", home.to_str().unwrap())];
+        let dir = install_dir(Shell::Fish, env(&vars)).unwrap();
+
+        let files = write(&[Shell::Fish], &dir, false).unwrap();
+        assert_eq!(
+            files,
+            [home.join(".config/fish/completions/git-ignore.fish")]
+        );
+        assert!(setup_hint(Shell::Fish, &dir).is_none());
+        assert!(setup_hint(Shell::Zsh, &dir).is_some());
+        std::fs::remove_dir_all(home).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_overwrite_without_force() {
+        let dir = temp_dir("force");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("_git-ignore"), "mine").unwrap();
+
+        let err = write(&[Shell::Bash, Shell::Zsh], &dir, false).unwrap_err();
+        assert!(err.to_string().contains("pass '--force'"));
+        // Nothing is written when any of them would be replaced
+        assert!(!dir.join("git-ignore.bash").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("_git-ignore")).unwrap(),
+            "mine"
+        );
+
+        write(&[Shell::Bash, Shell::Zsh], &dir, true).unwrap();
+        assert_ne!(
+            std::fs::read_to_string(dir.join("_git-ignore")).unwrap(),
+            "mine"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,16 +1,24 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
     fs::{read_to_string, File},
-    io::Write,
-    path::{Path, PathBuf},
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
 use etcetera::AppStrategy;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
-use crate::ignore::{old_project_dirs, project_dirs, Type};
+use crate::{
+    cli::{GenerateArgs, LineEndings},
+    ignore::{old_project_dirs, project_dirs, TemplateSource, Type},
+    log::{info, paint, warning, DIM, EMPHASIS},
+};
+
+/// Environment variable that points at the config file to use.
+pub const CONFIG_ENV: &str = "GIT_IGNORE_CONFIG";
 
 fn config_file() -> PathBuf {
     project_dirs().config_dir().join("config.toml")
@@ -20,17 +28,406 @@ fn old_config_file() -> PathBuf {
     old_project_dirs().config_dir().join("config.toml")
 }
 
+/// Where user templates are read from.
+pub fn templates_dir() -> PathBuf {
+    project_dirs().config_dir().join("templates")
+}
+
+/// Picks the config file to use. A file passed on the command line wins over
+/// one in the environment, without either the default locations are searched.
+pub fn resolve_config_file(flag: Option<PathBuf>, env: Option<OsString>) -> Option<PathBuf> {
+    flag.or_else(|| env.filter(|file| !file.is_empty()).map(PathBuf::from))
+}
+
+/// Every path the config and user templates are stored in, including where
+/// older versions kept the config. Only `config` and the templates next to it
+/// when a config file was given.
+pub fn state_paths(config: Option<&Path>) -> Vec<PathBuf> {
+    if let Some(config) = config {
+        return vec![config.to_path_buf(), config.with_file_name("templates")];
+    }
+
+    // The old and new config are in the same place on some platforms
+    let mut paths = vec![config_file(), templates_dir(), old_config_file()];
+    paths.sort_unstable();
+    paths.dedup();
+    paths
+}
+
+/// Moves the user templates kept next to the old config to `new`, next to the
+/// migrated one. They're left where they are if `new` already exists.
+fn migrate_templates(old: &Path, new: &Path) -> Result<()> {
+    if old == new || !old.is_dir() {
+        return Ok(());
+    }
+    if new.exists() {
+        warning!(
+            "'{}' already exists, move the templates in '{}' there yourself",
+            new.display(),
+            old.display()
+        );
+        return Ok(());
+    }
+
+    std::fs::rename(old, new)
+        .with_context(|| format!("Could not move '{}' to '{}'", old.display(), new.display()))?;
+    info!("Migrated user templates to '{}'", new.display());
+    Ok(())
+}
+
+/// Removes `name` from `map` ignoring case, since names used to be added with
+/// whatever case they were given.
+fn remove_ignoring_case<V>(map: &mut HashMap<String, V>, name: &str) -> Option<V> {
+    let key = map
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case(name))?
+        .clone();
+    map.remove(&key)
+}
+
+/// Writes `map` with its keys sorted, so the config doesn't change more than
+/// it has to when it's written.
+fn sorted<V: Serialize, S: Serializer>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+/// Warns that the alias or template `name` hides the downloaded template with
+/// the same name when `shadows`, which is `None` when there's no cache to tell.
+fn warn_if_shadowing(what: &str, name: &str, shadows: Option<bool>) {
+    match shadows {
+        Some(true) => warning!(
+            "The {} '{}' hides the downloaded template with the same name, pass '--simple' to use the template",
+            what,
+            name
+        ),
+        Some(false) => {}
+        None => info!(
+            "Nothing is cached yet, so '{}' wasn't checked against the downloaded templates",
+            name
+        ),
+    }
+}
+
+/// Creates `file` with the heading a new user template `name` starts with.
+pub fn write_stub(name: &str, file: &Path) -> Result<()> {
+    let mut file =
+        File::create(file).with_context(|| format!("Could not create '{}'", file.display()))?;
+    file.write_all(format!("\n### {} ###\n", name).as_bytes())?;
+    Ok(())
+}
+
+/// Checks that `name` can be used as the name of an alias or template (a
+/// `what`) and lowercases it. Like the keys of the downloaded templates, only
+/// letters, digits and `+`, `_`, `.` or `-` are allowed.
+pub fn validate_name(what: &str, name: &str) -> Result<String> {
+    let name = name.to_lowercase();
+    if name.is_empty() {
+        bail!("The name of the {} can't be empty", what);
+    }
+    if name.contains(char::is_whitespace) {
+        bail!("The {} '{}' can't have whitespace in its name", what, name);
+    }
+    if name.contains(['/', '\\']) {
+        bail!(
+            "The {} '{}' can't have a path separator in its name",
+            what,
+            name
+        );
+    }
+    let allowed = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "+_.-".contains(c);
+    if let Some(c) = name.chars().find(|&c| !allowed(c)) {
+        bail!(
+            "The {} '{}' can't have '{}' in its name, only letters, digits and '+', '_', '.' or '-' are allowed",
+            what,
+            name,
+            c
+        );
+    }
+
+    Ok(name)
+}
+
+/// The file name of a template renamed from `old` to `new`: a file named after
+/// the template, with or without an extension, is named after `new`, others
+/// keep their name.
+fn renamed_file_name(file_name: &str, old: &str, new: &str) -> String {
+    let path = Path::new(file_name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    if !stem.eq_ignore_ascii_case(old) {
+        return file_name.to_string();
+    }
+    match path.extension() {
+        Some(extension) => format!("{}.{}", new, extension.to_string_lossy()),
+        None => new.to_string(),
+    }
+}
+
+/// Fails if `file_name` isn't a plain file name in the templates directory.
+fn validate_file_name(file_name: &str) -> Result<()> {
+    let mut components = Path::new(file_name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !file_name.contains(['/', '\\']) => Ok(()),
+        _ => bail!(
+            "The template file '{}' must be a file name in the templates directory, not a path",
+            file_name
+        ),
+    }
+}
+
+/// Lowercases and trims `members`, dropping empty ones and any that are
+/// already in `existing` or earlier in `members`.
+fn new_members(existing: &[String], members: &[String]) -> Vec<String> {
+    let mut added: Vec<String> = Vec::new();
+    for member in members.iter().map(|member| member.trim().to_lowercase()) {
+        if !member.is_empty() && !existing.contains(&member) && !added.contains(&member) {
+            added.push(member);
+        }
+    }
+    added
+}
+
+/// Lowercases and trims the names and members of `aliases` like they're
+/// written by `alias add`, for configs written before they were. Aliases
+/// whose names only differ in case are merged, in the order of their names.
+fn normalize_aliases(aliases: HashMap<String, Vec<String>>) -> HashMap<String, Vec<String>> {
+    let mut normalized: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, members) in aliases.into_iter().collect::<BTreeMap<_, _>>() {
+        let existing = normalized.entry(name.trim().to_lowercase()).or_default();
+        let added = new_members(existing, &members);
+        existing.extend(added);
+    }
+    normalized
+}
+
+/// The contents of a new user template, along with where they were read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateContents {
+    pub source: String,
+    pub contents: String,
+    /// The URL they were downloaded from, kept in the config
+    pub url: Option<String>,
+}
+
+impl TemplateContents {
+    /// Checks that `bytes` read from `source` are text.
+    pub fn new(source: String, bytes: Vec<u8>) -> Result<Self> {
+        let Ok(contents) = String::from_utf8(bytes) else {
+            bail!("'{}' isn't UTF-8 text", source);
+        };
+        if contents.contains('\0') {
+            bail!("'{}' isn't text, it has NUL bytes", source);
+        }
+
+        Ok(Self {
+            source,
+            contents,
+            url: None,
+        })
+    }
+
+    /// Reads the file `path`, or stdin if it's `-`.
+    pub fn read(path: &Path) -> Result<Self> {
+        if path == Path::new("-") {
+            let mut bytes = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut bytes)
+                .context("Could not read the template from stdin")?;
+            return Self::new("stdin".to_string(), bytes);
+        }
+
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Could not read '{}'", path.display()))?;
+        Self::new(path.display().to_string(), bytes)
+    }
+}
+
+/// Aliases on their own, as they're exported and imported to share them
+/// between configs.
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Aliases {
+    pub aliases: BTreeMap<String, Vec<String>>,
+}
+
+impl Aliases {
+    /// Parses exported aliases, checking that every alias has a name and
+    /// members and that no two names only differ in case.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let parsed: Aliases = toml::from_str(contents)?;
+        let mut seen = Vec::new();
+        for (name, members) in &parsed.aliases {
+            let key = validate_name("alias", name)?;
+            if members.is_empty() {
+                bail!("Alias '{}' has no members", name);
+            }
+            if seen.contains(&key) {
+                bail!("Alias '{}' is in the file more than once", name);
+            }
+            seen.push(key);
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Templates and flags used together with `--profile <name>`, each flag is
+/// named like the option it sets.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub auto: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub write: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub here: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub append: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub overwrite: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub merge: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub backup: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub strict: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub compact: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub no_section_headers: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub no_footer: bool,
+}
+
+impl Profile {
+    /// The flags it sets, like they're passed on the command line.
+    pub fn flags(&self) -> Vec<&'static str> {
+        [
+            (self.auto, "--auto"),
+            (self.write, "--write"),
+            (self.here, "--here"),
+            (self.output.is_some(), "--output"),
+            (self.append, "--append"),
+            (self.overwrite, "--overwrite"),
+            (self.merge, "--merge"),
+            (self.backup, "--backup"),
+            (self.strict, "--strict"),
+            (self.compact, "--compact"),
+            (self.no_section_headers, "--no-section-headers"),
+            (self.no_footer, "--no-footer"),
+        ]
+        .into_iter()
+        .filter_map(|(set, flag)| set.then_some(flag))
+        .collect()
+    }
+
+    /// Sets the flags in `args` that weren't given on the command line. Where
+    /// to write and how are only taken from the profile if neither was given.
+    pub fn apply(&self, args: &mut GenerateArgs) {
+        let destination =
+            args.write || args.here || args.output.is_some() || args.local_exclude || args.global;
+        if !destination {
+            args.write |= self.write;
+            args.here |= self.here;
+            args.output.clone_from(&self.output);
+        }
+        if !(args.append || args.force || args.overwrite || args.merge || args.remove) {
+            args.append |= self.append;
+            args.overwrite |= self.overwrite;
+            args.merge |= self.merge;
+        }
+
+        args.auto |= self.auto;
+        args.backup |= self.backup;
+        args.strict |= self.strict;
+        args.compact |= self.compact;
+        args.no_section_headers |= self.no_section_headers;
+        args.no_footer |= self.no_footer;
+        args.exclude.extend(self.exclude.iter().cloned());
+    }
+
+    /// Fails if it sets options that can't be used together.
+    fn validate(&self) -> Result<()> {
+        let conflicts = [
+            (self.write && self.output.is_some(), "write", "output"),
+            (self.append && self.overwrite, "append", "overwrite"),
+            (self.merge && self.overwrite, "merge", "overwrite"),
+        ];
+        if let Some((_, a, b)) = conflicts.into_iter().find(|(both, _, _)| *both) {
+            bail!("sets both '{}' and '{}'", a, b);
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     #[serde(skip)]
     pub path: PathBuf,
+    /// Where user templates are read from
+    #[serde(skip)]
+    pub templates_dir: PathBuf,
+    #[serde(serialize_with = "sorted")]
     pub aliases: HashMap<String, Vec<String>>,
+    #[serde(serialize_with = "sorted")]
     pub templates: HashMap<String, String>,
+    /// Where user templates added with `--from-url` were downloaded from
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub template_urls: HashMap<String, String>,
+    /// Templates or aliases added to everything generated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_templates: Vec<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub profiles: HashMap<String, Profile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_ttl_days: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<TemplateSource>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_timeout_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_update: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_download_mb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_endings: Option<LineEndings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<bool>,
+    /// Whether the built-in aliases can be used, defaults to true
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub builtin_aliases: Option<bool>,
 }
 
 impl Config {
-    pub fn create(force: bool, migrate: bool) -> Result<()> {
-        let config_file = config_file();
+    /// Creates the config at `path`, or the default location without it.
+    pub fn create(path: Option<PathBuf>, force: bool, migrate: bool) -> Result<()> {
+        let config_file = path.unwrap_or_else(config_file);
         Config::create_dir(
             config_file
                 .parent()
@@ -44,119 +441,597 @@ impl Config {
                     .context("Could not copy old config file to new location")?;
                 std::fs::remove_file(&old_config_file)
                     .context("Could not remove old config file")?;
-                eprintln!(
-                    "{}: Migrated old config file to new location",
-                    "INFO".bold().blue()
-                );
+                info!("Migrated old config file to new location");
+                migrate_templates(
+                    &old_config_file.with_file_name("templates"),
+                    &config_file.with_file_name("templates"),
+                )?;
             } else {
-                eprintln!(
-                    "{}: No old config file found, nothing to migrate",
-                    "WARN".bold().red()
-                );
+                warning!("No old config file found, nothing to migrate");
             }
         }
 
         if config_file.exists() && !force {
-            eprintln!("{}: config already exist", "INFO".bold().blue());
+            info!("config already exist");
             return Ok(());
         }
 
         if config_file.exists() && force {
-            eprintln!("{}: overwriting existing config file", "WARN".bold().red());
+            warning!("overwriting existing config file");
         }
 
         let config = Config::new(config_file);
         config.write()
     }
 
-    pub fn from_dir() -> Option<Self> {
-        let config_file = Config::find_config_file()?;
-        if config_file.exists() {
-            let file = Path::new(&config_file);
-            let file = match read_to_string(file) {
-                Ok(content) => content,
-                Err(_) => return None,
-            };
-
-            match toml::from_str::<Config>(&file).as_mut() {
-                Ok(config) => {
-                    config.path = config_file;
-                    Some(config.clone())
-                }
-                Err(_) => None,
-            }
-        } else {
-            None
-        }
+    pub fn from_dir(path: Option<&Path>) -> Option<Self> {
+        Config::load(path).ok().flatten()
     }
 
-    pub fn list_aliases(&self) {
-        if self.aliases.is_empty() {
-            return println!("{}", "No aliases defined".blue());
-        }
+    /// Reads the config file at `path`, or the one in the default location
+    /// without it, failing if it can't be read or parsed.
+    pub fn load(path: Option<&Path>) -> Result<Option<Self>> {
+        let config_file = match path {
+            Some(path) if path.exists() => path.to_path_buf(),
+            Some(_) => return Ok(None),
+            None => match Config::find_config_file() {
+                Some(config_file) => config_file,
+                None => return Ok(None),
+            },
+        };
+
+        let file = read_to_string(&config_file)
+            .with_context(|| format!("Could not read '{}'", config_file.display()))?;
+        let mut config = toml::from_str::<Config>(&file)
+            .with_context(|| format!("Could not parse '{}'", config_file.display()))?;
+        config.templates_dir = match path {
+            Some(_) => config_file.with_file_name("templates"),
+            None => templates_dir(),
+        };
+        config.path = config_file;
+        config.aliases = normalize_aliases(std::mem::take(&mut config.aliases));
+        config.warn_about_invalid_names();
+
+        Ok(Some(config))
+    }
 
-        println!("{}", "Available aliases:".bold().green());
-        for (name, aliases) in &self.aliases {
-            println!("{} => {:?}", name.blue(), aliases);
+    /// Warns about aliases and templates added before their names were
+    /// validated, they still work as long as they can be given.
+    fn warn_about_invalid_names(&self) {
+        let aliases = self.aliases.keys().map(|name| ("alias", name));
+        let templates = self.templates.keys().map(|name| ("template", name));
+        let mut names: Vec<_> = aliases.chain(templates).collect();
+        names.sort_unstable();
+        for (what, name) in names {
+            if let Err(err) = validate_name(what, name) {
+                warning!("{}, rename it in '{}'", err, self.path.display());
+            }
         }
     }
 
-    pub fn add_alias(&mut self, name: String, aliases: Vec<String>) -> Result<()> {
+    /// Adds an alias, lowercased like every name is when it's looked up. An
+    /// existing alias is only replaced when `force`, see `warn_if_shadowing`
+    /// for `shadows`.
+    pub fn add_alias(
+        &mut self,
+        name: String,
+        aliases: Vec<String>,
+        force: bool,
+        shadows: Option<bool>,
+    ) -> Result<()> {
+        let name = validate_name("alias", &name)?;
+        if let Some(existing) = self.alias_mut(&name).filter(|_| !force) {
+            bail!(
+                "Alias '{}' already exists for {:?}, pass '--force' to replace it",
+                name,
+                existing
+            );
+        }
+        let aliases = new_members(&[], &aliases);
         println!("Created alias {} for {:?}", name.blue(), aliases);
+        warn_if_shadowing("alias", &name, shadows);
+        remove_ignoring_case(&mut self.aliases, &name);
         self.aliases.insert(name, aliases);
         self.write()
     }
 
-    pub fn remove_alias(&mut self, name: &str) -> Result<()> {
-        if self.aliases.remove(name).is_some() {
+    /// Removes the alias `name`, see `remove_references` for `cascade` and
+    /// `downloaded`.
+    pub fn remove_alias(
+        &mut self,
+        name: &str,
+        cascade: bool,
+        downloaded: Option<bool>,
+    ) -> Result<()> {
+        if remove_ignoring_case(&mut self.aliases, name).is_some() {
             println!("Removed alias {}", name.blue());
+            self.remove_references(name, cascade, downloaded);
         } else {
             println!("No alias named {} found", name.blue());
         }
         self.write()
     }
 
-    pub fn list_templates(&self) {
+    /// Adds `members` to the end of the alias `name`, skipping ones it
+    /// already has.
+    pub fn append_to_alias(&mut self, name: &str, members: Vec<String>) -> Result<()> {
+        let Some(existing) = self.alias_mut(name) else {
+            bail!(
+                "No alias named '{}', create it with `git ignore alias add {}`",
+                name,
+                name
+            );
+        };
+        let added = new_members(existing, &members);
+        existing.extend(added);
+        println!("Updated alias {} to {:?}", name.blue(), existing);
+        self.write()
+    }
+
+    /// Removes `members` from the alias `name`. An alias left without members
+    /// is only removed when `delete_empty`.
+    pub fn remove_alias_members(
+        &mut self,
+        name: &str,
+        members: &[String],
+        delete_empty: bool,
+        downloaded: Option<bool>,
+    ) -> Result<()> {
+        let Some(existing) = self.alias_mut(name) else {
+            bail!("No alias named '{}'", name);
+        };
+        for member in members {
+            if !existing.iter().any(|m| m.eq_ignore_ascii_case(member)) {
+                println!("{} isn't in alias {}", member.yellow(), name.blue());
+            }
+        }
+        let remaining = new_members(&[], existing)
+            .into_iter()
+            .filter(|m| !members.iter().any(|member| m.eq_ignore_ascii_case(member)))
+            .collect::<Vec<_>>();
+
+        if remaining.is_empty() {
+            if !delete_empty {
+                bail!(
+                    "Alias '{}' would have no members left, pass '--delete-empty' to remove it",
+                    name
+                );
+            }
+            return self.remove_alias(name, false, downloaded);
+        }
+
+        println!("Updated alias {} to {:?}", name.blue(), remaining);
+        *existing = remaining;
+        self.write()
+    }
+
+    /// Renames the alias `old` to `new`, replacing an alias named `new` only
+    /// when `force`.
+    pub fn rename_alias(
+        &mut self,
+        old: &str,
+        new: &str,
+        force: bool,
+        shadows: Option<bool>,
+    ) -> Result<()> {
+        let new = validate_name("alias", new)?;
+        let renamed = !old.eq_ignore_ascii_case(&new);
+        if renamed && self.alias_mut(&new).is_some() && !force {
+            bail!(
+                "An alias named '{}' already exists, pass '--force' to replace it",
+                new
+            );
+        }
+        let Some(members) = remove_ignoring_case(&mut self.aliases, old) else {
+            bail!("No alias named '{}'", old);
+        };
+
+        remove_ignoring_case(&mut self.aliases, &new);
+        println!("Renamed alias {} to {}", old.blue(), new.blue());
+        warn_if_shadowing("alias", &new, shadows);
+        self.aliases.insert(new, members);
+        self.write()
+    }
+
+    /// The aliases as TOML, sorted by name.
+    pub fn export_aliases(&self) -> Result<String> {
+        let aliases = Aliases {
+            aliases: self.aliases.clone().into_iter().collect(),
+        };
+        Ok(toml::to_string_pretty(&aliases)?)
+    }
+
+    /// Adds the aliases exported to `file`. An alias with the same name and
+    /// other members is kept unless `replace`, either way it's reported.
+    pub fn import_aliases(&mut self, file: &Path, replace: bool) -> Result<()> {
+        let contents =
+            read_to_string(file).with_context(|| format!("Could not read '{}'", file.display()))?;
+        let imported = Aliases::parse(&contents)
+            .with_context(|| format!("Could not import aliases from '{}'", file.display()))?;
+
+        let mut added = 0;
+        for (name, members) in imported.aliases {
+            let members = new_members(&[], &members);
+            match self.alias_mut(&name) {
+                Some(existing) if *existing == members => {}
+                Some(existing) if replace => {
+                    warning!("Replaced alias '{}', it was {:?}", name, existing);
+                    *existing = members;
+                    added += 1;
+                }
+                Some(existing) => warning!(
+                    "Kept alias '{}' as {:?} instead of {:?}, pass '--replace' to import it",
+                    name,
+                    existing,
+                    members
+                ),
+                None => {
+                    self.aliases.insert(name.to_lowercase(), members);
+                    added += 1;
+                }
+            }
+        }
+
+        info!("Imported {} aliases from '{}'", added, file.display());
+        self.write()
+    }
+
+    fn alias_mut(&mut self, name: &str) -> Option<&mut Vec<String>> {
+        self.aliases
+            .iter_mut()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, members)| members)
+    }
+
+    /// The profile `name`, ignoring case, failing if there is none or it's
+    /// invalid.
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        let Some((name, profile)) = self
+            .profiles
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        else {
+            let mut names: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            if names.is_empty() {
+                bail!("No profile named '{}', the config has none", name);
+            }
+            bail!(
+                "No profile named '{}', the config has {}",
+                name,
+                names.join(", ")
+            );
+        };
+        profile
+            .validate()
+            .with_context(|| format!("Profile '{}' is invalid", name))?;
+
+        Ok(profile)
+    }
+
+    pub fn list_profiles(&self) {
+        if self.profiles.is_empty() {
+            return println!("{}", "No profiles defined".blue());
+        }
+
+        println!("{}", "Available profiles:".bold().green());
+        let profiles: BTreeMap<_, _> = self.profiles.iter().collect();
+        for (name, profile) in profiles {
+            let mut details = profile.templates.join(", ");
+            let flags = profile.flags();
+            if !flags.is_empty() {
+                details = format!("{} {}", details, flags.join(" ").dimmed());
+            }
+            println!("{} => {}", name.blue(), details.trim_start());
+        }
+    }
+
+    /// Prints the profile `name` like it's written in the config.
+    pub fn show_profile(&mut self, name: &str) -> Result<()> {
+        let profile = self.profile(name)?;
+        print!("{}", toml::to_string_pretty(profile)?);
+        Ok(())
+    }
+
+    /// Lists the user templates with the path of their file, and what's in
+    /// them when `content` is set. Missing files are marked instead of failing.
+    pub fn list_templates(&self, content: bool) -> Result<()> {
         if self.templates.is_empty() {
-            return println!("{}", "No templates defined".blue());
+            println!("{}", "No templates defined".blue());
+            return Ok(());
         }
 
         println!("{}", "Available templates:".bold().green());
-        for (name, path) in &self.templates {
-            println!("{} => {:?}", name.blue(), path);
+        let templates: BTreeMap<_, _> = self.templates.iter().collect();
+        for (name, file_name) in templates {
+            let path = self.templates_dir.join(file_name);
+            if !path.exists() {
+                println!(
+                    "{} => {} {}",
+                    name.blue(),
+                    path.display(),
+                    "(missing)".yellow()
+                );
+            } else if content {
+                println!("{} => {}", name.blue(), path.display());
+                let contents = self.template_contents(name, &path)?;
+                print!("{}", contents);
+                if !contents.is_empty() && !contents.ends_with('\n') {
+                    println!();
+                }
+            } else {
+                println!("{} => {}", name.blue(), path.display());
+            }
         }
+
+        Ok(())
     }
 
-    pub fn add_template(&mut self, name: String, file_name: String) -> Result<()> {
-        let file = self
-            .path
-            .parent()
-            .context("Could not get parent directory of config file")?
-            .join("templates")
-            .join(&file_name);
+    /// Prints the contents of the user template `name` on stdout, and the path
+    /// of its file on stderr unless `--quiet` is passed, so the contents can be
+    /// piped elsewhere.
+    pub fn show_template(&self, name: &str) -> Result<()> {
+        let path = self.template_file(name)?;
+        let contents = self.template_contents(name, &path)?;
+        info!("{}", paint(path.display(), DIM));
+        print!("{}", contents);
 
-        println!(
-            "Created template {} at {}",
-            name.blue(),
-            file.to_str().unwrap_or_default().yellow()
-        );
+        Ok(())
+    }
 
-        let mut file = File::create(file)?;
-        file.write_all(format!("\n### {} ###\n", name).as_bytes())?;
+    /// Reads the file of the template `name` at `path`, saying where it was
+    /// expected when it's missing.
+    fn template_contents(&self, name: &str, path: &Path) -> Result<String> {
+        if !path.exists() {
+            bail!(
+                "The file of template '{}' is missing, expected it at '{}'",
+                name,
+                path.display()
+            );
+        }
+
+        read_to_string(path).with_context(|| {
+            format!(
+                "Could not read template '{}' from '{}'",
+                name,
+                path.display()
+            )
+        })
+    }
+
+    /// Adds a user template read from `file_name`, an existing template is
+    /// only replaced when `force`. Without `contents` the file is only created
+    /// if it doesn't exist, so its contents are never lost, with them a file
+    /// with other contents is only replaced when `force`.
+    pub fn add_template(
+        &mut self,
+        name: String,
+        file_name: String,
+        force: bool,
+        shadows: Option<bool>,
+        contents: Option<TemplateContents>,
+    ) -> Result<()> {
+        let name = validate_name("template", &name)?;
+        validate_file_name(&file_name)?;
+        if let Some((_, existing)) = self
+            .templates
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&name))
+            .filter(|_| !force)
+        {
+            bail!(
+                "Template '{}' already exists in '{}', pass '--force' to replace it",
+                name,
+                existing
+            );
+        }
+
+        let file = self.templates_dir.join(&file_name);
+        remove_ignoring_case(&mut self.template_urls, &name);
+        if let Some(TemplateContents {
+            source,
+            contents,
+            url,
+        }) = contents
+        {
+            let existing = read_to_string(&file).ok();
+            if existing.as_ref().is_some_and(|e| *e != contents) && !force {
+                bail!(
+                    "'{}' already exists with other contents, pass '--force' to replace it",
+                    file.display()
+                );
+            }
+            std::fs::write(&file, contents)
+                .with_context(|| format!("Could not write '{}'", file.display()))?;
+            println!(
+                "Created template {} at {} from {}",
+                name.blue(),
+                file.to_str().unwrap_or_default().yellow(),
+                source.yellow()
+            );
+            if let Some(url) = url {
+                self.template_urls.insert(name.clone(), url);
+            }
+        } else if file.exists() {
+            println!(
+                "Added template {} using the existing {}",
+                name.blue(),
+                file.to_str().unwrap_or_default().yellow()
+            );
+        } else {
+            println!(
+                "Created template {} at {}",
+                name.blue(),
+                file.to_str().unwrap_or_default().yellow()
+            );
+            write_stub(&name, &file)?;
+        }
+        warn_if_shadowing("template", &name, shadows);
 
+        remove_ignoring_case(&mut self.templates, &name);
         self.templates.insert(name, file_name);
         self.write()
     }
 
-    pub fn remove_template(&mut self, name: &str) -> Result<()> {
-        if self.templates.remove(name).is_some() {
+    /// Removes the template `name`, see `remove_references` for `cascade` and
+    /// `downloaded`.
+    pub fn remove_template(
+        &mut self,
+        name: &str,
+        cascade: bool,
+        downloaded: Option<bool>,
+    ) -> Result<()> {
+        if remove_ignoring_case(&mut self.templates, name).is_some() {
+            remove_ignoring_case(&mut self.template_urls, name);
             println!("Removed template {}", name.blue());
+            self.remove_references(name, cascade, downloaded);
         } else {
             println!("No template named {} found", name.blue());
         }
         self.write()
     }
 
+    /// Renames the template `old` to `new`, replacing a template named `new`
+    /// only when `force`. A file named after the template is renamed with
+    /// it, and aliases including it are changed to include `new`.
+    pub fn rename_template(
+        &mut self,
+        old: &str,
+        new: &str,
+        force: bool,
+        shadows: Option<bool>,
+    ) -> Result<()> {
+        let new = validate_name("template", new)?;
+        let renamed = !old.eq_ignore_ascii_case(&new);
+        let exists = |name: &str| {
+            self.templates
+                .keys()
+                .any(|key| key.eq_ignore_ascii_case(name))
+        };
+        if !exists(old) {
+            bail!("No template named '{}'", old);
+        }
+        if renamed && exists(&new) && !force {
+            bail!(
+                "A template named '{}' already exists, pass '--force' to replace it",
+                new
+            );
+        }
+
+        let file_name = self.template_file(old)?.file_name().map_or_else(
+            || new.clone(),
+            |name| renamed_file_name(&name.to_string_lossy(), old, &new),
+        );
+        let (from, to) = (
+            self.template_file(old)?,
+            self.templates_dir.join(&file_name),
+        );
+        if from != to && to.exists() && !force {
+            bail!(
+                "'{}' already exists, pass '--force' to replace it",
+                to.display()
+            );
+        }
+        if from != to && from.exists() {
+            std::fs::rename(&from, &to).with_context(|| {
+                format!(
+                    "Could not rename '{}' to '{}'",
+                    from.display(),
+                    to.display()
+                )
+            })?;
+        }
+
+        remove_ignoring_case(&mut self.templates, old);
+        let url = remove_ignoring_case(&mut self.template_urls, old);
+        let aliases = self.dangling_references(old);
+        remove_ignoring_case(&mut self.templates, &new);
+        remove_ignoring_case(&mut self.template_urls, &new);
+        println!(
+            "Renamed template {} to {} at {}",
+            old.blue(),
+            new.blue(),
+            to.display().to_string().yellow()
+        );
+        warn_if_shadowing("template", &new, shadows);
+
+        for alias in aliases {
+            let members = self.aliases.entry(alias.clone()).or_default();
+            for member in members.iter_mut() {
+                if member.eq_ignore_ascii_case(old) {
+                    member.clone_from(&new);
+                }
+            }
+            *members = new_members(&[], members);
+            println!("Updated alias {} to {:?}", alias.blue(), members);
+        }
+        if let Some(url) = url {
+            self.template_urls.insert(new.clone(), url);
+        }
+        self.templates.insert(new, file_name);
+        self.write()
+    }
+
+    /// The aliases that include `name` but wouldn't find it, sorted. A member
+    /// with the name of its own alias is the template, any other member an
+    /// alias before a downloaded template.
+    pub fn dangling_references(&self, name: &str) -> Vec<String> {
+        if self
+            .templates
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case(name))
+        {
+            return Vec::new();
+        }
+        let is_alias = self
+            .aliases
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case(name));
+        let mut aliases: Vec<String> = self
+            .aliases
+            .iter()
+            .filter(|(alias, _)| !is_alias || alias.eq_ignore_ascii_case(name))
+            .filter(|(_, members)| members.iter().any(|m| m.eq_ignore_ascii_case(name)))
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        aliases.sort_unstable();
+        aliases
+    }
+
+    /// Deals with the aliases that still include `name` after it was removed.
+    /// They're only reported unless `cascade`, which removes `name` from them
+    /// and removes the aliases left without members in turn. When `downloaded`
+    /// they're kept, as they now include the downloaded template.
+    fn remove_references(&mut self, name: &str, cascade: bool, downloaded: Option<bool>) {
+        for alias in self.dangling_references(name) {
+            if downloaded == Some(true) {
+                info!(
+                    "Alias '{}' includes '{}', which is now the downloaded template",
+                    alias, name
+                );
+                continue;
+            }
+            if !cascade {
+                warning!(
+                    "Alias '{}' still includes '{}', which no longer exists, pass '--cascade' to remove it",
+                    alias,
+                    name
+                );
+                continue;
+            }
+
+            let members = self.aliases.entry(alias.clone()).or_default();
+            members.retain(|member| !member.eq_ignore_ascii_case(name));
+            if members.is_empty() {
+                self.aliases.remove(&alias);
+                println!("Removed alias {}, it has no members left", alias.blue());
+                self.remove_references(&alias, true, None);
+            } else {
+                println!("Removed {} from alias {}", name.yellow(), alias.blue());
+            }
+        }
+    }
+
     pub fn names(&self) -> Vec<Type> {
         let aliases = self.aliases.keys();
         let templates = self.templates.keys();
@@ -168,8 +1043,24 @@ impl Config {
         res
     }
 
-    pub fn read_template(path: &str) -> Result<String> {
-        let dir = project_dirs().config_dir().join("templates").join(path);
+    /// The file of the user template `name`, which might not exist.
+    pub fn template_file(&self, name: &str) -> Result<PathBuf> {
+        match self
+            .templates
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        {
+            Some((_, file_name)) => Ok(self.templates_dir.join(file_name)),
+            None => bail!(
+                "No template named '{}', create it with `git ignore template add {} <FILE_NAME>`",
+                name,
+                name
+            ),
+        }
+    }
+
+    pub fn read_template(&self, path: &str) -> Result<String> {
+        let dir = self.templates_dir.join(path);
         let content = read_to_string(dir)?;
 
         Ok(content)
@@ -177,8 +1068,24 @@ impl Config {
 
     fn new(path: PathBuf) -> Self {
         Self {
+            templates_dir: path.with_file_name("templates"),
             aliases: HashMap::default(),
             templates: HashMap::default(),
+            template_urls: HashMap::new(),
+            default_templates: Vec::new(),
+            profiles: HashMap::new(),
+            cache_ttl_days: None,
+            server: None,
+            source: None,
+            sources: Vec::new(),
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            proxy: None,
+            background_update: None,
+            max_download_mb: None,
+            line_endings: None,
+            backup: None,
+            builtin_aliases: None,
             path,
         }
     }
@@ -206,18 +1113,14 @@ impl Config {
         let old_config_file = old_config_file();
 
         if config_file.exists() && old_config_file.exists() {
-            eprintln!(
-                "{}: Found both old and new config file, remove `{}` to silence this warning",
-                "WARN".bold().red(),
-                old_config_file.to_string_lossy().italic().blue()
+            warning!(
+                "Found both old and new config file, remove `{}` to silence this warning",
+                paint(old_config_file.to_string_lossy(), EMPHASIS)
             );
             Some(config_file)
         } else if old_config_file.exists() {
-            eprintln!(
-                "{}: Found old config file, please run `{}` to move it. This file will be ignored in a future release.",
-                "WARN".bold().red(),
-                "git ignore init --migrate".italic().blue()
-            );
+            warning!("Found old config file, please run `{}` to move it. This file will be ignored in a future release.",
+                paint("git ignore init --migrate", EMPHASIS));
             Some(old_config_file)
         } else if config_file.exists() {
             Some(config_file)
@@ -226,3 +1129,458 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{migrate_templates, validate_name, Aliases, Config, TemplateContents};
+
+    /// An empty directory for a test to keep its config and templates in.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("git-ignore-config-test-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        dir
+    }
+
+    /// A config without aliases or templates that's written to `dir`.
+    fn empty_config(dir: &Path) -> Config {
+        let mut config: Config = toml::from_str("[aliases]\n[templates]\n").unwrap();
+        config.path = dir.join("config.toml");
+        config.templates_dir = dir.join("templates");
+        config
+    }
+
+    #[test]
+    fn migrates_templates_next_to_the_config() {
+        let dir = temp_dir("migrate");
+        let old = dir.join("templates");
+        let new = dir.join("new").join("templates");
+        std::fs::write(old.join("mine.gitignore"), "*.log\n").unwrap();
+        std::fs::create_dir_all(dir.join("new")).unwrap();
+
+        migrate_templates(&old, &new).unwrap();
+        assert!(!old.exists());
+        assert_eq!(
+            std::fs::read_to_string(new.join("mine.gitignore")).unwrap(),
+            "*.log\n"
+        );
+
+        // Existing templates are never replaced
+        std::fs::create_dir_all(&old).unwrap();
+        migrate_templates(&old, &new).unwrap();
+        assert!(old.is_dir() && new.join("mine.gitignore").exists());
+        migrate_templates(&new, &new).unwrap();
+        assert!(new.is_dir());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn validates_names() {
+        for name in ["rust", "C++", "intellij+all", "Visual_Studio.code", "go-1"] {
+            assert_eq!(validate_name("alias", name).unwrap(), name.to_lowercase());
+        }
+
+        let err = |name: &str| validate_name("template", name).unwrap_err().to_string();
+        assert_eq!(err(""), "The name of the template can't be empty");
+        for name in ["rust node", " rust", "rust\t", "a\nb"] {
+            assert!(err(name).contains("whitespace"), "{:?}", name);
+        }
+        for name in ["../evil", "a/b", "a\\b", "/"] {
+            assert!(err(name).contains("path separator"), "{:?}", name);
+        }
+        for (name, c) in [("rust!", '!'), ("ünïcode", 'ü'), ("a:b", ':'), ("a*", '*')] {
+            assert!(err(name).contains(&format!("'{}'", c)), "{:?}", name);
+        }
+
+        let dir = temp_dir("names");
+        let mut config = empty_config(&dir);
+        assert!(config
+            .add_alias("rust node".into(), vec!["rust".into()], false, Some(false))
+            .is_err());
+        assert!(config
+            .add_template("../evil".into(), "evil".into(), false, Some(false), None)
+            .is_err());
+        assert!(config
+            .add_template("evil".into(), "../evil".into(), false, Some(false), None)
+            .is_err());
+        assert!(config.aliases.is_empty() && config.templates.is_empty());
+        config
+            .add_alias("Web".into(), vec!["node".into()], false, Some(false))
+            .unwrap();
+        assert!(config.rename_alias("web", "my web", false, None).is_err());
+        assert_eq!(config.aliases["web"], ["node"]);
+
+        // Names from older versions are only warned about
+        std::fs::write(
+            &config.path,
+            "[aliases]\n\"rust node\" = [\"rust\"]\n[templates]\n",
+        )
+        .unwrap();
+        let config = Config::load(Some(&config.path)).unwrap().unwrap();
+        assert_eq!(config.aliases["rust node"], ["rust"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn never_overwrites_without_force() {
+        let dir = temp_dir("add-force");
+        let mut config = empty_config(&dir);
+
+        config
+            .add_alias("Web".into(), vec!["node".into()], false, Some(false))
+            .unwrap();
+        let err = config
+            .add_alias("web".into(), vec!["elm".into()], false, Some(false))
+            .unwrap_err();
+        assert!(err.to_string().contains(r#"["node"]"#));
+        assert_eq!(config.aliases["web"], ["node"]);
+        config
+            .add_alias("WEB".into(), vec!["elm".into()], true, Some(false))
+            .unwrap();
+        assert_eq!(config.aliases["web"], ["elm"]);
+
+        // Re-adding a template must never truncate its hand-edited file
+        config
+            .add_template("work".into(), "work.txt".into(), false, Some(false), None)
+            .unwrap();
+        let file = dir.join("templates").join("work.txt");
+        std::fs::write(&file, "secrets/\n").unwrap();
+        let err = config
+            .add_template("Work".into(), "other.txt".into(), false, Some(false), None)
+            .unwrap_err();
+        assert!(err.to_string().contains("work.txt"));
+        config
+            .add_template("work".into(), "work.txt".into(), true, Some(false), None)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "secrets/\n");
+
+        // An existing file is used for a new template too
+        config
+            .add_template("notes".into(), "work.txt".into(), false, Some(false), None)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "secrets/\n");
+        assert_eq!(config.templates["notes"], "work.txt");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn adds_templates_from_files() {
+        let dir = temp_dir("from-file");
+        let mut config = empty_config(&dir);
+
+        let source = dir.join(".gitignore");
+        std::fs::write(&source, "secrets/\n").unwrap();
+        let contents = TemplateContents::read(&source).unwrap();
+        assert_eq!(contents.source, source.display().to_string());
+        config
+            .add_template(
+                "work".into(),
+                "work.txt".into(),
+                false,
+                None,
+                Some(contents),
+            )
+            .unwrap();
+        let file = dir.join("templates").join("work.txt");
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "secrets/\n");
+        assert_eq!(config.templates["work"], "work.txt");
+        assert_eq!(config.template_file("WORK").unwrap(), file);
+        assert!(config.template_file("notes").is_err());
+
+        // Other contents are only written over with `--force`
+        let other = TemplateContents::new("other".into(), b"*.log\n".to_vec()).unwrap();
+        let err = config
+            .add_template(
+                "notes".into(),
+                "work.txt".into(),
+                false,
+                None,
+                Some(other.clone()),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "secrets/\n");
+        assert!(!config.templates.contains_key("notes"));
+        config
+            .add_template("work".into(), "work.txt".into(), true, None, Some(other))
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "*.log\n");
+
+        assert!(TemplateContents::new("bad".into(), vec![0xff, 0xfe]).is_err());
+        assert!(TemplateContents::new("bin".into(), b"a\0b".to_vec()).is_err());
+        assert!(TemplateContents::read(&dir.join("missing")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn edits_alias_members() {
+        let dir = temp_dir("alias-members");
+        let mut config = empty_config(&dir);
+        config
+            .aliases
+            .insert("Web".into(), vec!["node".into(), "deno".into()]);
+        config
+            .add_alias("js".into(), vec!["node".into()], false, Some(false))
+            .unwrap();
+
+        let members = |names: &[&str]| names.iter().map(ToString::to_string).collect::<Vec<_>>();
+        config
+            .append_to_alias("web", members(&["Elm", "node", "elm"]))
+            .unwrap();
+        assert_eq!(config.aliases["Web"], ["node", "deno", "elm"]);
+        assert!(config.append_to_alias("css", members(&["sass"])).is_err());
+
+        config
+            .remove_alias_members("WEB", &members(&["deno", "zig"]), false, None)
+            .unwrap();
+        assert_eq!(config.aliases["Web"], ["node", "elm"]);
+
+        // Emptying an alias needs to be asked for
+        let all = members(&["node", "elm"]);
+        assert!(config
+            .remove_alias_members("web", &all, false, None)
+            .is_err());
+        assert_eq!(config.aliases["Web"], ["node", "elm"]);
+        config
+            .remove_alias_members("web", &all, true, None)
+            .unwrap();
+        assert!(!config.aliases.contains_key("Web"));
+
+        // Keys are written sorted so the file only changes where it has to
+        config
+            .add_alias("web".into(), vec!["elm".into()], false, Some(false))
+            .unwrap();
+        config
+            .add_alias("css".into(), vec!["sass".into()], false, Some(false))
+            .unwrap();
+        let written = std::fs::read_to_string(&config.path).unwrap();
+        assert!(written.find("css").unwrap() < written.find("web").unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renames_aliases() {
+        let dir = temp_dir("alias-rename");
+        let mut config = empty_config(&dir);
+        config
+            .add_alias("js".into(), vec!["node".into()], false, Some(false))
+            .unwrap();
+
+        // Renaming keeps the members and doesn't replace other aliases
+        config
+            .rename_alias("JS", "Script", false, Some(false))
+            .unwrap();
+        assert_eq!(config.aliases["script"], ["node"]);
+        assert!(!config.aliases.contains_key("js"));
+        config
+            .add_alias("web".into(), vec!["elm".into()], false, Some(false))
+            .unwrap();
+        assert!(config
+            .rename_alias("script", "WEB", false, Some(false))
+            .is_err());
+        assert!(config
+            .rename_alias("js", "css", false, Some(false))
+            .is_err());
+        assert_eq!(config.aliases["web"], ["elm"]);
+        config
+            .rename_alias("script", "WEB", true, Some(false))
+            .unwrap();
+        assert_eq!(config.aliases["web"], ["node"]);
+        assert!(!config.aliases.contains_key("script"));
+        // Only changing the case isn't a conflict
+        config.aliases.insert("Css".into(), vec!["sass".into()]);
+        config
+            .rename_alias("Css", "CSS", false, Some(false))
+            .unwrap();
+        assert_eq!(config.aliases["css"], ["sass"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn removes_dangling_references() {
+        let dir = temp_dir("dangling");
+        let config = |toml: &str| {
+            let mut config: Config = toml::from_str(toml).unwrap();
+            config.path = dir.join("config.toml");
+            config
+        };
+        let config = |toml: &str| {
+            config(&format!(
+                "[aliases]\n{}\n[templates]\nmycorp = 'a'\nnode = 'b'\n",
+                toml
+            ))
+        };
+
+        // Only reported without `--cascade`
+        let mut web = config("web = ['rust', 'MyCorp']");
+        web.remove_template("mycorp", false, None).unwrap();
+        assert_eq!(web.aliases["web"], ["rust", "MyCorp"]);
+        assert_eq!(web.dangling_references("mycorp"), ["web"]);
+        web.remove_alias("nothing", true, None).unwrap();
+        assert_eq!(web.aliases["web"], ["rust", "MyCorp"]);
+
+        let mut web = config("web = ['rust', 'mycorp']");
+        web.remove_template("MYCORP", true, None).unwrap();
+        assert_eq!(web.aliases["web"], ["rust"]);
+
+        // Aliases left empty are removed along with their own references
+        let mut nested = config("base = ['mycorp']\ndev = ['base', 'rust']\nall = ['dev']");
+        nested.remove_template("mycorp", true, None).unwrap();
+        assert!(!nested.aliases.contains_key("base"));
+        assert_eq!(nested.aliases["dev"], ["rust"]);
+        assert_eq!(nested.aliases["all"], ["dev"]);
+
+        let mut nested = config("base = ['node']\ndev = ['base']");
+        nested.remove_alias("base", false, None).unwrap();
+        assert_eq!(nested.aliases["dev"], ["base"]);
+        assert_eq!(nested.dangling_references("base"), ["dev"]);
+
+        // A member with the alias's own name is the template, others the alias
+        let mut own = config("node = ['node', 'yarn']\nweb = ['node']");
+        assert_eq!(own.dangling_references("node"), Vec::<String>::new());
+        own.remove_template("node", true, None).unwrap();
+        assert_eq!(own.aliases["node"], ["yarn"]);
+        assert_eq!(own.aliases["web"], ["node"]);
+
+        // Still found as a user or downloaded template
+        let mut kept = config("mycorp = ['rust']\nweb = ['mycorp']");
+        kept.remove_alias("mycorp", true, None).unwrap();
+        assert_eq!(kept.aliases["web"], ["mycorp"]);
+        let mut kept = config("web = ['node']");
+        kept.remove_template("node", true, Some(true)).unwrap();
+        assert_eq!(kept.aliases["web"], ["node"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renames_templates_with_their_files() {
+        let dir = temp_dir("template-rename");
+        let templates = dir.join("templates");
+        let mut config: Config = toml::from_str(
+            "[aliases]\nweb = ['node', 'Work']\nnotes = ['notes', 'rust']\n[templates]\nwork = 'work.txt'\nnotes = 'my-notes'\nother = 'other'\n",
+        )
+        .unwrap();
+        config.path = dir.join("config.toml");
+        config.templates_dir = templates.clone();
+        for file in ["work.txt", "my-notes", "other"] {
+            std::fs::write(templates.join(file), file).unwrap();
+        }
+
+        config.rename_template("WORK", "job", false, None).unwrap();
+        assert_eq!(config.templates["job"], "job.txt");
+        assert!(!config.templates.contains_key("work"));
+        assert_eq!(
+            std::fs::read_to_string(templates.join("job.txt")).unwrap(),
+            "work.txt"
+        );
+        assert!(!templates.join("work.txt").exists());
+        assert_eq!(config.aliases["web"], ["node", "job"]);
+
+        // Files not named after the template keep their name, and only the
+        // alias including the template itself changes
+        config
+            .rename_template("notes", "journal", false, None)
+            .unwrap();
+        assert_eq!(config.templates["journal"], "my-notes");
+        assert_eq!(config.aliases["notes"], ["journal", "rust"]);
+
+        // Nothing is replaced without `--force`
+        let err = config
+            .rename_template("job", "other", false, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        std::fs::write(templates.join("x.txt"), "x").unwrap();
+        config.templates.insert("x".into(), "x.txt".into());
+        let err = config
+            .rename_template("other", "job", false, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        assert_eq!(config.templates["job"], "job.txt");
+        assert!(config
+            .rename_template("missing", "new", false, None)
+            .is_err());
+
+        config.rename_template("job", "other", true, None).unwrap();
+        assert_eq!(config.templates["other"], "other.txt");
+        assert_eq!(
+            std::fs::read_to_string(templates.join("other.txt")).unwrap(),
+            "work.txt"
+        );
+        assert!(!config.templates.contains_key("job"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn aliases_round_trip_through_export() {
+        let dir = temp_dir("alias-export");
+        let mut config = empty_config(&dir);
+        config
+            .aliases
+            .insert("web".into(), vec!["node".into(), "elm".into()]);
+        config.aliases.insert("jvm".into(), vec!["java".into()]);
+        let before = config.aliases.clone();
+
+        let file = dir.join("aliases.toml");
+        let exported = config.export_aliases().unwrap();
+        assert!(exported.find("jvm").unwrap() < exported.find("web").unwrap());
+        std::fs::write(&file, &exported).unwrap();
+
+        config.aliases.clear();
+        config.import_aliases(&file, false).unwrap();
+        assert_eq!(config.aliases, before);
+        let written = std::fs::read_to_string(&config.path).unwrap();
+        let reread: Config = toml::from_str(&written).unwrap();
+        assert_eq!(reread.aliases, before);
+
+        // Conflicts are only replaced when asked to
+        config.aliases.insert("web".into(), vec!["deno".into()]);
+        config.import_aliases(&file, false).unwrap();
+        assert_eq!(config.aliases["web"], ["deno"]);
+        config.import_aliases(&file, true).unwrap();
+        assert_eq!(config.aliases, before);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn validates_imported_aliases() {
+        assert!(Aliases::parse("[aliases]\nweb = [\"node\"]\n").is_ok());
+        assert!(Aliases::parse("[aliases]\nweb = []\n").is_err());
+        assert!(Aliases::parse("[aliases]\nweb = \"node\"\n").is_err());
+        assert!(Aliases::parse("[aliases]\nweb = [\"node\"]\nWeb = [\"elm\"]\n").is_err());
+        assert!(Aliases::parse("[templates]\nwork = \"work.txt\"\n").is_err());
+        assert!(Aliases::parse("[aliases]\n\"rust node\" = [\"rust\"]\n").is_err());
+    }
+
+    #[test]
+    fn normalizes_legacy_aliases_on_load() {
+        let dir = temp_dir("legacy-aliases");
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            "[aliases]\nWeb = [' Node ', '', 'NODE', 'Rust']\nweb = ['rust', 'ELM']\nAPI = ['Rust']\n[templates]\n",
+        )
+        .unwrap();
+        let mut config = Config::load(Some(&path)).unwrap().unwrap();
+        assert_eq!(config.aliases.len(), 2);
+        assert_eq!(config.aliases["web"], ["node", "rust", "elm"]);
+        assert_eq!(config.aliases["api"], ["rust"]);
+
+        // Written back the same way
+        config
+            .add_alias(
+                "JS".into(),
+                vec!["  Deno".into(), " ".into()],
+                false,
+                Some(false),
+            )
+            .unwrap();
+        assert_eq!(config.aliases["js"], ["deno"]);
+        config.append_to_alias("web", vec!["Node ".into()]).unwrap();
+        assert_eq!(config.aliases["web"], ["node", "rust", "elm"]);
+        let reloaded = Config::load(Some(&path)).unwrap().unwrap();
+        assert_eq!(reloaded.aliases, config.aliases);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+/// How a template is represented in a `.gitignore`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Coverage {
+    /// It was written by us, found from its section or the footer
+    Written,
+    /// At least half of its patterns are there
+    Patterns,
+    Missing,
+}
+
+impl Coverage {
+    pub fn is_covered(self) -> bool {
+        self != Coverage::Missing
+    }
+}
+
+/// How a detected template is covered, for `git ignore check`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Checked {
+    pub template: String,
+    pub coverage: Coverage,
+}
+
+/// The patterns in `contents`, normalized so ones that differ only in a
+/// leading or trailing slash compare equal.
+pub fn patterns(contents: &str) -> HashSet<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.trim_start_matches('/')
+                .trim_end_matches('/')
+                .to_string()
+        })
+        .filter(|pattern| !pattern.is_empty())
+        .collect()
+}
+
+/// Whether the template `key` is in a `.gitignore` that has the templates
+/// in `written` and the patterns in `existing`. Without its contents a
+/// template only counts if it was written.
+pub fn coverage(
+    key: &str,
+    contents: Option<&str>,
+    written: &HashSet<String>,
+    existing: &HashSet<String>,
+) -> Coverage {
+    if written.contains(&key.to_lowercase()) {
+        return Coverage::Written;
+    }
+    let Some(contents) = contents else {
+        return Coverage::Missing;
+    };
+
+    let wanted = patterns(contents);
+    let present = wanted.intersection(existing).count();
+    if present * 2 >= wanted.len() {
+        Coverage::Patterns
+    } else {
+        Coverage::Missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{coverage, patterns, Coverage};
+
+    const RUST: &str =
+        "# Generated by Cargo\ndebug/\ntarget/\n\n# rustfmt backups\n**/*.rs.bk\n*.pdb\n";
+
+    fn written(keys: &[&str]) -> HashSet<String> {
+        keys.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn normalizes_patterns() {
+        let found = patterns("# comment\n/target/\n  node_modules/  \n\n/\n!keep\n");
+        assert_eq!(found, written(&["target", "node_modules", "!keep"]));
+    }
+
+    #[test]
+    fn written_templates_are_covered() {
+        let existing = patterns("### rust ###\n### end rust ###\n");
+        assert_eq!(
+            coverage("Rust", Some(RUST), &written(&["rust"]), &existing),
+            Coverage::Written
+        );
+        assert_eq!(
+            coverage("rust", None, &written(&["rust"]), &existing),
+            Coverage::Written
+        );
+        assert_eq!(
+            coverage("rust", None, &written(&["node"]), &existing),
+            Coverage::Missing
+        );
+    }
+
+    #[test]
+    fn compares_patterns_without_markers() {
+        let existing = patterns("/target\ndebug\n.env\n");
+        assert_eq!(
+            coverage("rust", Some(RUST), &written(&[]), &existing),
+            Coverage::Patterns
+        );
+
+        // One of four patterns isn't most of them
+        let existing = patterns("/target\n.env\n");
+        assert_eq!(
+            coverage("rust", Some(RUST), &written(&[]), &existing),
+            Coverage::Missing
+        );
+        assert!(!Coverage::Missing.is_covered());
+    }
+
+    #[test]
+    fn templates_without_patterns_are_covered() {
+        assert_eq!(
+            coverage("empty", Some("# nothing\n"), &written(&[]), &HashSet::new()),
+            Coverage::Patterns
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use crate::cli::Kind;
+
+/// Files npm always publishes, whatever `.npmignore` says.
+const NPM_ALWAYS_INCLUDED: &[&str] = &["package.json", "README", "LICENSE", "LICENCE"];
+
+/// What to do with a single pattern when translating it.
+#[derive(Debug, PartialEq, Eq)]
+enum Translated {
+    Keep(String),
+    /// Dropped since it has no effect, with the reason why
+    Drop(&'static str),
+}
+
+/// Translates generated `.gitignore` templates to the ignore file of `kind`.
+/// Comments and blank lines are kept as is. Returns the translated contents
+/// and a warning for every pattern that was dropped or may not work the same.
+pub fn translate(contents: &str, kind: Kind) -> (String, Vec<String>) {
+    let mut result = String::with_capacity(contents.len());
+    let mut warnings = Vec::new();
+
+    for line in contents.split_inclusive('\n') {
+        let pattern = line.trim_end_matches(['\r', '\n']);
+        if kind == Kind::Git || pattern.trim().is_empty() || pattern.starts_with('#') {
+            result.push_str(line);
+            continue;
+        }
+
+        if pattern.contains('\\') {
+            warnings.push(format!(
+                "'{}' is escaped, which may not work the same in {}",
+                pattern,
+                kind.file_name()
+            ));
+        }
+
+        let translated = match kind {
+            Kind::Git => Translated::Keep(pattern.to_string()),
+            Kind::Docker => docker(pattern),
+            Kind::Npm => npm(pattern),
+        };
+        match translated {
+            Translated::Keep(translated) => {
+                result.push_str(&translated);
+                result.push_str(&line[pattern.len()..]);
+            }
+            Translated::Drop(reason) => {
+                warnings.push(format!("Dropped '{}', {}", pattern, reason));
+            }
+        }
+    }
+
+    (result, warnings)
+}
+
+/// Docker matches every pattern from the root of the build context and has no
+/// directory only patterns, so a pattern without a slash has to be prefixed
+/// with `**/` to match at any depth like it does in git.
+fn docker(pattern: &str) -> Translated {
+    let (negation, pattern) = match pattern.strip_prefix('!') {
+        Some(rest) => ("!", rest),
+        None => ("", pattern),
+    };
+
+    let pattern = pattern.trim_end().trim_end_matches('/');
+    let pattern = match pattern.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if pattern.contains('/') => pattern.to_string(),
+        None => format!("**/{}", pattern),
+    };
+
+    if pattern.is_empty() || pattern == "**/" {
+        return Translated::Drop("it would ignore the whole build context");
+    }
+    Translated::Keep(format!("{}{}", negation, pattern))
+}
+
+/// `.npmignore` uses the same syntax as `.gitignore`, but some files are
+/// always published.
+fn npm(pattern: &str) -> Translated {
+    let name = pattern.trim_start_matches('/').trim_end();
+    let always_included = NPM_ALWAYS_INCLUDED.iter().any(|file| {
+        name == *file
+            || name
+                .strip_prefix(file)
+                .is_some_and(|rest| rest.starts_with(['.', '*']))
+    });
+
+    if !pattern.starts_with('!') && always_included {
+        return Translated::Drop("npm always publishes it");
+    }
+    Translated::Keep(pattern.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{docker, npm, translate, Translated};
+    use crate::cli::Kind;
+
+    fn keep(pattern: &str) -> Translated {
+        Translated::Keep(pattern.to_string())
+    }
+
+    #[test]
+    fn translates_docker_patterns() {
+        let table = [
+            ("*.log", keep("**/*.log")),
+            ("node_modules/", keep("**/node_modules")),
+            ("/target", keep("target")),
+            ("/dist/", keep("dist")),
+            ("docs/_build/", keep("docs/_build")),
+            ("**/*.rs.bk", keep("**/*.rs.bk")),
+            ("!.vscode/settings.json", keep("!.vscode/settings.json")),
+            ("!keep.log", keep("!**/keep.log")),
+            ("*.tmp  ", keep("**/*.tmp")),
+            (
+                "/",
+                Translated::Drop("it would ignore the whole build context"),
+            ),
+        ];
+
+        for (pattern, expected) in table {
+            assert_eq!(docker(pattern), expected, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn translates_npm_patterns() {
+        let table = [
+            ("*.log", keep("*.log")),
+            ("/coverage/", keep("/coverage/")),
+            ("!dist/", keep("!dist/")),
+            ("package.json", Translated::Drop("npm always publishes it")),
+            ("README.md", Translated::Drop("npm always publishes it")),
+            ("/LICENSE*", Translated::Drop("npm always publishes it")),
+            ("package-lock.json", keep("package-lock.json")),
+            ("READMEs/", keep("READMEs/")),
+        ];
+
+        for (pattern, expected) in table {
+            assert_eq!(npm(pattern), expected, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn keeps_comments_and_line_endings() {
+        let contents = "### node ###\r\n# Logs\r\n*.log\r\n\r\n/\r\n";
+        let (translated, warnings) = translate(contents, Kind::Docker);
+        assert_eq!(translated, "### node ###\r\n# Logs\r\n**/*.log\r\n\r\n");
+        assert_eq!(warnings.len(), 1);
+
+        let (translated, warnings) = translate(contents, Kind::Git);
+        assert_eq!((translated.as_str(), warnings.len()), (contents, 0));
+    }
+
+    #[test]
+    fn warns_about_escapes() {
+        let (translated, warnings) = translate("\\#file\n", Kind::Docker);
+        assert_eq!(translated, "**/\\#file\n");
+        assert!(warnings[0].contains(".dockerignore"));
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::Write;
+
+use colored::Colorize;
+
+/// Lines of context shown around each change.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// The shortest list of edits turning `old` into `new`, using the greedy
+/// algorithm from Myers' "An O(ND) Difference Algorithm and Its Variations".
+fn edits(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0; 2 * max as usize + 3];
+    // The furthest reaching x for every diagonal after each round, indexed by
+    // `k + d`, to walk back through once we've reached the end
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+
+            if x >= n && y >= m {
+                trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+                break 'search;
+            }
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+    }
+
+    let mut result = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[d as usize - 1];
+        let furthest = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && furthest(k - 1) < furthest(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = furthest(previous_k);
+        let previous_y = previous_x - previous_k;
+
+        let inserted = previous_k == k + 1;
+        let after_edit = if inserted { previous_x } else { previous_x + 1 };
+
+        let equal = x - after_edit;
+        result.extend(std::iter::repeat_n(Edit::Equal, equal as usize));
+        result.push(if inserted { Edit::Insert } else { Edit::Delete });
+        x = previous_x;
+        y = previous_y;
+    }
+    result.extend(std::iter::repeat_n(Edit::Equal, x as usize));
+    result.reverse();
+
+    result
+}
+
+/// How many lines are added and removed going from `old` to `new`.
+pub fn stat(old: &str, new: &str) -> (usize, usize) {
+    let old: Vec<_> = old.split_inclusive('\n').collect();
+    let new: Vec<_> = new.split_inclusive('\n').collect();
+    let found = edits(&old, &new);
+    let count = |edit| found.iter().filter(|e| **e == edit).count();
+
+    (count(Edit::Insert), count(Edit::Delete))
+}
+
+/// A line in the diff, with its line numbers in the old and new file.
+struct Line<'a> {
+    edit: Edit,
+    text: &'a str,
+    old: usize,
+    new: usize,
+}
+
+/// Formats the difference between `old` and `new` as a unified diff, colored if
+/// `stdout` is. Empty if they are the same.
+pub fn unified(old: &str, new: &str, name: &str) -> String {
+    let old_lines: Vec<_> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<_> = new.split_inclusive('\n').collect();
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    for edit in edits(&old_lines, &new_lines) {
+        let text = match edit {
+            Edit::Insert => new_lines[j],
+            _ => old_lines[i],
+        };
+        lines.push(Line {
+            edit,
+            text,
+            old: i,
+            new: j,
+        });
+        match edit {
+            Edit::Equal => (i, j) = (i + 1, j + 1),
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+
+    let mut out = String::new();
+    let changes: Vec<_> = (0..lines.len())
+        .filter(|&i| lines[i].edit != Edit::Equal)
+        .collect();
+    let Some(&first) = changes.first() else {
+        return out;
+    };
+
+    let _ = writeln!(out, "{}", format!("--- {}", name).bold());
+    let _ = writeln!(out, "{}", format!("+++ {}", name).bold());
+
+    // Changes closer together than twice the context share a hunk
+    let mut start = first;
+    let mut end = first;
+    for &change in changes.iter().skip(1).chain(std::iter::once(&usize::MAX)) {
+        if change != usize::MAX && change - end <= 2 * CONTEXT + 1 {
+            end = change;
+            continue;
+        }
+        hunk(
+            &mut out,
+            &lines[start.saturating_sub(CONTEXT)..(end + CONTEXT + 1).min(lines.len())],
+        );
+        (start, end) = (change, change);
+    }
+
+    out
+}
+
+fn hunk(out: &mut String, lines: &[Line]) {
+    let count = |skip: Edit| lines.iter().filter(|l| l.edit != skip).count();
+    let (old_count, new_count) = (count(Edit::Insert), count(Edit::Delete));
+    // An empty range starts at the line before it
+    let start = |line: usize, count: usize| if count == 0 { line } else { line + 1 };
+
+    let header = format!(
+        "@@ -{},{} +{},{} @@",
+        start(lines[0].old, old_count),
+        old_count,
+        start(lines[0].new, new_count),
+        new_count
+    );
+    let _ = writeln!(out, "{}", header.cyan());
+
+    for line in lines {
+        let text = line.text.strip_suffix('\n').unwrap_or(line.text);
+        let _ = match line.edit {
+            Edit::Equal => writeln!(out, " {}", text),
+            Edit::Delete => writeln!(out, "{}", format!("-{}", text).red()),
+            Edit::Insert => writeln!(out, "{}", format!("+{}", text).green()),
+        };
+        if !line.text.ends_with('\n') {
+            let _ = writeln!(out, "\\ No newline at end of file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edits, stat, unified, Edit};
+
+    #[test]
+    fn finds_shortest_edits() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let found = edits(&old, &new);
+        assert_eq!(found.iter().filter(|e| **e != Edit::Equal).count(), 5);
+        assert_eq!(edits(&[], &["a"]), [Edit::Insert]);
+        assert_eq!(edits(&["a"], &[]), [Edit::Delete]);
+        assert_eq!(edits(&["a"], &["a"]), [Edit::Equal]);
+        assert!(edits(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn counts_changed_lines() {
+        assert_eq!(stat("a\nb\nc\n", "a\nB\nc\nd\n"), (2, 1));
+        assert_eq!(stat("a\n", "a\n"), (0, 0));
+    }
+
+    #[test]
+    fn diffs_against_empty_files() {
+        assert_eq!(
+            unified("", "target/\n*.log\n", ".gitignore"),
+            "--- .gitignore\n+++ .gitignore\n@@ -0,0 +1,2 @@\n+target/\n+*.log\n"
+        );
+        assert_eq!(unified("target/\n", "target/\n", ".gitignore"), "");
+    }
+
+    #[test]
+    fn shows_context_around_changes() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\nseventeen\n";
+        assert_eq!(
+            unified(old, new, ".gitignore"),
+            "--- .gitignore\n+++ .gitignore\n@@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n@@ -14,3 +14,4 @@\n 14\n 15\n 16\n+seventeen\n"
+        );
+    }
+
+    #[test]
+    fn marks_missing_newlines() {
+        assert_eq!(
+            unified("*.log", "*.log\ntarget/\n", ".gitignore"),
+            "--- .gitignore\n+++ .gitignore\n@@ -1,1 +1,2 @@\n-*.log\n\\ No newline at end of file\n+*.log\n+target/\n"
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::{
+    config::Config,
+    ignore::{format_age, Core},
+};
+
+/// How a check turned out, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Skipped,
+    Passed,
+    Warning,
+    Failed,
+}
+
+impl Status {
+    /// What `git ignore doctor` exits with when this is the worst result.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Skipped | Status::Passed => 0,
+            Status::Warning => 1,
+            Status::Failed => 2,
+        }
+    }
+}
+
+/// The result of a single check, with a hint on how to fix it unless it
+/// passed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Check {
+    pub status: Status,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn passed(message: impl Into<String>) -> Self {
+        Check {
+            status: Status::Passed,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn skipped(message: impl Into<String>) -> Self {
+        Check {
+            status: Status::Skipped,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Check {
+            status: Status::Warning,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn failed(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Check {
+            status: Status::Failed,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Checks the config, user templates, cache and sources in that order. The
+/// sources aren't contacted when `offline`.
+pub fn run(app: &Core, offline: bool) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let config = check_config(&mut checks, Config::load(app.config_file()));
+    if let Some(config) = &config {
+        checks.push(check_templates_dir(config, &config.templates_dir));
+        checks.extend(check_user_templates(config));
+    }
+
+    let cached = check_cache(&mut checks, app);
+    if let (Some(config), true) = (&config, cached) {
+        checks.extend(check_aliases(app, config));
+    }
+
+    if let Err(err) = app.check_sources() {
+        checks.push(Check::failed(
+            format!("The sources are invalid: {:#}", err),
+            "fix `GIT_IGNORE_SERVER` or the `server` and `sources` in the config",
+        ));
+    } else if offline {
+        checks.push(Check::skipped(
+            "Not checking if the sources can be reached, running offline",
+        ));
+    } else {
+        for (source, reached) in app.reach_sources() {
+            checks.push(match reached {
+                Ok(()) => Check::passed(format!("{} can be reached", source)),
+                Err(err) => Check::failed(
+                    format!("{:#}", err),
+                    "check your connection and proxy settings, or pass '--offline' to only use the cache",
+                ),
+            });
+        }
+    }
+
+    checks
+}
+
+fn check_config(checks: &mut Vec<Check>, config: Result<Option<Config>>) -> Option<Config> {
+    match config {
+        Ok(Some(config)) => {
+            checks.push(Check::passed(format!(
+                "Config '{}' is valid",
+                config.path.display()
+            )));
+            Some(config)
+        }
+        Ok(None) => {
+            checks.push(Check::passed(
+                "No config file, aliases and user templates need one",
+            ));
+            None
+        }
+        Err(err) => {
+            checks.push(Check::failed(
+                format!("{:#}", err),
+                "fix the file or create a new one with `git ignore init --force`",
+            ));
+            None
+        }
+    }
+}
+
+/// Whether user templates are read from next to the config, they aren't when
+/// the config is still where older versions kept it.
+fn check_templates_dir(config: &Config, dir: &Path) -> Check {
+    let beside = config.path.with_file_name("templates");
+    if beside != dir && beside.is_dir() {
+        Check::warning(
+            format!(
+                "User templates are read from '{}', not '{}' next to the config",
+                dir.display(),
+                beside.display()
+            ),
+            "move the config and templates with `git ignore init --migrate`",
+        )
+    } else if !dir.is_dir() && !config.templates.is_empty() {
+        Check::failed(
+            format!(
+                "The user templates directory '{}' is missing",
+                dir.display()
+            ),
+            "create it with `git ignore init`",
+        )
+    } else {
+        Check::passed(format!("User templates are read from '{}'", dir.display()))
+    }
+}
+
+fn check_user_templates(config: &Config) -> Vec<Check> {
+    let mut templates: Vec<_> = config.templates.iter().collect();
+    templates.sort_unstable();
+
+    let failed: Vec<Check> = templates
+        .iter()
+        .filter_map(|(name, file)| {
+            let err = config.read_template(file).err()?;
+            Some(Check::failed(
+                format!(
+                    "User template '{}' can't be read from '{}': {}",
+                    name, file, err
+                ),
+                format!(
+                    "create the file or remove the template with `git ignore template remove {}`",
+                    name
+                ),
+            ))
+        })
+        .collect();
+    if !failed.is_empty() {
+        return failed;
+    }
+
+    vec![Check::passed(format!(
+        "All {} user templates can be read",
+        templates.len()
+    ))]
+}
+
+/// Whether the cache can be read, so the templates in it can be checked.
+fn check_cache(checks: &mut Vec<Check>, app: &Core) -> bool {
+    if !app.cache_exists() {
+        checks.push(Check::warning(
+            format!("No templates are cached in '{}'", app.cache_dir().display()),
+            "download them with `git ignore -u`",
+        ));
+        return false;
+    }
+
+    match app.verify_cache() {
+        Ok(count) => {
+            let age = app.cache_age().map(format_age);
+            let age = age.as_deref().unwrap_or("at an unknown time");
+            checks.push(if app.cache_is_stale() {
+                Check::warning(
+                    format!("{} templates are cached but were updated {}", count, age),
+                    "update them with `git ignore -u`",
+                )
+            } else {
+                Check::passed(format!("{} templates are cached, updated {}", count, age))
+            });
+            true
+        }
+        Err(err) => {
+            checks.push(Check::failed(
+                format!("{:#}", err),
+                "download the templates again with `git ignore cache clear` and `git ignore -u`",
+            ));
+            false
+        }
+    }
+}
+
+fn check_aliases(app: &Core, config: &Config) -> Vec<Check> {
+    let mut names: Vec<String> = config.aliases.keys().cloned().collect();
+    names.sort_unstable();
+
+    // One at a time so a cycle is reported for the aliases it's in
+    let broken: Vec<Check> = names
+        .iter()
+        .filter_map(|name| {
+            // Expanded even when a user template shadows it, user templates
+            // count as known members
+            let expansion = match app.expand_alias(name) {
+                Ok(expansion) => expansion?,
+                Err(err) => {
+                    return Some(Check::failed(
+                        format!("{:#}", err),
+                        "break the cycle by changing one of its aliases with `git ignore alias add`",
+                    ))
+                }
+            };
+            if expansion.missing.is_empty() {
+                return None;
+            }
+            Some(Check::warning(
+                format!(
+                    "Alias '{}' includes unknown templates: {}",
+                    expansion.name,
+                    expansion.missing.join(", ")
+                ),
+                format!(
+                    "remove them with `git ignore alias remove-member {} {}` or find the right names with `git ignore search`",
+                    expansion.name,
+                    expansion.missing.join(" ")
+                ),
+            ))
+        })
+        .collect();
+    if !broken.is_empty() {
+        return broken;
+    }
+
+    vec![Check::passed(format!(
+        "All {} aliases only include known templates",
+        names.len()
+    ))]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{check_aliases, check_config, check_templates_dir, Status};
+    use crate::{config::Config, ignore::Core};
+
+    fn config(path: PathBuf) -> Config {
+        let mut config: Config =
+            toml::from_str("[aliases]\n[templates]\nwork = \"work.txt\"\n").unwrap();
+        config.path = path;
+        config
+    }
+
+    #[test]
+    fn worst_result_decides_the_exit_code() {
+        let worst = [
+            Status::Passed,
+            Status::Failed,
+            Status::Skipped,
+            Status::Warning,
+        ]
+        .into_iter()
+        .max()
+        .unwrap();
+        assert_eq!(worst, Status::Failed);
+        assert_eq!(Status::Warning.exit_code(), 1);
+        assert_eq!(Status::Skipped.max(Status::Passed).exit_code(), 0);
+    }
+
+    #[test]
+    fn reports_broken_configs() {
+        let mut checks = Vec::new();
+        let config = check_config(
+            &mut checks,
+            Err(anyhow::anyhow!("Could not parse 'config.toml'")),
+        );
+        assert!(config.is_none());
+        assert_eq!(checks[0].status, Status::Failed);
+        assert!(checks[0].hint.as_deref().unwrap().contains("init --force"));
+
+        let mut checks = Vec::new();
+        assert!(check_config(&mut checks, Ok(None)).is_none());
+        assert_eq!(checks[0].status, Status::Passed);
+    }
+
+    #[test]
+    fn user_templates_are_known_alias_members() {
+        let dir =
+            std::env::temp_dir().join(format!("git-ignore-doctor-alias-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.toml");
+        std::fs::write(
+            &file,
+            "builtin_aliases = false\n[aliases]\nweb = ['notes', 'rust']\nwork = ['node', 'elm']\n[templates]\nnotes = 'notes.txt'\nwork = 'work.txt'\n",
+        )
+        .unwrap();
+        let app = Core::new(Some(dir.join("cache")), Some(file.clone())).unwrap();
+        let config = app.config.as_ref().unwrap();
+
+        // `work` is shadowed by the user template, but its members are checked
+        let checks = check_aliases(&app, config);
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, Status::Warning);
+        assert!(checks[0]
+            .message
+            .contains("'work' includes unknown templates: elm"));
+
+        std::fs::write(
+            &file,
+            "[aliases]\nweb = ['notes', 'rust']\n[templates]\nnotes = 'notes.txt'\n",
+        )
+        .unwrap();
+        let app = Core::new(Some(dir.join("cache")), Some(file)).unwrap();
+        let checks = check_aliases(&app, app.config.as_ref().unwrap());
+        assert_eq!(checks[0].status, Status::Passed);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn finds_misplaced_templates() {
+        let dir = std::env::temp_dir().join(format!("git-ignore-doctor-{}", std::process::id()));
+        let old = dir.join("old");
+        let new = dir.join("new").join("templates");
+        std::fs::create_dir_all(old.join("templates")).unwrap();
+
+        // The config is in the old place with its templates next to it
+        let check = check_templates_dir(&config(old.join("config.toml")), &new);
+        assert_eq!(check.status, Status::Warning);
+        assert!(check.hint.unwrap().contains("--migrate"));
+
+        let check = check_templates_dir(&config(dir.join("new").join("config.toml")), &new);
+        assert_eq!(check.status, Status::Failed);
+
+        std::fs::create_dir_all(&new).unwrap();
+        let check = check_templates_dir(&config(dir.join("new").join("config.toml")), &new);
+        assert_eq!(check.status, Status::Passed);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use std::{path::Path, process::ExitStatus};
+
+use anyhow::{Context, Result};
+
+use crate::pager::shell_command;
+
+/// Used when neither `VISUAL` or `EDITOR` is set.
+const DEFAULT_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+/// Finds the editor to use like git does, from `VISUAL` or `EDITOR`.
+pub fn editor_command<E>(env: E) -> String
+where
+    E: Fn(&str) -> Option<String>,
+{
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(env)
+        .map(|editor| editor.trim().to_string())
+        .find(|editor| !editor.is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+/// Opens `file` in `editor` and waits for it to exit. The editor is run by the
+/// shell, so it can have arguments, e.g. `code --wait`.
+pub fn edit(editor: &str, file: &Path) -> Result<ExitStatus> {
+    shell_command(editor)
+        .arg(file)
+        .status()
+        .with_context(|| format!("Could not start the editor '{}'", editor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit, editor_command, DEFAULT_EDITOR};
+    use crate::pager::env;
+
+    #[test]
+    fn editor_follows_git_precedence() {
+        let vars = env(&[("VISUAL", "code --wait"), ("EDITOR", "nano")]);
+        assert_eq!(editor_command(vars), "code --wait");
+        assert_eq!(editor_command(env(&[("EDITOR", "nano")])), "nano");
+        assert_eq!(
+            editor_command(env(&[("VISUAL", " "), ("EDITOR", "nano")])),
+            "nano"
+        );
+        assert_eq!(editor_command(env(&[])), DEFAULT_EDITOR);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn runs_the_editor_on_the_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("git-ignore-editor-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let editor = dir.join("fake editor");
+        std::fs::write(&editor, "#!/bin/sh\necho \"$1\" >> \"$3\"\nexit $2\n").unwrap();
+        std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let file = dir.join("my template.txt");
+
+        let command = |args: &str| format!("'{}' {}", editor.display(), args);
+        assert!(edit(&command("edited 0"), &file).unwrap().success());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "edited\n");
+
+        let status = edit(&command("again 3"), &file).unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "edited\nagain\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use crate::ignore::Language;
+
+include!(concat!(env!("OUT_DIR"), "/fallback.rs"));
+
+/// The `source` of templates that were embedded in the binary rather than
+/// downloaded.
+pub const FALLBACK_SOURCE: &str = "built-in";
+
+/// A small set of common templates from `data/fallback` that is baked into
+/// the binary, so we have something to work with when nothing is cached and
+/// the templates can't be downloaded.
+pub fn templates() -> HashMap<String, Language> {
+    fallback_templates()
+        .into_iter()
+        .map(|(name, contents)| {
+            let key = name.to_lowercase();
+            let language = Language {
+                key: key.clone(),
+                name: name.to_string(),
+                file_name: format!("{}.gitignore", name),
+                contents: format!("\n### {} ###\n{}", name, contents),
+                source: Some(FALLBACK_SOURCE.to_string()),
+            };
+            (key, language)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{templates, FALLBACK_SOURCE};
+
+    #[test]
+    fn embeds_curated_templates() {
+        let templates = templates();
+        for key in [
+            "rust",
+            "node",
+            "python",
+            "go",
+            "java",
+            "macos",
+            "windows",
+            "linux",
+            "visualstudiocode",
+        ] {
+            assert!(templates.contains_key(key), "missing {}", key);
+        }
+
+        let rust = &templates["rust"];
+        assert_eq!(rust.name, "Rust");
+        assert!(rust.contents.starts_with("\n### Rust ###\n"));
+        assert_eq!(rust.source.as_deref(), Some(FALLBACK_SOURCE));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::ignore::ListEntry;
+
+/// Scores how well `query` matches `candidate` from 0 to 100, ignoring case.
+/// An exact match is 100, a query whose letters all appear in order in the
+/// candidate scores higher the closer together they are and the more of it
+/// they cover, e.g. `vscode` in `visualstudiocode`. Anything else is scored
+/// by how many typos away from the candidate or its start it is, so `pyhton`
+/// still finds `python`.
+pub fn score(query: &str, candidate: &str) -> u32 {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let lowered: Vec<char> = candidate.to_lowercase().chars().collect();
+    if query.is_empty() || lowered.is_empty() {
+        return 0;
+    } else if query == lowered {
+        return 100;
+    }
+
+    let starts = word_starts(candidate);
+    subsequence(&query, &lowered, &starts).max(typos(&query, &lowered))
+}
+
+/// The entries scoring at least `threshold` against `query`, best first. Both
+/// the name and the display name of a template are matched.
+pub fn search(query: &str, entries: Vec<ListEntry>, threshold: u32) -> Vec<(u32, ListEntry)> {
+    let mut matches: Vec<_> = entries
+        .into_iter()
+        .map(|entry| {
+            let name = entry.name.as_deref().map_or(0, |name| score(query, name));
+            (score(query, &entry.key).max(name), entry)
+        })
+        .filter(|(score, _)| *score >= threshold)
+        .collect();
+    matches.sort_by(|(a, a_entry), (b, b_entry)| b.cmp(a).then(a_entry.key.cmp(&b_entry.key)));
+
+    matches
+}
+
+/// Whether each character starts a word, i.e. comes first, after something
+/// that isn't a letter or number or is an uppercase letter after a lowercase
+/// one like in `VisualStudioCode`.
+fn word_starts(candidate: &str) -> Vec<bool> {
+    let chars: Vec<char> = candidate.chars().collect();
+    // Lowercasing can turn one character into several, keep the lengths equal
+    let lowered_len = candidate.to_lowercase().chars().count();
+    let mut starts: Vec<bool> = (0..chars.len())
+        .map(|i| {
+            i == 0
+                || !chars[i - 1].is_alphanumeric()
+                || (chars[i - 1].is_lowercase() && chars[i].is_uppercase())
+        })
+        .collect();
+    starts.resize(lowered_len, false);
+
+    starts
+}
+
+/// Scores `query` as a subsequence of `candidate` between 50 and 95, 0 if it
+/// isn't one. Every matched character is worth a point, with another if it
+/// follows the previous match and another if it starts a word.
+fn subsequence(query: &[char], candidate: &[char], starts: &[bool]) -> u32 {
+    let mut points = 0;
+    let mut previous = None;
+    let mut next = 0;
+
+    for c in query {
+        let Some(found) = candidate[next..].iter().position(|x| x == c) else {
+            return 0;
+        };
+        let index = next + found;
+        points += 1 + u32::from(previous == Some(index.wrapping_sub(1))) + u32::from(starts[index]);
+        previous = Some(index);
+        next = index + 1;
+    }
+
+    let tightness = f64::from(points) / (3 * query.len()) as f64;
+    let coverage = query.len() as f64 / candidate.len() as f64;
+    50 + (45.0 * (tightness + coverage) / 2.0).round() as u32
+}
+
+/// Scores how close `query` is to `candidate` or the start of it by the number
+/// of typos between them, at most 90 since it's never exact.
+fn typos(query: &[char], candidate: &[char]) -> u32 {
+    let similarity = |other: &[char]| {
+        let longest = query.len().max(other.len()) as f64;
+        1.0 - distance(query, other) as f64 / longest
+    };
+    let start = &candidate[..query.len().min(candidate.len())];
+    // Only matching the start says less about the whole name
+    let best = similarity(candidate).max(similarity(start) * 0.9);
+
+    (90.0 * best).round().max(0.0) as u32
+}
+
+/// The number of insertions, deletions, substitutions and swaps of adjacent
+/// characters to turn `a` into `b`, i.e. their optimal string alignment
+/// distance.
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance, score, search};
+    use crate::ignore::{ListEntry, Origin};
+
+    fn entry(key: &str, name: Option<&str>) -> ListEntry {
+        ListEntry {
+            key: key.to_string(),
+            kind: Origin::Template,
+            name: name.map(ToString::to_string),
+            targets: Vec::new(),
+            source: None,
+            shadows: false,
+        }
+    }
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn counts_typos() {
+        assert_eq!(distance(&chars("python"), &chars("python")), 0);
+        assert_eq!(distance(&chars("pyhton"), &chars("python")), 1);
+        assert_eq!(distance(&chars("rsut"), &chars("rust")), 1);
+        assert_eq!(distance(&chars("jav"), &chars("java")), 1);
+        assert_eq!(distance(&chars(""), &chars("go")), 2);
+        assert_eq!(distance(&chars("kitten"), &chars("sitting")), 3);
+    }
+
+    #[test]
+    fn scores_matches() {
+        assert_eq!(score("rust", "rust"), 100);
+        assert_eq!(score("Rust", "rust"), 100);
+        assert_eq!(score("", "rust"), 0);
+
+        assert!(score("pyhton", "python") >= 70);
+        assert!(score("vscode", "visualstudiocode") >= 60);
+        assert!(score("vscode", "VisualStudioCode") > score("vscode", "visualstudiocode"));
+        assert!(score("rust", "rust") > score("rust", "rustrover"));
+        assert!(score("node", "nodejs") > score("node", "nativescript"));
+        assert!(score("rust", "haskell") < 50);
+    }
+
+    #[test]
+    fn finds_obvious_typos() {
+        let entries = vec![
+            entry("python", Some("Python")),
+            entry("pycharm", Some("PyCharm")),
+            entry("rust", Some("Rust")),
+            entry("rustrover", None),
+            entry("visualstudiocode", Some("VisualStudioCode")),
+            entry("visualstudio", Some("VisualStudio")),
+            entry("node", Some("Node")),
+            entry("go", Some("Go")),
+        ];
+        let best = |query: &str| {
+            let found = search(query, entries.clone(), 50);
+            found.first().map(|(_, e)| e.key.clone())
+        };
+
+        assert_eq!(best("pyhton").as_deref(), Some("python"));
+        assert_eq!(best("rsut").as_deref(), Some("rust"));
+        assert_eq!(best("ndoe").as_deref(), Some("node"));
+        assert_eq!(best("vscode").as_deref(), Some("visualstudiocode"));
+        assert_eq!(best("rust").as_deref(), Some("rust"));
+        assert_eq!(best("xyzzy"), None);
+    }
+
+    #[test]
+    fn ranks_best_first() {
+        let entries = vec![
+            entry("rustrover", None),
+            entry("rust", None),
+            entry("trust", None),
+        ];
+        let found = search("rust", entries, 50);
+        let keys: Vec<_> = found.iter().map(|(_, e)| e.key.as_str()).collect();
+        assert_eq!(keys[0], "rust");
+        assert!(found.windows(2).all(|w| w[0].0 >= w[1].0));
+        assert!(search("rust", vec![entry("rust", None)], 101).is_empty());
+    }
+}
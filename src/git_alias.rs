@@ -0,0 +1,151 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// The git config key of the alias.
+pub const KEY: &str = "alias.ignore";
+/// What the alias runs, a shell command so git finds the binary on `PATH`.
+pub const VALUE: &str = "!git-ignore";
+
+/// What registering the alias has to do.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Plan {
+    /// The alias is already set up
+    Unchanged,
+    /// Set the alias, replacing `previous` if it was set to something else
+    Set { previous: Option<String> },
+}
+
+/// Decides what to do given the alias currently in the git config. A
+/// different alias is only replaced with `force`.
+pub fn plan(existing: Option<&str>, force: bool) -> Result<Plan> {
+    match existing {
+        Some(VALUE) => Ok(Plan::Unchanged),
+        Some(previous) if !force => bail!(
+            "The git alias 'ignore' is already set to '{}', pass '--force' to replace it",
+            previous
+        ),
+        previous => Ok(Plan::Set {
+            previous: previous.map(ToString::to_string),
+        }),
+    }
+}
+
+/// The command that sets the alias, in a form that can be pasted in a shell.
+pub fn command(value: &str) -> String {
+    format!("git config --global {} '{}'", KEY, value)
+}
+
+/// Reads the alias from the global git config, `None` if it isn't set.
+pub fn current() -> Result<Option<String>> {
+    let output = Command::new("git")
+        .args(["config", "--global", "--get", KEY])
+        .stderr(Stdio::null())
+        .output()
+        .context("Could not run git, is it installed?")?;
+
+    // git exits with 1 when the key isn't set
+    match output.status.code() {
+        Some(0) => Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        )),
+        Some(1) => Ok(None),
+        _ => bail!(
+            "Could not read '{}' from the git config: {}",
+            KEY,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    }
+}
+
+/// Sets the alias in the global git config.
+pub fn set() -> Result<()> {
+    let status = Command::new("git")
+        .args(["config", "--global", KEY, VALUE])
+        .status()
+        .context("Could not run git, is it installed?")?;
+    if !status.success() {
+        bail!("Could not set '{}' in the git config", KEY);
+    }
+
+    Ok(())
+}
+
+/// Finds `bin` in the directories of `path`, like the shell would.
+pub fn find_on_path(bin: &str, path: Option<&OsStr>) -> Option<PathBuf> {
+    let names = if cfg!(windows) {
+        vec![format!("{}.exe", bin), bin.to_string()]
+    } else {
+        vec![bin.to_string()]
+    };
+
+    std::env::split_paths(path?)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|file| is_executable(file))
+}
+
+#[cfg(unix)]
+fn is_executable(file: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(file: &Path) -> bool {
+    file.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::{command, find_on_path, plan, Plan, VALUE};
+
+    #[test]
+    fn only_replaces_other_aliases_with_force() {
+        assert_eq!(plan(None, false).unwrap(), Plan::Set { previous: None });
+        assert_eq!(plan(Some(VALUE), false).unwrap(), Plan::Unchanged);
+        assert_eq!(plan(Some(VALUE), true).unwrap(), Plan::Unchanged);
+
+        let err = plan(Some("!gi"), false).unwrap_err().to_string();
+        assert!(err.contains("'!gi'") && err.contains("--force"));
+        assert_eq!(
+            plan(Some("!gi"), true).unwrap(),
+            Plan::Set {
+                previous: Some("!gi".into())
+            }
+        );
+        assert_eq!(
+            command(VALUE),
+            "git config --global alias.ignore '!git-ignore'"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn finds_executables_on_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("git-ignore-path-{}", std::process::id()));
+        let (empty, bin) = (dir.join("empty"), dir.join("bin"));
+        std::fs::create_dir_all(&empty).unwrap();
+        std::fs::create_dir_all(&bin).unwrap();
+        let file = bin.join("git-ignore");
+        std::fs::write(&file, "").unwrap();
+
+        let path = std::env::join_paths([&empty, &bin]).unwrap();
+        assert_eq!(find_on_path("git-ignore", Some(&path)), None);
+
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(find_on_path("git-ignore", Some(&path)), Some(file));
+        assert_eq!(find_on_path("git-ignore", Some(&OsString::new())), None);
+        assert_eq!(find_on_path("git-ignore", None), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use std::{collections::HashMap, ffi::OsStr, io::Read, path::Path};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+
+use crate::ignore::Language;
+
+/// Tarball of the default branch of [github/gitignore](https://github.com/github/gitignore).
+pub const GITHUB_TARBALL: &str =
+    "https://codeload.github.com/github/gitignore/tar.gz/refs/heads/main";
+
+/// Converts a gzipped tarball of the `github/gitignore` repository into the
+/// same shape as the gitignore.io API, so the cache looks the same regardless
+/// of where the templates came from. Templates in the root of the repository
+/// win over those in `Global/` and `community/` if their names collide.
+pub fn templates_from_tarball<R: Read>(reader: R) -> Result<HashMap<String, Language>> {
+    let mut data = Vec::new();
+    GzDecoder::new(reader)
+        .read_to_end(&mut data)
+        .context("Could not decompress template archive")?;
+
+    let mut templates: HashMap<String, (usize, Language)> = HashMap::new();
+    for (path, contents) in entries(&data)? {
+        let path = Path::new(&path);
+        if path.extension() != Some(OsStr::new("gitignore")) {
+            continue;
+        }
+
+        let (Some(name), Some(file_name)) = (
+            path.file_stem().and_then(OsStr::to_str),
+            path.file_name().and_then(OsStr::to_str),
+        ) else {
+            continue;
+        };
+
+        let key = name.to_lowercase();
+        let depth = path.components().count();
+        if matches!(templates.get(&key), Some((existing, _)) if *existing <= depth) {
+            continue;
+        }
+
+        let language = Language {
+            key: key.clone(),
+            name: name.to_string(),
+            file_name: file_name.to_string(),
+            contents: format!("\n### {} ###\n{}", name, String::from_utf8_lossy(contents)),
+            source: None,
+        };
+        templates.insert(key, (depth, language));
+    }
+
+    if templates.is_empty() {
+        bail!("No templates found in the template archive");
+    }
+
+    Ok(templates
+        .into_iter()
+        .map(|(key, (_, language))| (key, language))
+        .collect())
+}
+
+/// Walks an uncompressed tar archive and returns the path and contents of every
+/// regular file in it. Only the subset of the format GitHub produces is
+/// supported, i.e. ustar with PAX or GNU long names.
+fn entries(data: &[u8]) -> Result<Vec<(String, &[u8])>> {
+    let mut result = Vec::new();
+    let mut long_name = None;
+    let mut offset = 0;
+
+    while offset + 512 <= data.len() {
+        let header = &data[offset..offset + 512];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+
+        let size = parse_octal(&header[124..136])?;
+        let start = offset + 512;
+        let body = data
+            .get(start..start + size)
+            .context("Template archive is truncated")?;
+
+        match header[156] {
+            b'0' | 0 => {
+                let path = long_name.take().unwrap_or_else(|| header_path(header));
+                result.push((path, body));
+            }
+            b'x' => long_name = pax_path(body),
+            b'L' => long_name = Some(c_str(body)),
+            _ => {}
+        }
+
+        offset = start + size.div_ceil(512) * 512;
+    }
+
+    Ok(result)
+}
+
+fn header_path(header: &[u8]) -> String {
+    let name = c_str(&header[0..100]);
+    let prefix = c_str(&header[345..500]);
+
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+/// PAX extended headers are a list of `<length> <key>=<value>\n` records.
+fn pax_path(body: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter_map(|record| record.split_once(' '))
+        .find_map(|(_, record)| record.strip_prefix("path=").map(String::from))
+}
+
+fn parse_octal(field: &[u8]) -> Result<usize> {
+    let field = c_str(field);
+    let field = field.trim();
+    if field.is_empty() {
+        return Ok(0);
+    }
+
+    usize::from_str_radix(field, 8).context("Invalid entry size in template archive")
+}
+
+fn c_str(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::templates_from_tarball;
+
+    fn tar_entry(archive: &mut Vec<u8>, path: &str, contents: &str) {
+        let mut header = [0u8; 512];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        let size = format!("{:011o}\0", contents.len());
+        header[124..136].copy_from_slice(size.as_bytes());
+        header[156] = b'0';
+
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(contents.as_bytes());
+        archive.resize(archive.len().div_ceil(512) * 512, 0);
+    }
+
+    fn tarball(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        for (path, contents) in entries {
+            tar_entry(&mut archive, path, contents);
+        }
+        archive.extend_from_slice(&[0; 1024]);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&archive).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn converts_gitignore_files() {
+        let data = tarball(&[
+            ("gitignore-main/README.md", "# gitignore"),
+            ("gitignore-main/Rust.gitignore", "target/\n"),
+            ("gitignore-main/Global/macOS.gitignore", ".DS_Store\n"),
+        ]);
+
+        let templates = templates_from_tarball(data.as_slice()).unwrap();
+        assert_eq!(templates.len(), 2);
+
+        let rust = &templates["rust"];
+        assert_eq!(rust.name, "Rust");
+        assert_eq!(rust.file_name, "Rust.gitignore");
+        assert_eq!(rust.contents, "\n### Rust ###\ntarget/\n");
+        assert!(templates.contains_key("macos"));
+    }
+
+    #[test]
+    fn root_templates_take_precedence() {
+        let data = tarball(&[
+            ("gitignore-main/community/Go.gitignore", "community\n"),
+            ("gitignore-main/Go.gitignore", "root\n"),
+        ]);
+
+        let templates = templates_from_tarball(data.as_slice()).unwrap();
+        assert!(templates["go"].contents.ends_with("root\n"));
+    }
+
+    #[test]
+    fn empty_archive_is_an_error() {
+        let data = tarball(&[("gitignore-main/README.md", "# gitignore")]);
+        assert!(templates_from_tarball(data.as_slice()).is_err());
+    }
+}
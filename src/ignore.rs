@@ -1,21 +1,77 @@
 use std::{
     cmp::Ordering,
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     env::current_dir,
+    ffi::OsString,
     fmt::{write, Display, Write},
-    fs::{read_dir, read_to_string, DirEntry, File},
+    fs::{read_dir, read_to_string, DirEntry, File, OpenOptions, TryLockError},
     hash::{Hash, Hasher},
-    io::Write as _,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write as _},
     path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
+use attohttpc::{header, RequestBuilder, StatusCode};
 use colored::Colorize;
 use directories::ProjectDirs;
 use etcetera::{choose_app_strategy, AppStrategy, AppStrategyArgs};
-use serde::{Deserialize, Serialize};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use regex::Regex;
+use serde::{Deserialize, Serialize, Serializer};
+use url::Url;
 
-use crate::{config::Config, detector::Detectors};
+use crate::{
+    block,
+    builtin::{self, BUILTIN_SOURCE},
+    config::{resolve_config_file, Config, TemplateContents, CONFIG_ENV},
+    coverage::{self, Checked},
+    detector::Detectors,
+    fallback::{self, FALLBACK_SOURCE},
+    github::{templates_from_tarball, GITHUB_TARBALL},
+    log::{debug, info, trace, warning},
+    progress::Progress,
+    proxy::{is_reachable, Proxies, Redacted},
+};
+
+/// Where templates are fetched from unless overridden.
+pub const DEFAULT_SERVER: &str = "https://www.gitignore.io/api/list?format=json";
+
+/// The comment generated `.gitignore` files start with.
+const ATTRIBUTION: &str = "### Created by https://www.gitignore.io";
+
+/// Starts the line noting that the templates came from the fallback ones.
+const FALLBACK_NOTE: &str = "### Generated from the built-in fallback templates";
+
+/// Generated `.gitignore` files end with this followed by the templates they
+/// were generated from, so the same set can be generated again later.
+const FOOTER_PREFIX: &str = "# git-ignore:";
+
+/// Environment variable that overrides the template server.
+pub const SERVER_ENV: &str = "GIT_IGNORE_SERVER";
+
+/// Environment variable that overrides where templates are cached.
+pub const CACHE_DIR_ENV: &str = "GIT_IGNORE_CACHE_DIR";
+
+/// How long to wait for a connection to the template server, unless
+/// overridden by `connect_timeout_secs` in the config.
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// How long to wait for data from the template server, unless overridden by
+/// `read_timeout_secs` in the config.
+pub const DEFAULT_READ_TIMEOUT_SECS: u64 = 30;
+
+/// How many times a download that failed for transient reasons is retried.
+const MAX_RETRIES: u32 = 3;
+
+/// The largest response we accept from a template server in megabytes, unless
+/// overridden by `max_download_mb` in the config.
+pub const DEFAULT_MAX_DOWNLOAD_MB: u64 = 20;
+
+/// How many days the cache is used before it is automatically refreshed, unless
+/// overridden by `cache_ttl_days` in the config.
+pub const DEFAULT_CACHE_TTL_DAYS: u64 = 30;
 
 #[cfg(target_os = "windows")]
 pub fn project_dirs() -> etcetera::app_strategy::Windows {
@@ -45,232 +101,6626 @@ pub fn old_project_dirs() -> ProjectDirs {
 
 #[derive(Debug)]
 pub struct Core {
-    server: String,
+    sources: Vec<Source>,
+    /// Why the configured sources are invalid, only reported once they're
+    /// needed so everything that doesn't fetch templates keeps working
+    sources_error: Option<String>,
+    proxies: Proxies,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    retry_backoff: Duration,
+    max_download: u64,
     cache_dir: PathBuf,
+    index_file: PathBuf,
+    templates_dir: PathBuf,
+    /// The single file all templates were cached in by older versions
     ignore_file: PathBuf,
+    /// Like `ignore_file`, but compressed
+    compressed_file: PathBuf,
+    meta_file: PathBuf,
     detectors: Detectors,
+    /// Templates read ahead of a background update, so it can't change them
+    /// halfway through a run
+    snapshot: OnceLock<HashMap<Type, Entry>>,
+    /// The config file given on the command line or in the environment
+    config_file: Option<PathBuf>,
     pub config: Option<Config>,
+    pub offline: bool,
+    pub verbose: bool,
+    /// Wrap each template in delimiter comments in the generated output
+    pub section_headers: bool,
+    /// Strip comments and blank lines from the generated output
+    pub compact: bool,
+    /// End the generated output with the templates it was generated from
+    pub footer: bool,
+    /// Templates to leave out even when an alias or `--auto` includes them,
+    /// lowercased
+    pub exclude: Vec<String>,
+}
+
+/// Summary of the on-disk template cache.
+#[derive(Debug)]
+pub struct CacheInfo {
+    pub path: PathBuf,
+    /// Size of the cache on disk
+    pub size: u64,
+    /// Size of the templates once decompressed
+    pub uncompressed: u64,
+    pub templates: usize,
+    pub updated: Option<SystemTime>,
+}
+
+/// Counts of everything templates can be generated from, for `git ignore
+/// stats`.
+#[derive(Serialize, Debug, Default)]
+pub struct Stats {
+    /// Templates in the cache
+    pub templates: usize,
+    pub aliases: usize,
+    pub user_templates: usize,
+    /// How many cached templates came from each source
+    pub sources: BTreeMap<String, usize>,
+    pub cache_dir: PathBuf,
+    /// Size of the cache on disk
+    pub cache_size: u64,
+    /// When the templates were last updated, in seconds since the Unix epoch
+    pub updated: Option<u64>,
+    /// The config file, if there is one
+    pub config: Option<PathBuf>,
+}
+
+/// Information about the last successful update, stored next to the cache.
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct CacheMeta {
+    /// When the templates were last fetched or confirmed to be up to date, in
+    /// seconds since the Unix epoch
+    updated_at: Option<u64>,
+    /// Response headers for each source URL
+    #[serde(default)]
+    sources: HashMap<String, SourceMeta>,
 }
 
+/// The cache bundled into a single file, for moving it to a machine that can't
+/// download the templates itself.
 #[derive(Deserialize, Serialize, Debug)]
-struct Language {
-    key: String,
-    name: String,
-    #[serde(rename = "fileName")]
-    file_name: String,
-    contents: String,
+struct CacheExport {
+    meta: CacheMeta,
+    templates: HashMap<String, Language>,
 }
 
-#[derive(Debug, Clone)]
-pub enum Type {
-    Normal(String),
-    Alias(String),
-    Template(String),
+/// Response headers from the last successful download from a source, used to
+/// avoid downloading the templates again when nothing has changed.
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct SourceMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
 }
 
-impl Display for Type {
+/// Templates downloaded from a single source.
+struct Download {
+    templates: HashMap<String, Language>,
+    meta: SourceMeta,
+    size: u64,
+}
+
+/// How long to wait for another process to finish updating the cache.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often to check whether another process has finished updating the cache.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Exclusive lock on the cache while it is being updated, released on drop.
+struct UpdateLock(File);
+
+impl Drop for UpdateLock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+/// How many templates should be named in an update summary before the rest
+/// are only counted.
+const MAX_LISTED_TEMPLATES: usize = 10;
+
+/// Which templates an update added, removed or changed, by key.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct TemplateDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<String>,
+}
+
+impl TemplateDiff {
+    fn new(old: &HashMap<String, Language>, new: &HashMap<String, Language>) -> Self {
+        let mut diff = TemplateDiff::default();
+        for (key, language) in new {
+            match old.get(key) {
+                None => diff.added.push(key.clone()),
+                Some(previous) if previous.contents != language.contents => {
+                    diff.changed.push(key.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = old
+            .keys()
+            .filter(|key| !new.contains_key(*key))
+            .cloned()
+            .collect();
+
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.changed.sort_unstable();
+        diff
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Display for TemplateDiff {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Type::Normal(name) => write(f, format_args!("{}", name)),
-            Type::Alias(name) => write(f, format_args!("{}", name.yellow())),
-            Type::Template(name) => write(f, format_args!("{}", name.blue())),
+        if self.is_empty() {
+            return write!(f, "No templates changed");
+        }
+
+        let names = |keys: &[String]| {
+            let mut names = keys
+                .iter()
+                .take(MAX_LISTED_TEMPLATES)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ");
+            if keys.len() > MAX_LISTED_TEMPLATES {
+                write!(names, " and {} more", keys.len() - MAX_LISTED_TEMPLATES).unwrap();
+            }
+            names
+        };
+
+        let mut parts = Vec::new();
+        if !self.added.is_empty() {
+            let noun = if self.added.len() == 1 {
+                "template"
+            } else {
+                "templates"
+            };
+            parts.push(format!(
+                "{} {} added ({})",
+                self.added.len(),
+                noun,
+                names(&self.added)
+            ));
+        }
+        if !self.removed.is_empty() {
+            parts.push(format!(
+                "{} removed ({})",
+                self.removed.len(),
+                names(&self.removed)
+            ));
+        }
+        if !self.changed.is_empty() {
+            parts.push(format!("{} changed", self.changed.len()));
         }
+
+        write!(f, "{}", parts.join(", "))
     }
 }
 
-impl PartialEq for Type {
-    fn eq(&self, other: &Self) -> bool {
-        self.inner() == other.inner()
+/// A failure that might go away if we try again, like a dropped connection or
+/// the server having a bad day.
+#[derive(Debug)]
+struct Transient(anyhow::Error);
+
+impl Display for Transient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.0)
     }
 }
 
-impl Eq for Type {}
+impl std::error::Error for Transient {}
 
-impl PartialOrd for Type {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl SourceMeta {
+    fn from_headers(headers: &header::HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+
+        SourceMeta {
+            etag: get(header::ETAG),
+            last_modified: get(header::LAST_MODIFIED),
+        }
     }
 }
 
-impl Ord for Type {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.inner().cmp(other.inner())
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Language {
+    pub key: String,
+    pub name: String,
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    pub contents: String,
+    /// URL of the source the template was fetched from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// A template in the cache index. Its contents are stored in a file of their
+/// own and only read once the template is used, unless they are already in
+/// memory.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct Entry {
+    name: String,
+    #[serde(rename = "fileName")]
+    file_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    /// Size of the contents in bytes
+    size: u64,
+    #[serde(skip)]
+    contents: Option<String>,
+}
+
+impl Entry {
+    /// Returns true if the template is one of the embedded fallback templates.
+    fn is_fallback(&self) -> bool {
+        self.source.as_deref() == Some(FALLBACK_SOURCE)
+    }
+
+    fn into_language(self, key: String, contents: String) -> Language {
+        Language {
+            key,
+            name: self.name,
+            file_name: self.file_name,
+            contents,
+            source: self.source,
+        }
     }
 }
 
-impl Hash for Type {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.inner().hash(state);
+impl From<Language> for Entry {
+    fn from(language: Language) -> Self {
+        Entry {
+            name: language.name,
+            file_name: language.file_name,
+            source: language.source,
+            size: language.contents.len() as u64,
+            contents: Some(language.contents),
+        }
     }
 }
 
-impl Type {
-    fn inner(&self) -> &str {
+/// What a name passed on the command line was resolved to.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    /// A downloaded template
+    Template,
+    /// An alias from the config
+    Alias,
+    /// A template from the user's template directory
+    User,
+}
+
+impl Origin {
+    /// How the kind of name is shown in listings.
+    pub fn name(self) -> &'static str {
         match self {
-            Type::Normal(name) | Type::Alias(name) | Type::Template(name) => name,
+            Origin::Template => "template",
+            Origin::Alias => "alias",
+            Origin::User => "user",
         }
     }
+}
 
-    fn contains(&self, name: &str) -> bool {
-        let inner = self.inner();
-        inner.contains(name)
-    }
+/// A single entry of `--list --format json`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ListEntry {
+    pub key: String,
+    pub kind: Origin,
+    /// The display name of a downloaded template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The templates an alias expands to
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<String>,
+    /// Where a downloaded template came from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Whether an alias or user template hides a downloaded template
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub shadows: bool,
 }
 
-impl Core {
-    /// Creates a new instance of the `git-ignore` program. Thanks to
-    /// `directories` we support crossplatform caching of our results, the cache
-    /// directories works on macOS, Linux and Windows. See the documentation for
-    /// their locations.
-    pub fn new() -> Self {
-        let cache_dir = project_dirs().cache_dir();
-        let ignore_file = cache_dir.join("ignore.json");
-        let config = Config::from_dir();
+/// A single resolved name and its contents.
+#[derive(Serialize, Debug)]
+pub struct Resolved {
+    pub key: String,
+    pub source: Origin,
+    pub content: String,
+}
 
-        Core {
-            server: "https://www.gitignore.io/api/list?format=json".into(),
-            cache_dir,
-            ignore_file,
-            detectors: Detectors::default(),
-            config,
+/// What a name given on the command line expands to, worked out before any
+/// template is read.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    /// The name as it was given
+    pub name: String,
+    /// What the name is, nothing if it wasn't found or was excluded
+    pub kind: Option<Origin>,
+    /// The templates it expands to, in the order they're written
+    pub templates: Vec<String>,
+    /// Templates it would have expanded to that were excluded
+    pub excluded: Vec<String>,
+    /// Members of an alias that weren't found
+    pub missing: Vec<String>,
+    /// Members of an alias that are user templates, the rest of `templates`
+    /// are downloaded ones
+    pub user_templates: Vec<String>,
+    /// Whether `--auto` detected it instead of it being given
+    pub detected: bool,
+    /// Whether it was added from `default_templates` in the config
+    pub default: bool,
+    /// The profile that added it, if it wasn't given
+    pub profile: Option<String>,
+}
+
+/// The lines of a template matching `git ignore grep`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    pub key: String,
+    pub kind: Origin,
+    pub lines: Vec<String>,
+}
+
+/// An alias expanded all the way down, for `git ignore alias show`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AliasExpansion {
+    pub name: String,
+    /// The members as they're written in the config
+    pub members: Vec<String>,
+    /// The templates it expands to through nested aliases, in order
+    pub resolved: Vec<String>,
+    /// Members of it or the aliases it includes that weren't found
+    pub missing: Vec<String>,
+}
+
+/// Which templates a line of a `.gitignore` comes from, for `git ignore
+/// explain`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub line: String,
+    pub templates: Vec<Contributor>,
+    /// Whether the templates only have the line with different whitespace
+    pub trimmed: bool,
+    /// Whether the `.gitignore` has the line, if there is one
+    pub in_gitignore: Option<bool>,
+}
+
+/// A template containing a line that's being explained.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Contributor {
+    pub key: String,
+    pub kind: Origin,
+}
+
+/// Something a name refers to, see `Core::matches`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Match {
+    /// A user template and the file it's read from
+    User { file: PathBuf },
+    /// An alias and what each of its members is
+    Alias { members: Vec<(String, Member)> },
+    /// A downloaded template, its key in the cache and where it came from
+    Template { key: String, source: String },
+}
+
+/// What a member of an alias refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Member {
+    /// Another alias, expanded in its place
+    Alias,
+    /// A user template and its file
+    User(PathBuf),
+    /// A template and its key in the cache
+    Template(String),
+    /// Nothing that's known
+    Missing,
+}
+
+/// A name that didn't match any template or alias.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unresolved {
+    pub name: String,
+    /// The alias the name is a member of, if it came from one
+    pub alias: Option<String>,
+}
+
+impl Display for Unresolved {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.alias {
+            Some(alias) => write!(f, "'{}' from the alias '{}'", self.name, alias),
+            None => write!(f, "'{}'", self.name),
         }
     }
+}
 
-    /// Both updates and initializes `git-ignore`. Creates the cache directory
-    /// if it doesn't exist and then downloads the templates from
-    /// [gitignore.io](https://www.gitignore.io), saving them in the cache
-    /// directory.
-    pub fn update(&self) -> Result<()> {
-        self.create_dirs()?;
-        self.fetch_gitignore()?;
+/// Serialized as just the name, like it was before aliases were reported.
+impl Serialize for Unresolved {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name)
+    }
+}
 
-        eprintln!("{}: Update successful", "Info".bold().green());
-        Ok(())
+/// The result of generating a `.gitignore`, used for both the plain and JSON
+/// output.
+#[derive(Serialize, Debug, Default)]
+pub struct Generated {
+    pub templates: Vec<Resolved>,
+    /// Names that didn't match any template or alias
+    pub unresolved: Vec<Unresolved>,
+    /// The final `.gitignore` with every template in it
+    pub combined: String,
+    /// Every template in the output with aliases expanded, in order
+    #[serde(skip)]
+    keys: Vec<String>,
+    /// Every excluded template that was left out
+    #[serde(skip)]
+    excluded: Vec<String>,
+}
+
+impl Generated {
+    /// Every template in the output with aliases expanded.
+    pub fn keys(&self) -> &[String] {
+        &self.keys
     }
 
-    pub fn list(&self, names: &[String], simple: bool) -> Result<String> {
-        let templates = self.all_names(simple)?;
-        let mut result = if names.is_empty() {
-            templates.into_iter().collect::<Vec<_>>()
-        } else {
-            let mut result = Vec::new();
+    /// Every excluded template that would otherwise have been in the output.
+    pub fn excluded(&self) -> &[String] {
+        &self.excluded
+    }
 
-            for entry in templates {
-                for name in names {
-                    if entry.contains(name) {
-                        result.push(entry.clone());
-                    }
-                }
+    /// Fails if no template with any patterns was resolved, since the output
+    /// would be nothing but our header and footer. `detected` is whether the
+    /// names were autodetected.
+    pub fn ensure_content(&self, detected: bool) -> Result<()> {
+        let has_patterns = self.templates.iter().any(|t| {
+            t.content
+                .lines()
+                .any(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        });
+        if has_patterns {
+            return Ok(());
+        }
+
+        if !self.unresolved.is_empty() {
+            let names: Vec<_> = self.unresolved.iter().map(|u| u.name.as_str()).collect();
+            bail!("Could not find {}, nothing was generated", names.join(", "));
+        } else if !self.templates.is_empty() {
+            bail!("The templates have no patterns, nothing was generated");
+        } else if detected {
+            bail!("No templates were detected in the current directory, nothing was generated");
+        }
+        bail!("No templates were given, nothing was generated")
+    }
+
+    /// Adds a resolved name made up of one or more templates, each wrapped in
+    /// section delimiters in the combined output if `headers` is set. Every
+    /// template is separated from the previous one by a single blank line.
+    fn push(&mut self, key: &str, source: Origin, templates: &[(&str, String)], headers: bool) {
+        let mut content = String::new();
+        for (name, body) in templates {
+            let body_only = trim_body(body);
+            if !body_only.is_empty() {
+                content.push_str(body_only);
+                content.push('\n');
             }
-            result
-        };
+            if !self.keys.iter().any(|k| k == name) {
+                self.keys.push(name.to_string());
+            }
+            let block = if headers {
+                section(name, body)
+            } else {
+                trim_body(body).to_string()
+            };
 
-        result.sort_unstable();
+            if block.is_empty() {
+                continue;
+            }
+            if !self.combined.is_empty() {
+                self.combined.push('\n');
+            }
+            self.combined.push_str(&block);
+            self.combined.push('\n');
+        }
 
-        let result = result.into_iter().fold(String::new(), |mut s, r| {
-            writeln!(s, "  {}", r).unwrap();
-            s
+        self.templates.push(Resolved {
+            key: key.to_string(),
+            source,
+            content,
         });
+    }
+}
 
-        Ok(result)
+/// Formats a single line of `--list` output, optionally followed by the source
+/// the template came from. Plain entries are tab separated without any colors.
+fn list_entry(
+    entry: &Type,
+    origin: Option<&String>,
+    targets: &[String],
+    shadows: bool,
+    plain: bool,
+) -> String {
+    let mut line = match (origin, plain) {
+        (Some(origin), true) => format!("{}\t{}", entry.inner(), origin),
+        (None, true) => entry.inner().to_string(),
+        (Some(origin), false) => format!("  {} ({})", entry, origin.dimmed()),
+        (None, false) => format!("  {}", entry),
+    };
+    if plain {
+        return line;
     }
+    if !targets.is_empty() {
+        let targets = format!("→ {}", shorten_targets(targets, TARGETS_WIDTH));
+        line = format!("{} {}", line, targets.dimmed());
+    }
+    if shadows {
+        line = format!("{} {}", line, SHADOWS.yellow());
+    }
+    line
+}
+
+/// How many characters of the members of an alias are shown in lists.
+const TARGETS_WIDTH: usize = 40;
 
-    /// Creates a formatted string of all the configured templates
-    pub fn get_templates(&self, names: &[String], simple: bool) -> Result<String> {
-        let (aliases, templates) = match &self.config {
-            Some(config) if !simple => (config.aliases.clone(), config.templates.clone()),
-            _ => (HashMap::new(), HashMap::new()),
+/// Joins `targets`, leaving out the ones that don't fit in `width`
+/// characters and ending with an ellipsis if any were.
+fn shorten_targets(targets: &[String], width: usize) -> String {
+    let mut shown = String::new();
+    for (i, target) in targets.iter().enumerate() {
+        let next = if shown.is_empty() {
+            target.clone()
+        } else {
+            format!("{}, {}", shown, target)
+        };
+        // Leave room for the ellipsis unless this is the last one
+        let room = if i + 1 == targets.len() {
+            width
+        } else {
+            width - 3
         };
+        if next.chars().count() > room {
+            return if shown.is_empty() {
+                "…".to_string()
+            } else {
+                format!("{}, …", shown)
+            };
+        }
+        shown = next;
+    }
+    shown
+}
 
-        let ignore_file = self.read_file()?;
-        let mut result = String::new();
+/// Marks aliases and user templates hiding a downloaded template in lists.
+const SHADOWS: &str = "*shadows remote*";
 
-        for name in names {
-            if let Some(val) = templates.get(name) {
-                let template = Config::read_template(val)?;
-                result.push_str(&template);
-            } else if let Some(val) = aliases.get(name) {
-                for alias in val {
-                    if let Some(language) = ignore_file.get(&Type::Alias(alias.to_string())) {
-                        result.push_str(&language.contents);
-                    }
-                }
-            } else if let Some(language) = ignore_file.get(&Type::Normal(name.to_string())) {
-                result.push_str(&language.contents);
+/// Formats `--list --long` output with the name, kind and either the templates
+/// an alias expands to or the display name of a template in aligned columns.
+pub fn long_list(entries: &[ListEntry]) -> String {
+    let key_width = entries.iter().map(|e| e.key.chars().count()).max();
+    let key_width = key_width.unwrap_or_default();
+
+    entries.iter().fold(String::new(), |mut s, entry| {
+        let kind = entry.kind.name();
+        let mut detail = match entry.kind {
+            Origin::Alias => entry.targets.join(", "),
+            _ => entry.name.clone().unwrap_or_default(),
+        };
+        if entry.shadows {
+            detail = format!("{} {}", detail, SHADOWS).trim_start().to_string();
+        }
+
+        let line = format!("{:key_width$}  {:8}  {}", entry.key, kind, detail);
+        writeln!(s, "{}", line.trim_end()).unwrap();
+        s
+    })
+}
+
+/// Whether `entry` is an alias or user template with the same name as one of
+/// the downloaded `templates`.
+fn shadows(templates: &HashMap<Type, Entry>, entry: &Type) -> bool {
+    !matches!(entry, Type::Normal(_))
+        && templates.contains_key(&Type::Normal(entry.inner().to_lowercase()))
+}
+
+/// Copies `map` with its keys lowercased, names are matched ignoring case and
+/// templates are all lowercase, but aliases and user templates keep the case
+/// they were added with.
+fn lowercase_keys<V: Clone>(map: &HashMap<String, V>) -> HashMap<String, V> {
+    map.iter()
+        .map(|(key, val)| (key.to_lowercase(), val.clone()))
+        .collect()
+}
+
+/// Whether `member` of the alias `alias` is another alias. A member named like
+/// the alias it's in is the template the alias shadows.
+fn is_nested_alias(aliases: &HashMap<String, Vec<String>>, alias: &str, member: &str) -> bool {
+    let member = member.to_lowercase();
+    member != alias && aliases.contains_key(&member)
+}
+
+/// Expands the alias at the end of `path` into `resolution`, following
+/// members that are aliases themselves. Aliases on `path` are being expanded,
+/// so finding one of them again is a cycle. `aliases` and the user `templates`
+/// have lowercase keys.
+fn expand_alias(
+    path: &mut Vec<String>,
+    aliases: &HashMap<String, Vec<String>>,
+    templates: &HashMap<String, String>,
+    cached: &impl Fn(&str) -> bool,
+    excluded: &impl Fn(&str) -> bool,
+    resolution: &mut Resolution,
+) -> Result<()> {
+    let alias = path.last().context("No alias to expand")?.clone();
+    let members = aliases.get(&alias).map(Vec::as_slice).unwrap_or_default();
+    debug!("'{}' is an alias for {}", alias, members.join(", "));
+
+    for member in members {
+        let key = member.to_lowercase();
+        if excluded(&key) {
+            if !resolution.excluded.contains(&key) {
+                resolution.excluded.push(key);
+            }
+        } else if is_nested_alias(aliases, &alias, &key) {
+            if let Some(start) = path.iter().position(|seen| *seen == key) {
+                let mut cycle = path[start..].to_vec();
+                cycle.push(key);
+                bail!(
+                    "The alias '{}' includes itself: {}",
+                    cycle[0],
+                    cycle.join(" -> ")
+                );
+            }
+            path.push(key);
+            expand_alias(path, aliases, templates, cached, excluded, resolution)?;
+            path.pop();
+        } else if key != alias && templates.contains_key(&key) {
+            debug!("'{}' is a user template", member);
+            if !resolution.templates.contains(&key) {
+                resolution.templates.push(key.clone());
+                resolution.user_templates.push(key);
+            }
+        } else if cached(&key) {
+            debug!("'{}' found in the cache", member);
+            if !resolution.templates.contains(&key) {
+                resolution.templates.push(key);
+            }
+        } else {
+            debug!("'{}' not found in the cache", member);
+            if !resolution.missing.contains(member) {
+                resolution.missing.push(member.clone());
             }
         }
+    }
+
+    Ok(())
+}
+
+/// Combines the names given on the command line with the detected ones,
+/// keeping the order they were given in and dropping duplicates, ignoring
+/// case, so the generated output is the same every run.
+pub fn requested_names(names: Vec<String>, detected: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    names
+        .into_iter()
+        .chain(detected)
+        .filter(|name| seen.insert(name.to_lowercase()))
+        .collect()
+}
+
+/// The `defaults` that aren't already in `requested`, ignoring case, to add
+/// after them.
+pub fn missing_defaults(requested: &[String], defaults: Vec<String>) -> Vec<String> {
+    let mut seen: HashSet<String> = requested.iter().map(|name| name.to_lowercase()).collect();
+    defaults
+        .into_iter()
+        .filter(|name| seen.insert(name.to_lowercase()))
+        .collect()
+}
+
+/// The last line of a generated `.gitignore`, listing the templates in it.
+fn footer(keys: &[String]) -> String {
+    format!("{} {}", FOOTER_PREFIX, keys.join(","))
+}
+
+/// Reads back the templates a `.gitignore` was generated from, if it has a
+/// footer.
+pub fn parse_footer(contents: &str) -> Option<Vec<String>> {
+    let keys = contents
+        .lines()
+        .rev()
+        .find_map(|line| line.trim_end().strip_prefix(FOOTER_PREFIX))?;
+
+    Some(
+        keys.split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
 
-        if !result.is_empty() {
-            let mut header = "\n\n### Created by https://www.gitignore.io".to_string();
-            header.push_str(&result);
-            result = header;
+/// Removes what we add around generated templates from `contents`: the block
+/// markers, the attribution, the fallback note, the section delimiters and the
+/// footer. Like in `written_templates`, a `### <key> ###` header is only ours
+/// if its section is closed, so the headers of templates are kept. Blank lines
+/// left next to each other are joined and the ones at the start and end
+/// removed.
+pub fn strip_generated(contents: &str) -> String {
+    let lines: Vec<&str> = contents.lines().map(str::trim_end).collect();
+    let mut kept: Vec<&str> = Vec::new();
+    for line in contents.lines() {
+        let trimmed = line.trim_end();
+        let opened = section_start(trimmed)
+            .is_some_and(|key| lines.contains(&format!("### end {} ###", key).as_str()));
+        let generated = [block::BLOCK_START, block::BLOCK_END, ATTRIBUTION].contains(&trimmed)
+            || trimmed.starts_with(FALLBACK_NOTE)
+            || trimmed.starts_with(FOOTER_PREFIX)
+            || (trimmed.starts_with("### end ") && trimmed.ends_with(" ###"))
+            || opened;
+        let repeated = trimmed.is_empty() && kept.last().is_some_and(|l| l.trim().is_empty());
+        if !generated && !repeated {
+            kept.push(line);
         }
+    }
+    let kept = kept.join("\n");
+    let kept = kept.trim_matches(['\r', '\n']);
 
-        Ok(result)
+    if kept.trim().is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", kept)
     }
+}
 
-    pub fn autodetect_templates(&self) -> Result<Vec<String>> {
-        let entries: Vec<DirEntry> = read_dir(current_dir()?)?.map(Result::unwrap).collect();
-        Ok(self.detectors.detects(entries.as_slice()))
+/// Reads the `.gitignore` at `path` for `template add --from-gitignore`,
+/// without what we added when generating it.
+pub fn template_from_gitignore(path: &Path) -> Result<TemplateContents> {
+    if !path.exists() {
+        bail!(
+            "There is no '{}' to create the template from",
+            path.display()
+        );
+    }
+    let mut template = TemplateContents::read(path)?;
+    if template.contents.trim().is_empty() {
+        bail!(
+            "'{}' is empty, there's nothing to create the template from",
+            path.display()
+        );
+    }
+    template.contents = strip_generated(&template.contents);
+    if template.contents.is_empty() {
+        bail!(
+            "'{}' only has what git ignore generated, there's nothing to create the template from",
+            path.display()
+        );
     }
 
-    fn all_names(&self, simple: bool) -> Result<HashSet<Type>> {
-        let templates = self.read_file()?;
+    Ok(template)
+}
 
-        if simple {
-            return Ok(templates.keys().cloned().collect());
-        }
+/// Strips comments and repeated blank lines from a `.gitignore`, keeping only
+/// the attribution line and footer. Escaped `\#` patterns and `!` negations are
+/// kept since only lines starting with `#` are comments.
+fn compact(contents: &str) -> String {
+    let mut result = String::new();
+    let mut blank = false;
+    let mut attribution = false;
 
-        let config_names = match &self.config {
-            Some(config) => config.names(),
-            _ => vec![],
-        };
+    for line in contents.lines() {
+        if line.trim().is_empty() {
+            blank = !result.is_empty();
+            continue;
+        }
 
-        let mut combined: HashSet<Type> = config_names.into_iter().collect();
-        combined.extend(templates.keys().cloned());
+        if line.starts_with('#') && !line.starts_with(FOOTER_PREFIX) {
+            if attribution || line != ATTRIBUTION {
+                continue;
+            }
+            attribution = true;
+        }
 
-        Ok(combined)
+        if blank {
+            result.push('\n');
+            blank = false;
+        }
+        result.push_str(line);
+        result.push('\n');
     }
 
-    /// Fetches all the templates from [gitignore.io](http://gitignore.io/),
-    /// and writes the contents to the cache for easy future retrieval.
-    fn fetch_gitignore(&self) -> Result<()> {
-        let res = attohttpc::get(&self.server).send()?;
+    result
+}
 
-        let mut file = File::create(&self.ignore_file)?;
-        file.write_all(&res.bytes()?)?;
+/// Strips leading blank lines and trailing whitespace from a template, an
+/// escaped trailing space is part of the last pattern and is kept.
+fn trim_body(body: &str) -> &str {
+    let start = body.len() - body.trim_start_matches(['\n', '\r']).len();
+    let trimmed = body.trim_end();
+    let end = if trimmed.ends_with('\\') && body[trimmed.len()..].starts_with(' ') {
+        trimmed.len() + 1
+    } else {
+        trimmed.len()
+    };
 
-        Ok(())
-    }
+    body.get(start..end.max(start)).unwrap_or_default()
+}
 
-    /// Returns true if the cache directory or `ignore.json` file exists, false
-    /// otherwise.
-    pub fn cache_exists(&self) -> bool {
-        self.cache_dir.exists() || self.ignore_file.exists()
+/// Converts `contents` to CRLF or LF line endings, lines that already end in
+/// CRLF are left as is instead of getting another carriage return.
+pub fn convert_line_endings(contents: &str, crlf: bool) -> String {
+    let contents = contents.replace("\r\n", "\n");
+    if crlf {
+        contents.replace('\n', "\r\n")
+    } else {
+        contents
     }
+}
 
-    /// Creates the cache dir if it doesn't exist.
-    fn create_dirs(&self) -> std::io::Result<()> {
-        if !self.cache_exists() {
-            std::fs::create_dir_all(&self.cache_dir)?;
-        }
+/// What happened when writing the generated output to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Written {
+    Created,
+    Appended,
+    Overwritten,
+    /// The generated block in the file was replaced
+    Replaced,
+    /// This many missing patterns were appended by `--merge`
+    Merged(usize),
+    /// This many patterns were removed by `--remove`
+    Removed(usize),
+    /// The file already had every pattern, nothing was written
+    Unchanged,
+}
 
-        Ok(())
+/// What to do when the file we're writing to already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Existing {
+    /// Fail without touching it
+    Refuse,
+    Append,
+    /// Truncate it and write only the generated output
+    Overwrite,
+}
+
+/// Where `--output` writes to, relative paths are relative to `cwd` and `-`
+/// means stdout.
+pub fn output_path(cwd: &Path, output: &Path) -> Option<PathBuf> {
+    (output != Path::new("-")).then(|| cwd.join(output))
+}
+
+/// Finds the root of the repository `cwd` is in by looking for a `.git`
+/// directory, or the `.git` file of a worktree or submodule, in it and its
+/// parents. When run by git with `GIT_DIR` set, its parent is the root.
+pub fn repo_root(cwd: &Path, git_dir: Option<&Path>) -> Option<PathBuf> {
+    if let Some(git_dir) = git_dir {
+        let git_dir = cwd.join(git_dir);
+        if git_dir.file_name().is_some_and(|name| name == ".git") {
+            return git_dir.parent().map(Path::to_path_buf);
+        }
     }
 
-    /// Reads the `ignore.json` and serializes it using Serde to a `HashMap` where
-    /// the keys are each individual template and the value the contents (and
-    /// some other stuff).
-    fn read_file(&self) -> Result<HashMap<Type, Language>> {
-        let file = Path::new(&self.ignore_file);
-        let file = read_to_string(file)?;
+    cwd.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
 
-        let result: HashMap<String, Language> = serde_json::from_str(&file)?;
-        let result: HashMap<Type, Language> = result
-            .into_iter()
-            .map(|(k, v)| (Type::Normal(k), v))
-            .collect();
+/// The `info/exclude` file of the repository at `root`, creating `info` if
+/// needed. In a worktree `.git` is a file pointing to its git directory, whose
+/// `commondir` points to the one shared with the main worktree that has the
+/// exclude file.
+pub fn exclude_file(root: &Path) -> Result<PathBuf> {
+    let dot_git = root.join(".git");
+    let git_dir = if dot_git.is_file() {
+        let contents = read_to_string(&dot_git)
+            .with_context(|| format!("Could not read '{}'", dot_git.display()))?;
+        let target = contents
+            .lines()
+            .find_map(|line| line.strip_prefix("gitdir:"))
+            .with_context(|| {
+                format!("'{}' does not point to a git directory", dot_git.display())
+            })?;
+        root.join(target.trim())
+    } else {
+        dot_git
+    };
 
-        Ok(result)
+    let common_dir = match read_to_string(git_dir.join("commondir")) {
+        Ok(common) => git_dir.join(common.trim()),
+        Err(_) => git_dir,
+    };
+
+    let info = common_dir.join("info");
+    std::fs::create_dir_all(&info).map_err(|e| file_error(e, "create", &info))?;
+    Ok(info.join("exclude"))
+}
+
+/// The global excludes file git reads, `core.excludesFile` if it's set and
+/// `$XDG_CONFIG_HOME/git/ignore` otherwise. Its directory is created if it
+/// doesn't exist.
+pub fn global_excludes_file() -> Result<PathBuf> {
+    let configured = git_config("core.excludesFile").unwrap_or_else(|e| {
+        warning!("Could not read core.excludesFile, using the default: {}", e);
+        None
+    });
+    let home = etcetera::home_dir().ok();
+    let path = excludes_path(
+        configured.as_deref(),
+        std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+        home.as_deref(),
+    )
+    .context("Could not find your home directory")?;
+    debug!("The global excludes file is '{}'", path.display());
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| file_error(e, "create", parent))?;
+    }
+
+    Ok(path)
+}
+
+/// Reads `key` from the git config, `None` if it's not set.
+fn git_config(key: &str) -> Result<Option<String>> {
+    let output = std::process::Command::new("git")
+        .args(["config", "--get", key])
+        .output()
+        .context("Could not run git")?;
+
+    match output.status.code() {
+        Some(0) => Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        )),
+        // Git exits with 1 when the key isn't set
+        Some(1) => Ok(None),
+        _ => bail!("{}", String::from_utf8_lossy(&output.stderr).trim()),
+    }
+}
+
+/// Where git looks for global excludes, see `core.excludesFile` in
+/// git-config(1). A leading `~/` in the configured path is the home directory.
+fn excludes_path(
+    configured: Option<&str>,
+    xdg_config_home: Option<PathBuf>,
+    home: Option<&Path>,
+) -> Option<PathBuf> {
+    match configured.filter(|path| !path.is_empty()) {
+        Some(path) => match path.strip_prefix("~/") {
+            Some(rest) => home.map(|home| home.join(rest)),
+            None => Some(PathBuf::from(path)),
+        },
+        None => xdg_config_home
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| home.map(|home| home.join(".config")))
+            .map(|config| config.join("git").join("ignore")),
+    }
+}
+
+/// What writing to a file will do, worked out before touching it so
+/// `--dry-run` can show it.
+#[derive(Debug)]
+pub struct Change {
+    pub written: Written,
+    /// The file before and after, `before` is empty if it doesn't exist
+    pub before: String,
+    pub after: String,
+    /// What is appended to the file, `None` if it's written from scratch
+    appended: Option<String>,
+}
+
+impl Change {
+    fn appending(written: Written, before: String, appended: String) -> Self {
+        let mut after = before.clone();
+        if !after.is_empty() && !after.ends_with('\n') {
+            after.push('\n');
+        }
+        after.push_str(&appended);
+
+        Change {
+            written,
+            before,
+            after,
+            appended: Some(appended),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.before == self.after
+    }
+}
+
+/// Reads the file we're about to write to, `None` if it doesn't exist.
+fn read_existing(path: &Path) -> Result<Option<Vec<u8>>> {
+    if path.is_dir() {
+        let err = std::io::Error::from(ErrorKind::IsADirectory);
+        return Err(file_error(err, "write to", path));
+    }
+
+    match std::fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(file_error(e, "read", path)),
+    }
+}
+
+/// Adds what we couldn't do to which file to `err`, e.g. "Could not write to
+/// '.gitignore'", with a hint on what to do about it for the usual reasons.
+fn file_error(err: impl Into<anyhow::Error>, action: &str, path: &Path) -> anyhow::Error {
+    let err = err.into();
+    let kind = err
+        .chain()
+        .find_map(|e| e.downcast_ref::<std::io::Error>())
+        .map(std::io::Error::kind);
+    let hint = match kind {
+        Some(ErrorKind::PermissionDenied) => {
+            Some("permission denied, pass '--output' to write somewhere else")
+        }
+        Some(ErrorKind::ReadOnlyFilesystem) => Some(
+            "the file system is read-only, check how it's mounted or pass '--output' to write somewhere else",
+        ),
+        Some(ErrorKind::IsADirectory) => {
+            Some("it is a directory, pass '--output' to write to a file instead")
+        }
+        _ => None,
+    };
+
+    let message = format!("Could not {} '{}'", action, path.display());
+    match hint {
+        Some(hint) => err.context(format!("{}, {}", message, hint)),
+        None => err.context(message),
+    }
+}
+
+/// Works out what writing the generated output to `path` does, `existing`
+/// decides what happens if it's already there. When `managed` the output is
+/// wrapped in block markers and unless overwriting, an existing block in the
+/// file is replaced instead, leaving the rest of it alone.
+pub fn plan_write(
+    path: &Path,
+    generated: &str,
+    existing: Existing,
+    managed: bool,
+) -> Result<Change> {
+    let contents = if managed {
+        block::wrap(generated)
+    } else {
+        generated.to_string()
+    };
+
+    let Some(bytes) = read_existing(path)? else {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            if !parent.is_dir() {
+                bail!(
+                    "Could not write to '{}', the directory '{}' does not exist",
+                    path.display(),
+                    parent.display()
+                );
+            }
+        }
+
+        return Ok(Change {
+            written: Written::Created,
+            before: String::new(),
+            after: contents,
+            appended: None,
+        });
+    };
+    let before = String::from_utf8_lossy(&bytes).into_owned();
+
+    if managed && existing != Existing::Overwrite {
+        let text = std::str::from_utf8(&bytes)
+            .with_context(|| format!("Could not read '{}'", path.display()))?;
+        let replaced = block::replace(text, generated)
+            .with_context(|| format!("Could not update '{}'", path.display()))?;
+        if let Some(replaced) = replaced {
+            return Ok(Change {
+                written: Written::Replaced,
+                before,
+                after: replaced,
+                appended: None,
+            });
+        }
+    }
+
+    match existing {
+        Existing::Refuse => bail!(
+            "'{}' already exists, use '--append' or '--overwrite' to write to it",
+            path.display()
+        ),
+        Existing::Append => Ok(Change::appending(Written::Appended, before, contents)),
+        Existing::Overwrite => Ok(Change {
+            written: Written::Overwritten,
+            before,
+            after: contents,
+            appended: None,
+        }),
+    }
+}
+
+/// Works out which of the patterns in `contents` have to be appended to
+/// `path`, all of them if it doesn't exist.
+pub fn plan_merge(path: &Path, contents: &str) -> Result<Change> {
+    let Some(bytes) = read_existing(path)? else {
+        return plan_write(path, contents, Existing::Refuse, false);
+    };
+    let before = String::from_utf8_lossy(&bytes).into_owned();
+
+    match missing_patterns(&before, contents) {
+        (_, 0) => Ok(Change {
+            written: Written::Unchanged,
+            after: before.clone(),
+            before,
+            appended: None,
+        }),
+        (missing, count) => Ok(Change::appending(Written::Merged(count), before, missing)),
+    }
+}
+
+/// Works out what removing the patterns of the templates in `keys`, whose
+/// combined output is `generated`, from `path` does.
+pub fn plan_remove(path: &Path, keys: &[String], generated: &str) -> Result<Change> {
+    let Some(bytes) = read_existing(path)? else {
+        bail!(
+            "'{}' does not exist, there is nothing to remove",
+            path.display()
+        );
+    };
+    let text = std::str::from_utf8(&bytes)
+        .with_context(|| format!("Could not read '{}'", path.display()))?;
+
+    let (after, count) = remove_patterns(text, keys, generated);
+    Ok(Change {
+        written: if count == 0 {
+            Written::Unchanged
+        } else {
+            Written::Removed(count)
+        },
+        before: text.to_string(),
+        after,
+        appended: None,
+    })
+}
+
+/// The key of a `### <key> ###` section delimiter, `None` for any other line
+/// including the `### end <key> ###` closing one.
+fn section_start(line: &str) -> Option<&str> {
+    let key = line.strip_prefix("### ")?.strip_suffix(" ###")?;
+    (!key.starts_with("end ")).then_some(key)
+}
+
+/// The templates already written to `contents`, lowercased. Found from their
+/// section delimiters and the footers listing them, so templates written
+/// with `--no-section-headers` or `--compact` are found too.
+pub fn written_templates(contents: &str) -> HashSet<String> {
+    let lines: Vec<&str> = contents.lines().map(str::trim_end).collect();
+    let mut written = HashSet::new();
+
+    for line in &lines {
+        if let Some(keys) = line.strip_prefix(FOOTER_PREFIX) {
+            let keys = keys.split(',').map(str::trim).filter(|k| !k.is_empty());
+            written.extend(keys.map(str::to_lowercase));
+        } else if let Some(key) = section_start(line) {
+            // Templates have their own `### Name ###` headers, only count
+            // the ones we closed
+            let end = format!("### end {} ###", key);
+            if lines.contains(&end.as_str()) {
+                written.insert(key.to_lowercase());
+            }
+        }
+    }
+
+    written
+}
+
+/// Removes the patterns in `generated` from the sections of `keys` in
+/// `existing`, and the sections themselves once they have no patterns left.
+/// Patterns outside of any section are only removed if none of the templates
+/// have one, i.e. they were added with `--no-section-headers`. A pattern that
+/// is also in the section of a template we keep is never removed. Returns the
+/// new contents and how many patterns were removed.
+fn remove_patterns(existing: &str, keys: &[String], generated: &str) -> (String, usize) {
+    fn is_pattern(line: &str) -> bool {
+        !line.is_empty() && !line.starts_with('#')
+    }
+    let patterns: HashSet<&str> = generated
+        .lines()
+        .map(str::trim_end)
+        .filter(|l| is_pattern(l))
+        .collect();
+    let removed = |key: &str| keys.iter().any(|k| k.eq_ignore_ascii_case(key));
+
+    let lines: Vec<&str> = existing.split_inclusive('\n').collect();
+    let mut sections = Vec::with_capacity(lines.len());
+    let mut current = None;
+    for line in &lines {
+        let line = line.trim_end();
+        if let Some(key) = section_start(line) {
+            current = Some(key);
+        }
+        sections.push(current);
+        if line.starts_with("### end ") {
+            current = None;
+        }
+    }
+
+    let kept: HashSet<&str> = lines
+        .iter()
+        .zip(&sections)
+        .filter(|(_, section)| section.is_some_and(|key| !removed(key)))
+        .map(|(line, _)| line.trim_end())
+        .collect();
+    let has_sections = sections.iter().flatten().any(|key| removed(key));
+
+    let mut remove: Vec<bool> = lines
+        .iter()
+        .zip(&sections)
+        .map(|(line, section)| {
+            let line = line.trim_end();
+            let owned = match section {
+                Some(key) => removed(key),
+                None => !has_sections,
+            };
+            owned && is_pattern(line) && patterns.contains(line) && !kept.contains(line)
+        })
+        .collect();
+    let count = remove.iter().filter(|r| **r).count();
+
+    // Drop sections that are left without patterns, with the blank line
+    // separating them from the next one
+    let mut start = 0;
+    while start < lines.len() {
+        let Some(key) = sections[start].filter(|key| removed(key)) else {
+            start += 1;
+            continue;
+        };
+        let end = (start..lines.len())
+            .take_while(|&i| sections[i] == Some(key))
+            .last()
+            .unwrap_or(start);
+        let empty = (start..=end).all(|i| remove[i] || !is_pattern(lines[i].trim_end()));
+        if empty {
+            let blank = end + 1 < lines.len() && lines[end + 1].trim().is_empty();
+            remove[start..=end + usize::from(blank)].fill(true);
+        }
+        start = end + 1;
+    }
+
+    let mut after = String::with_capacity(existing.len());
+    for (line, remove) in lines.iter().zip(remove) {
+        if remove {
+            continue;
+        }
+        match line.trim_end().strip_prefix(FOOTER_PREFIX) {
+            Some(footer_keys) if count > 0 => {
+                let left: Vec<_> = footer_keys
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty() && !removed(key))
+                    .map(ToString::to_string)
+                    .collect();
+                if !left.is_empty() {
+                    after.push_str(&footer(&left));
+                    after.push_str(if line.ends_with("\r\n") { "\r\n" } else { "\n" });
+                }
+            }
+            _ => after.push_str(line),
+        }
+    }
+
+    // Don't leave the blank lines before what was removed at the end
+    let end = after.trim_end_matches(['\r', '\n']).len();
+    if count > 0 && end > 0 && end < after.len() {
+        after.truncate(end);
+        after.push_str(if existing.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        });
+    }
+
+    (after, count)
+}
+
+/// Makes a planned change to `path`, copying it first if `backup` is set.
+pub fn apply(path: &Path, change: &Change, backup: bool) -> Result<Written> {
+    match (change.written, &change.appended) {
+        (Written::Unchanged, _) => {}
+        (Written::Created, _) => {
+            std::fs::write(path, &change.after).map_err(|e| file_error(e, "create", path))?
+        }
+        (_, appended) => {
+            if backup {
+                backup_file(path)?;
+            }
+            match appended {
+                Some(appended) => {
+                    append_to(path, appended).map_err(|e| file_error(e, "append to", path))?
+                }
+                None => {
+                    overwrite(path, &change.after).map_err(|e| file_error(e, "write to", path))?
+                }
+            }
+        }
+    }
+
+    Ok(change.written)
+}
+
+/// Copies `path` to `<path>.bak` before it's modified, or to a timestamped
+/// `<path>.<YYYYMMDDHHMMSS>.bak` if that's taken so an older backup is never
+/// overwritten.
+pub fn backup_file(path: &Path) -> Result<PathBuf> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stamp: String = format_time(SystemTime::now())
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect();
+    let candidates = std::iter::once(format!("{}.bak", name))
+        .chain(std::iter::once(format!("{}.{}.bak", name, stamp)))
+        .chain((1..).map(|n| format!("{}.{}-{}.bak", name, stamp, n)));
+
+    let contents =
+        std::fs::read(path).with_context(|| format!("Could not read '{}'", path.display()))?;
+    let action = format!("back up '{}' to", path.display());
+    for candidate in candidates {
+        let backup = path.with_file_name(candidate);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup);
+        match file {
+            Ok(mut file) => {
+                file.write_all(&contents)
+                    .map_err(|e| file_error(e, &action, &backup))?;
+                info!("Backed up '{}' to '{}'", path.display(), backup.display());
+                return Ok(backup);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(file_error(e, &action, &backup)),
+        }
+    }
+
+    unreachable!("there are always more backup names to try")
+}
+
+/// Replaces `path` with `contents` by writing them to a file next to it and
+/// renaming that over it, so it's never left half written.
+pub fn replace_atomically(path: &Path, contents: &str) -> Result<()> {
+    // Replace what a symlink points to rather than the link itself
+    let path = std::fs::canonicalize(path)
+        .with_context(|| format!("Could not read '{}'", path.display()))?;
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let tmp = path.with_file_name(name);
+
+    let result = (|| -> Result<()> {
+        std::fs::write(&tmp, contents)?;
+        std::fs::set_permissions(&tmp, std::fs::metadata(&path)?.permissions())?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+
+    result.map_err(|e| file_error(e, "write to", &path))
+}
+
+/// Replaces the contents of an existing file with the generated output.
+fn overwrite(path: &Path, contents: &str) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).truncate(true).open(path)?;
+    file.write_all(contents.as_bytes())?;
+
+    Ok(())
+}
+
+/// Appends generated output to an existing `.gitignore`, adding a newline
+/// first if the file doesn't end with one so no patterns are glued together.
+pub fn append_to(path: &Path, contents: &str) -> Result<()> {
+    let existing = std::fs::read(path)?;
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    if existing.last().is_some_and(|c| *c != b'\n') {
+        file.write_all(b"\n")?;
+    }
+    file.write_all(contents.as_bytes())?;
+
+    Ok(())
+}
+
+/// The pattern lines in `generated` that aren't in `existing` and how many
+/// there are. Section headers and comments are kept for the patterns that are
+/// added, the footer is not since it would describe templates that are only
+/// partially added.
+fn missing_patterns(existing: &str, generated: &str) -> (String, usize) {
+    // Trailing whitespace is insignificant unless escaped, but a negation has
+    // to match exactly or we could drop one that re-includes a file
+    fn pattern(line: &str) -> &str {
+        if line.starts_with('!') {
+            line
+        } else {
+            line.trim_end()
+        }
+    }
+    fn is_header(line: &str) -> bool {
+        line.starts_with("### ") && line.ends_with(" ###") && !line.starts_with("### end ")
+    }
+
+    let mut seen: HashSet<&str> = existing
+        .lines()
+        .map(pattern)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect();
+
+    let mut kept: Vec<&str> = Vec::new();
+    let mut header = None;
+    let mut pending = Vec::new();
+    let mut count = 0;
+    for line in generated.lines() {
+        let trimmed = line.trim_end();
+        if is_header(trimmed) {
+            header = Some(line);
+            pending.clear();
+        } else if trimmed.starts_with("### end ") {
+            // Only close sections we added patterns to
+            if header.is_none() {
+                kept.push(line);
+            }
+            header = None;
+            pending.clear();
+        } else if trimmed.starts_with(FOOTER_PREFIX) {
+            pending.clear();
+        } else if trimmed.is_empty() || trimmed.starts_with('#') {
+            pending.push(line);
+        } else if seen.insert(pattern(line)) {
+            if let Some(header) = header.take() {
+                kept.extend(["", header]);
+                let blank = pending.iter().take_while(|l| l.trim().is_empty()).count();
+                pending.drain(..blank);
+            }
+            kept.append(&mut pending);
+            kept.push(line);
+            count += 1;
+        } else {
+            pending.clear();
+        }
+    }
+
+    let newline = if generated.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut missing = String::new();
+    for (i, line) in kept.iter().enumerate() {
+        let blank = line.trim().is_empty();
+        if blank && (missing.is_empty() || kept.get(i + 1).is_none_or(|l| l.trim().is_empty())) {
+            continue;
+        }
+        missing.push_str(line);
+        missing.push_str(newline);
+    }
+
+    (missing, count)
+}
+
+/// Wraps a template in `### <key> ###` and `### end <key> ###` comments. The
+/// `### Name ###` header templates usually start with is replaced by ours.
+fn section(key: &str, body: &str) -> String {
+    let header = format!("### {} ###", key);
+    let body = trim_body(body);
+    let body = match body.split_once('\n') {
+        Some((first, rest)) if first.trim().eq_ignore_ascii_case(&header) => rest,
+        None if body.trim().eq_ignore_ascii_case(&header) => "",
+        _ => body,
+    };
+
+    let body = trim_body(body);
+    if body.is_empty() {
+        format!("{}\n### end {} ###", header, key)
+    } else {
+        format!("{}\n{}\n### end {} ###", header, body, key)
+    }
+}
+
+/// Where templates are downloaded from.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TemplateSource {
+    /// The JSON API of [gitignore.io](https://www.gitignore.io)
+    #[default]
+    GitignoreIo,
+    /// A tarball of the [github/gitignore](https://github.com/github/gitignore) repository
+    Github,
+}
+
+impl TemplateSource {
+    fn default_url(self) -> &'static str {
+        match self {
+            TemplateSource::GitignoreIo => DEFAULT_SERVER,
+            TemplateSource::Github => GITHUB_TARBALL,
+        }
+    }
+}
+
+impl Display for TemplateSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateSource::GitignoreIo => write!(f, "gitignore.io"),
+            TemplateSource::Github => write!(f, "github/gitignore"),
+        }
+    }
+}
+
+/// A single place to download templates from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub kind: TemplateSource,
+    pub url: String,
+}
+
+impl Source {
+    /// Creates a source, making sure the URL is usable before we try to fetch
+    /// anything from it.
+    fn new(kind: TemplateSource, url: &str) -> Result<Self> {
+        let parsed = Url::parse(url).with_context(|| format!("Invalid server URL '{}'", url))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            bail!("Invalid server URL '{}', must be http or https", url);
+        }
+
+        Ok(Source {
+            kind,
+            url: url.to_string(),
+        })
+    }
+
+    /// Parses an entry in the `sources` list in the config, which is either the
+    /// name of a built-in source or the URL of a server using the same format
+    /// as gitignore.io.
+    fn parse(entry: &str) -> Result<Self> {
+        match entry {
+            "gitignore-io" => Ok(TemplateSource::GitignoreIo.into()),
+            "github" => Ok(TemplateSource::Github.into()),
+            url => Source::new(TemplateSource::GitignoreIo, url),
+        }
+    }
+}
+
+impl From<TemplateSource> for Source {
+    fn from(kind: TemplateSource) -> Self {
+        Source {
+            kind,
+            url: kind.default_url().to_string(),
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.url == self.kind.default_url() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{}", self.url)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Type {
+    Normal(String),
+    Alias(String),
+    Template(String),
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Normal(name) => write(f, format_args!("{}", name)),
+            Type::Alias(name) => write(f, format_args!("{}", name.yellow())),
+            Type::Template(name) => write(f, format_args!("{}", name.blue())),
+        }
+    }
+}
+
+impl PartialEq for Type {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner() == other.inner()
+    }
+}
+
+impl Eq for Type {}
+
+impl PartialOrd for Type {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Type {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.inner().cmp(other.inner())
+    }
+}
+
+impl Hash for Type {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner().hash(state);
+    }
+}
+
+impl Type {
+    fn inner(&self) -> &str {
+        match self {
+            Type::Normal(name) | Type::Alias(name) | Type::Template(name) => name,
+        }
+    }
+
+    /// What the name resolves to when generating.
+    fn origin(&self) -> Origin {
+        match self {
+            Type::Normal(_) => Origin::Template,
+            Type::Alias(_) => Origin::Alias,
+            Type::Template(_) => Origin::User,
+        }
+    }
+}
+
+/// How the names given to `--list` are matched against templates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Matching {
+    /// The name is part of the template's name, or of the display name of a
+    /// downloaded template ignoring case
+    #[default]
+    Substring,
+    /// The name is the template's name, ignoring case
+    Exact,
+    /// The template's name or display name starts with the name, ignoring case
+    Prefix,
+    /// The name is a regular expression matching the template's name or
+    /// display name, case matters unless it starts with `(?i)`
+    Regex,
+}
+
+impl Matching {
+    /// Whether `name` matches the template `key`, or `regex` when matching
+    /// regular expressions, which is `name` as compiled by `compile`.
+    fn matches(
+        self,
+        key: &str,
+        display_name: Option<&str>,
+        name: &str,
+        regex: Option<&Regex>,
+    ) -> bool {
+        if let Some(regex) = regex {
+            return regex.is_match(key) || display_name.is_some_and(|d| regex.is_match(d));
+        }
+
+        let name = &name.to_lowercase();
+        let display_name = display_name.map(str::to_lowercase);
+        match self {
+            Matching::Substring => {
+                key.to_lowercase().contains(name.as_str())
+                    || display_name.is_some_and(|d| d.contains(name))
+            }
+            Matching::Exact => key.to_lowercase() == *name,
+            Matching::Prefix => {
+                key.to_lowercase().starts_with(name)
+                    || display_name.is_some_and(|d| d.starts_with(name))
+            }
+            // Patterns are compiled before anything is matched
+            Matching::Regex => false,
+        }
+    }
+
+    /// Compiles every name when matching regular expressions, so it's only
+    /// done once, reporting each invalid one. Nothing needs compiling for the
+    /// other kinds of matching.
+    fn compile(self, names: &[String]) -> Result<Vec<Regex>> {
+        if self != Matching::Regex {
+            return Ok(Vec::new());
+        }
+
+        let mut patterns = Vec::new();
+        let mut errors = Vec::new();
+        for name in names {
+            match compile_regex(name) {
+                Ok(regex) => patterns.push(regex),
+                Err(err) => errors.push(err.to_string()),
+            }
+        }
+        if !errors.is_empty() {
+            bail!(errors.join("\n"));
+        }
+
+        Ok(patterns)
+    }
+}
+
+/// Compiles `pattern` as a regular expression. When it's invalid the error
+/// names the pattern and where in it the problem is, instead of the multi-line
+/// report the `regex` crate gives.
+fn compile_regex(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|err| {
+        let reason = match regex_syntax::Parser::new().parse(pattern) {
+            Err(regex_syntax::Error::Parse(err)) => {
+                format!("{} at position {}", err.kind(), err.span().start.column)
+            }
+            Err(regex_syntax::Error::Translate(err)) => {
+                format!("{} at position {}", err.kind(), err.span().start.column)
+            }
+            _ => err.to_string(),
+        };
+        anyhow!("Invalid pattern '{}': {}", pattern, reason)
+    })
+}
+
+impl Core {
+    /// Creates a new instance of the `git-ignore` program. Thanks to
+    /// `directories` we support crossplatform caching of our results, the cache
+    /// directories works on macOS, Linux and Windows. See the documentation for
+    /// their locations.
+    ///
+    /// The template server is taken from `$GIT_IGNORE_SERVER`, then the config
+    /// and finally falls back to the default for the configured source. The
+    /// cache directory is `cache_dir` if given, then `$GIT_IGNORE_CACHE_DIR`,
+    /// and the config is read from `config_file` or `$GIT_IGNORE_CONFIG`.
+    pub fn new(cache_dir: Option<PathBuf>, config_file: Option<PathBuf>) -> Result<Self> {
+        let config_file = resolve_config_file(config_file, std::env::var_os(CONFIG_ENV));
+        let config = Config::from_dir(config_file.as_deref());
+        let cache_dir = Core::resolve_cache_dir(cache_dir, std::env::var_os(CACHE_DIR_ENV));
+        let (sources, sources_error) =
+            match Core::resolve_sources(std::env::var(SERVER_ENV).ok(), config.as_ref()) {
+                Ok(sources) => (sources, None),
+                Err(err) => (Vec::new(), Some(format!("{:#}", err))),
+            };
+
+        let timeout = |secs: Option<u64>, default| Duration::from_secs(secs.unwrap_or(default));
+        let connect_timeout = timeout(
+            config.as_ref().and_then(|c| c.connect_timeout_secs),
+            DEFAULT_CONNECT_TIMEOUT_SECS,
+        );
+        let read_timeout = timeout(
+            config.as_ref().and_then(|c| c.read_timeout_secs),
+            DEFAULT_READ_TIMEOUT_SECS,
+        );
+
+        let proxies = Proxies::new(
+            |name| std::env::var(name).ok(),
+            config.as_ref().and_then(|c| c.proxy.as_deref()),
+        )?;
+
+        let max_download = config
+            .as_ref()
+            .and_then(|c| c.max_download_mb)
+            .unwrap_or(DEFAULT_MAX_DOWNLOAD_MB)
+            * 1024
+            * 1024;
+
+        let mut core = Core::with_cache_dir(cache_dir, config);
+        core.config_file = config_file;
+        core.sources = sources;
+        core.sources_error = sources_error;
+        core.proxies = proxies;
+        core.connect_timeout = connect_timeout;
+        core.read_timeout = read_timeout;
+        core.max_download = max_download;
+        Ok(core)
+    }
+
+    fn with_cache_dir(cache_dir: PathBuf, config: Option<Config>) -> Self {
+        let index_file = cache_dir.join("index.json");
+        let templates_dir = cache_dir.join("templates");
+        let ignore_file = cache_dir.join("ignore.json");
+        let compressed_file = cache_dir.join("ignore.json.gz");
+        let meta_file = cache_dir.join("ignore.meta.json");
+
+        Core {
+            sources: vec![TemplateSource::default().into()],
+            sources_error: None,
+            proxies: Proxies::default(),
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+            read_timeout: Duration::from_secs(DEFAULT_READ_TIMEOUT_SECS),
+            retry_backoff: Duration::from_secs(1),
+            max_download: DEFAULT_MAX_DOWNLOAD_MB * 1024 * 1024,
+            cache_dir,
+            index_file,
+            templates_dir,
+            ignore_file,
+            compressed_file,
+            meta_file,
+            detectors: Detectors::default(),
+            snapshot: OnceLock::new(),
+            config_file: None,
+            config,
+            offline: false,
+            verbose: false,
+            section_headers: true,
+            compact: false,
+            footer: true,
+            exclude: Vec::new(),
+        }
+    }
+
+    /// Picks where to cache templates. A directory passed on the command line
+    /// wins over one in the environment, falling back to the platform's cache
+    /// directory.
+    fn resolve_cache_dir(flag: Option<PathBuf>, env: Option<OsString>) -> PathBuf {
+        flag.or_else(|| env.filter(|dir| !dir.is_empty()).map(PathBuf::from))
+            .unwrap_or_else(|| project_dirs().cache_dir())
+    }
+
+    /// Picks where to fetch templates from. A server in the environment wins
+    /// over a list of `sources` in the config, which in turn wins over a single
+    /// `server`.
+    fn resolve_sources(env: Option<String>, config: Option<&Config>) -> Result<Vec<Source>> {
+        let kind = config.and_then(|c| c.source).unwrap_or_default();
+
+        if let Some(server) = env.filter(|s| !s.is_empty()) {
+            return Ok(vec![Source::new(kind, &server)?]);
+        }
+
+        match config {
+            Some(config) if !config.sources.is_empty() => {
+                config.sources.iter().map(|s| Source::parse(s)).collect()
+            }
+            Some(Config {
+                server: Some(server),
+                ..
+            }) => Ok(vec![Source::new(kind, server)?]),
+            _ => Ok(vec![kind.into()]),
+        }
+    }
+
+    /// Returns the sources templates are fetched from, in order of increasing
+    /// precedence.
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    /// Fails with why the sources are invalid, e.g. a malformed
+    /// `$GIT_IGNORE_SERVER`, before anything is fetched from them.
+    pub fn check_sources(&self) -> Result<()> {
+        match &self.sources_error {
+            Some(err) => bail!("{}", err),
+            None => Ok(()),
+        }
+    }
+
+    /// Both updates and initializes `git-ignore`. Creates the cache directory
+    /// if it doesn't exist and then downloads the templates from the configured
+    /// source, saving them in the cache directory.
+    pub fn update(&self) -> Result<()> {
+        if self.offline {
+            bail!("Cannot update templates while offline");
+        }
+        self.check_sources()?;
+
+        self.create_dirs()?;
+
+        let previous = self.cache_updated();
+        let (_lock, waited) = self.lock_update()?;
+        if waited && self.cache_exists() && self.cache_updated() != previous {
+            info!("Using the templates just downloaded by another process");
+            return Ok(());
+        }
+
+        let sources = self
+            .sources
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let start = Instant::now();
+        if let Some(size) = self.fetch_gitignore()? {
+            info!(
+                "Update from {} successful ({} in {:.1}s)",
+                sources,
+                format_size(size),
+                start.elapsed().as_secs_f32()
+            );
+        } else {
+            info!("Templates from {} already up to date", sources);
+        }
+
+        Ok(())
+    }
+
+    /// Lists the templates matching any of `names`, or all of them. `plain`
+    /// prints bare names without indentation or colors for use in scripts.
+    pub fn list(
+        &self,
+        names: &[String],
+        simple: bool,
+        matching: Matching,
+        kinds: &[Origin],
+        origin: bool,
+        plain: bool,
+    ) -> Result<String> {
+        let templates = self.read_file()?;
+        let origins = if origin {
+            self.origins(&templates)
+        } else {
+            HashMap::new()
+        };
+
+        let aliases = self.aliases(simple);
+        let builtin = self.builtin_aliases(simple);
+        let builtin_source = BUILTIN_SOURCE.to_string();
+        let result = self
+            .matching(&templates, names, simple, matching, kinds)?
+            .into_iter()
+            .fold(String::new(), |mut s, r| {
+                let shadows = shadows(&templates, &r);
+                let targets = match &r {
+                    Type::Alias(name) => aliases.get(&name.to_lowercase()).map(Vec::as_slice),
+                    _ => None,
+                };
+                let origin = match &r {
+                    Type::Alias(name) if builtin.contains_key(name) => Some(&builtin_source),
+                    _ => origins.get(r.inner()),
+                };
+                let line = list_entry(&r, origin, targets.unwrap_or_default(), shadows, plain);
+                writeln!(s, "{}", line).unwrap();
+                s
+            });
+
+        Ok(result)
+    }
+
+    /// Lists the templates matching any of `names`, or all of them, along with
+    /// what kind of template they are for `--list --format json`.
+    pub fn list_entries(
+        &self,
+        names: &[String],
+        simple: bool,
+        matching: Matching,
+        kinds: &[Origin],
+    ) -> Result<Vec<ListEntry>> {
+        let templates = self.read_file()?;
+        let origins = self.origins(&templates);
+        let aliases = self.aliases(simple);
+        let builtin = self.builtin_aliases(simple);
+
+        Ok(self
+            .matching(&templates, names, simple, matching, kinds)?
+            .into_iter()
+            .map(|entry| {
+                let key = entry.inner().to_string();
+                let name = match entry {
+                    Type::Normal(_) => templates.get(&entry).map(|t| t.name.clone()),
+                    _ => None,
+                };
+                let (targets, source) = match entry {
+                    Type::Normal(_) => (Vec::new(), origins.get(&key).cloned()),
+                    Type::Alias(_) => (
+                        aliases
+                            .get(&key.to_lowercase())
+                            .cloned()
+                            .unwrap_or_default(),
+                        builtin
+                            .contains_key(&key)
+                            .then(|| BUILTIN_SOURCE.to_string()),
+                    ),
+                    Type::Template(_) => (Vec::new(), None),
+                };
+
+                ListEntry {
+                    shadows: shadows(&templates, &entry),
+                    key,
+                    kind: entry.origin(),
+                    name,
+                    targets,
+                    source,
+                }
+            })
+            .collect())
+    }
+
+    /// Every template of one of `kinds`, or any kind, matching any of `names`,
+    /// or all of them, sorted by name. Downloaded templates also match on their
+    /// display name, ignoring case, so searching for "Visual Studio" finds
+    /// `visualstudiocode`.
+    fn matching(
+        &self,
+        entries: &HashMap<Type, Entry>,
+        names: &[String],
+        simple: bool,
+        matching: Matching,
+        kinds: &[Origin],
+    ) -> Result<Vec<Type>> {
+        let patterns = matching.compile(names)?;
+        let templates = self
+            .all_names(entries, simple)
+            .into_iter()
+            .filter(|entry| kinds.is_empty() || kinds.contains(&entry.origin()));
+        let mut result = if names.is_empty() {
+            templates.into_iter().collect::<Vec<_>>()
+        } else {
+            let mut result = Vec::new();
+
+            for entry in templates {
+                let display_name = match entry {
+                    Type::Normal(_) => entries.get(&entry).map(|e| e.name.as_str()),
+                    _ => None,
+                };
+
+                for (i, name) in names.iter().enumerate() {
+                    if matching.matches(entry.inner(), display_name, name, patterns.get(i)) {
+                        result.push(entry.clone());
+                    }
+                }
+            }
+            result
+        };
+
+        result.sort_unstable();
+        result.dedup();
+
+        Ok(result)
+    }
+
+    /// Resolves each name to a user template, an alias or a downloaded
+    /// template, in that order, and combines them into a single `.gitignore`.
+    pub fn get_templates(&self, names: &[String], simple: bool) -> Result<Generated> {
+        let (mut generated, fallback) = self.generate(names, simple, self.section_headers)?;
+
+        if !generated.combined.is_empty() {
+            let mut header = format!("\n\n{}\n", ATTRIBUTION);
+            if fallback {
+                header.push_str(FALLBACK_NOTE);
+                header.push_str(", run `git ignore -u` to get the latest\n");
+            }
+            header.push('\n');
+            generated.combined.insert_str(0, &header);
+
+            if self.footer {
+                generated.combined.push('\n');
+                generated.combined.push_str(&footer(&generated.keys));
+                generated.combined.push('\n');
+            }
+        }
+
+        if self.compact {
+            generated.combined = compact(&generated.combined);
+        }
+
+        Ok(generated)
+    }
+
+    /// Resolves a single name like `get_templates` for `git ignore show`, where
+    /// the combined output is only its contents, or every template in its own
+    /// section for an alias.
+    pub fn show(&self, name: &str, simple: bool) -> Result<Generated> {
+        let (mut generated, _) = self.generate(&[name.to_string()], simple, true)?;
+        if let [template] = generated.templates.as_slice() {
+            if template.source != Origin::Alias {
+                generated.combined = format!("{}\n", trim_body(&template.content));
+            }
+        }
+
+        Ok(generated)
+    }
+
+    /// The built-in aliases that aren't replaced by an alias in the config,
+    /// none when `simple` or `builtin_aliases = false` in the config.
+    pub fn builtin_aliases(&self, simple: bool) -> HashMap<String, Vec<String>> {
+        let mut aliases = builtin::aliases();
+        match &self.config {
+            _ if simple => return HashMap::new(),
+            Some(config) if config.builtin_aliases == Some(false) => return HashMap::new(),
+            Some(config) => {
+                aliases.retain(|name, _| {
+                    !config
+                        .aliases
+                        .keys()
+                        .any(|key| key.eq_ignore_ascii_case(name))
+                });
+            }
+            None => {}
+        }
+        aliases
+    }
+
+    /// Every alias by its lowercased name, the ones in the config along with
+    /// the built-in ones, none when `simple`.
+    pub fn aliases(&self, simple: bool) -> HashMap<String, Vec<String>> {
+        let mut aliases = self.builtin_aliases(simple);
+        if let Some(config) = self.config.as_ref().filter(|_| !simple) {
+            aliases.extend(lowercase_keys(&config.aliases));
+        }
+        aliases
+    }
+
+    /// The templates and aliases the config adds to everything generated,
+    /// none when `simple`.
+    pub fn default_templates(&self, simple: bool) -> Vec<String> {
+        match &self.config {
+            Some(config) if !simple => config.default_templates.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Everything `name` refers to, from the one used to the ones it shadows:
+    /// a user template, an alias and a downloaded template. Exclusions are
+    /// ignored.
+    pub fn matches(&self, name: &str, simple: bool) -> Result<Vec<Match>> {
+        let aliases = self.aliases(simple);
+        let (templates, templates_dir) = match &self.config {
+            Some(config) if !simple => (
+                lowercase_keys(&config.templates),
+                config.templates_dir.clone(),
+            ),
+            _ => (HashMap::new(), PathBuf::new()),
+        };
+        let ignore_file = self.read_file()?;
+        let cached = |name: &str| {
+            let key = name.to_lowercase();
+            ignore_file
+                .contains_key(&Type::Normal(key.clone()))
+                .then_some(key)
+        };
+
+        let key = name.to_lowercase();
+        let mut matches = Vec::new();
+        if let Some(file) = templates.get(&key) {
+            matches.push(Match::User {
+                file: templates_dir.join(file),
+            });
+        }
+        if let Some(members) = aliases.get(&key) {
+            let members = members
+                .iter()
+                .map(|member| {
+                    let member_key = member.to_lowercase();
+                    let user = templates.get(&member_key).filter(|_| member_key != key);
+                    let found = match (user, cached(member)) {
+                        _ if is_nested_alias(&aliases, &key, member) => Member::Alias,
+                        (Some(file), _) => Member::User(templates_dir.join(file)),
+                        (None, Some(cached)) => Member::Template(cached),
+                        (None, None) => Member::Missing,
+                    };
+                    (member.clone(), found)
+                })
+                .collect();
+            matches.push(Match::Alias { members });
+        }
+        if let Some(entry) = ignore_file.get(&Type::Normal(key.clone())) {
+            matches.push(Match::Template {
+                key,
+                source: self.source_name(entry.source.as_deref()),
+            });
+        }
+
+        Ok(matches)
+    }
+
+    /// Every line of the cached and user templates containing `pattern`, or
+    /// matching it as a regular expression when `regex`, by template name.
+    pub fn grep(&self, pattern: &str, regex: bool, simple: bool) -> Result<Vec<GrepMatch>> {
+        let regex = if regex {
+            Some(compile_regex(pattern)?)
+        } else {
+            None
+        };
+        self.find_lines(simple, |line| match &regex {
+            Some(regex) => regex.is_match(line),
+            None => line.contains(pattern),
+        })
+    }
+
+    /// The templates containing `line`, or a line that's the same once
+    /// trimmed if none contain it exactly.
+    pub fn explain(&self, line: &str, simple: bool) -> Result<Explanation> {
+        let found = self.find_lines(simple, |candidate| candidate.trim() == line.trim())?;
+        let exact: Vec<&GrepMatch> = found
+            .iter()
+            .filter(|found| found.lines.iter().any(|candidate| candidate == line))
+            .collect();
+        let trimmed = exact.is_empty();
+        let templates = if trimmed {
+            found.iter().collect()
+        } else {
+            exact
+        };
+
+        Ok(Explanation {
+            line: line.to_string(),
+            templates: templates
+                .into_iter()
+                .map(|found| Contributor {
+                    key: found.key.clone(),
+                    kind: found.kind,
+                })
+                .collect(),
+            trimmed: trimmed && !found.is_empty(),
+            in_gitignore: None,
+        })
+    }
+
+    /// Every line of the cached and user templates that `matches`, by
+    /// template name. User templates that can't be read are skipped with a
+    /// warning.
+    fn find_lines(&self, simple: bool, matches: impl Fn(&str) -> bool) -> Result<Vec<GrepMatch>> {
+        let matching = |contents: &str| -> Vec<String> {
+            contents
+                .lines()
+                .filter(|line| matches(line))
+                .map(ToString::to_string)
+                .collect()
+        };
+
+        let mut found = Vec::new();
+        for (key, entry) in self.read_file()? {
+            let lines = self.with_contents(key.inner(), &entry, matching)?;
+            found.push(GrepMatch {
+                key: key.inner().to_string(),
+                kind: Origin::Template,
+                lines,
+            });
+        }
+        if let Some(config) = self.config.as_ref().filter(|_| !simple) {
+            for (name, file) in lowercase_keys(&config.templates) {
+                match config.read_template(&file) {
+                    Ok(contents) => found.push(GrepMatch {
+                        key: name,
+                        kind: Origin::User,
+                        lines: matching(&contents),
+                    }),
+                    Err(err) => warning!("Could not read user template '{}': {}", name, err),
+                }
+            }
+        }
+
+        found.retain(|found| !found.lines.is_empty());
+        found.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(found)
+    }
+
+    /// Expands the alias `name` even if a user template shadows it, without
+    /// leaving anything out. `None` if there is no such alias.
+    pub fn expand_alias(&self, name: &str) -> Result<Option<AliasExpansion>> {
+        let aliases = self.aliases(false);
+        let Some(members) = aliases.get(&name.to_lowercase()) else {
+            return Ok(None);
+        };
+        // Shown with the case it has in the config
+        let name = match &self.config {
+            Some(config) => config
+                .aliases
+                .keys()
+                .find(|key| key.eq_ignore_ascii_case(name)),
+            None => None,
+        }
+        .map_or_else(|| name.to_lowercase(), Clone::clone);
+
+        let templates = match &self.config {
+            Some(config) => lowercase_keys(&config.templates),
+            None => HashMap::new(),
+        };
+        let ignore_file = self.read_file()?;
+        let cached = |key: &str| ignore_file.contains_key(&Type::Normal(key.to_string()));
+        let mut resolution = Resolution::default();
+        expand_alias(
+            &mut vec![name.to_lowercase()],
+            &aliases,
+            &templates,
+            &cached,
+            &|_| false,
+            &mut resolution,
+        )?;
+
+        Ok(Some(AliasExpansion {
+            name,
+            members: members.clone(),
+            resolved: resolution.templates,
+            missing: resolution.missing,
+        }))
+    }
+
+    /// Works out what each of `names` expands to, a user template, an alias or
+    /// a downloaded template in that order, leaving out excluded templates.
+    /// Nothing is read but the names of the templates.
+    pub fn resolve(&self, names: &[String], simple: bool) -> Result<Vec<Resolution>> {
+        let aliases = self.aliases(simple);
+        let templates = match &self.config {
+            Some(config) if !simple => lowercase_keys(&config.templates),
+            _ => HashMap::new(),
+        };
+        let ignore_file = self.read_file()?;
+        let excluded = |key: &str| {
+            let excluded = self.exclude.iter().any(|e| e == key);
+            if excluded {
+                debug!("Leaving out '{}', it was excluded", key);
+            }
+            excluded
+        };
+
+        let mut resolutions = Vec::new();
+        for name in names {
+            let key = name.to_lowercase();
+            let mut resolution = Resolution {
+                name: name.clone(),
+                ..Resolution::default()
+            };
+
+            if excluded(&key) {
+                resolution.excluded.push(key);
+            } else if let Some(val) = templates.get(&key) {
+                debug!("'{}' is a user template in '{}'", name, val);
+                resolution.kind = Some(Origin::User);
+                resolution.templates.push(key);
+            } else if aliases.contains_key(&key) {
+                resolution.kind = Some(Origin::Alias);
+                let cached = |key: &str| ignore_file.contains_key(&Type::Normal(key.to_string()));
+                expand_alias(
+                    &mut vec![key],
+                    &aliases,
+                    &templates,
+                    &cached,
+                    &excluded,
+                    &mut resolution,
+                )?;
+            } else if ignore_file.contains_key(&Type::Normal(key.clone())) {
+                debug!("'{}' found in the cache", name);
+                resolution.kind = Some(Origin::Template);
+                resolution.templates.push(key);
+            } else {
+                debug!("'{}' not found in the cache", name);
+            }
+            resolutions.push(resolution);
+        }
+
+        Ok(resolutions)
+    }
+
+    /// Resolves `names` and reads the templates they expand to, without the
+    /// header, footer or compacting. Returns whether any came from the
+    /// fallback templates.
+    fn generate(&self, names: &[String], simple: bool, headers: bool) -> Result<(Generated, bool)> {
+        let user_templates = match &self.config {
+            Some(config) if !simple => lowercase_keys(&config.templates),
+            _ => HashMap::new(),
+        };
+        let ignore_file = self.read_file()?;
+        let mut generated = Generated::default();
+        let mut fallback = false;
+
+        for resolution in self.resolve(names, simple)? {
+            for key in &resolution.excluded {
+                if !generated.excluded.contains(key) {
+                    generated.excluded.push(key.clone());
+                }
+            }
+            let Some(kind) = resolution.kind else {
+                if resolution.excluded.is_empty() {
+                    generated.unresolved.push(Unresolved {
+                        name: resolution.name,
+                        alias: None,
+                    });
+                }
+                continue;
+            };
+            for name in resolution.missing {
+                generated.unresolved.push(Unresolved {
+                    name,
+                    alias: Some(resolution.name.clone()),
+                });
+            }
+
+            let mut templates = Vec::new();
+            for key in &resolution.templates {
+                let user = kind == Origin::User || resolution.user_templates.contains(key);
+                let contents = match (user, user_templates.get(key), &self.config) {
+                    (true, Some(file), Some(config)) => config.read_template(file)?,
+                    _ => {
+                        let entry = ignore_file
+                            .get(&Type::Normal(key.clone()))
+                            .with_context(|| format!("'{}' is no longer in the cache", key))?;
+                        fallback |= entry.is_fallback();
+                        self.contents(key, entry)?
+                    }
+                };
+                templates.push((key.as_str(), contents));
+            }
+            generated.push(&resolution.name.to_lowercase(), kind, &templates, headers);
+        }
+
+        Ok((generated, fallback))
+    }
+
+    /// Works out how to regenerate the generated block in `path` from the
+    /// current templates, using the ones recorded in its footer. Returns them
+    /// along with the change.
+    pub fn plan_sync(&self, path: &Path) -> Result<(Vec<String>, Change)> {
+        let contents =
+            read_to_string(path).with_context(|| format!("Could not read '{}'", path.display()))?;
+        let range = block::find(&contents)
+            .with_context(|| format!("Could not sync '{}'", path.display()))?
+            .with_context(|| {
+                format!(
+                    "'{}' has no generated block to sync, write it with `git ignore <templates> --write` to add one",
+                    path.display()
+                )
+            })?;
+        let block = &contents[range];
+
+        let keys = parse_footer(block)
+            .filter(|keys| !keys.is_empty())
+            .with_context(|| {
+                format!(
+                    "The generated block in '{}' doesn't list its templates, write it again without '--no-footer'",
+                    path.display()
+                )
+            })?;
+        debug!(
+            "'{}' was generated from {}",
+            path.display(),
+            keys.join(", ")
+        );
+
+        let generated = self.get_templates(&keys, false)?;
+        for name in &generated.unresolved {
+            warning!("No template named {}", name);
+        }
+        generated.ensure_content(false)?;
+
+        let combined = convert_line_endings(&generated.combined, block.contains("\r\n"));
+        let change = plan_write(path, &combined, Existing::Refuse, true)?;
+        Ok((keys, change))
+    }
+
+    pub fn autodetect_templates(&self) -> Result<Vec<String>> {
+        self.detect_templates(&current_dir()?)
+    }
+
+    /// The templates detected from the files in `dir`, sorted.
+    pub fn detect_templates(&self, dir: &Path) -> Result<Vec<String>> {
+        let entries: Vec<DirEntry> = read_dir(dir)
+            .with_context(|| format!("Could not read '{}'", dir.display()))?
+            .map(Result::unwrap)
+            .collect();
+        let mut detected = self.detectors.detects(entries.as_slice());
+        detected.sort_unstable();
+        Ok(detected)
+    }
+
+    /// Whether each of `detected` is in the `.gitignore` `contents`, either
+    /// written by us or with most of its patterns.
+    pub fn check_coverage(&self, detected: &[String], contents: &str) -> Result<Vec<Checked>> {
+        let templates = self.read_file()?;
+        let written = written_templates(contents);
+        let existing = coverage::patterns(contents);
+
+        detected
+            .iter()
+            .map(|name| {
+                let key = name.to_lowercase();
+                let template = match templates.get(&Type::Normal(key.clone())) {
+                    Some(entry) => Some(self.contents(&key, entry)?),
+                    None => None,
+                };
+                Ok(Checked {
+                    template: name.clone(),
+                    coverage: coverage::coverage(&key, template.as_deref(), &written, &existing),
+                })
+            })
+            .collect()
+    }
+
+    /// Maps each cached template to the source it was downloaded from.
+    fn origins(&self, templates: &HashMap<Type, Entry>) -> HashMap<String, String> {
+        templates
+            .iter()
+            .filter_map(|(name, entry)| {
+                let url = entry.source.clone()?;
+                let origin = self
+                    .sources
+                    .iter()
+                    .find(|source| source.url == url)
+                    .map_or(url, ToString::to_string);
+                Some((name.inner().to_string(), origin))
+            })
+            .collect()
+    }
+
+    fn all_names(&self, templates: &HashMap<Type, Entry>, simple: bool) -> HashSet<Type> {
+        if simple {
+            return templates.keys().cloned().collect();
+        }
+
+        let config_names = match &self.config {
+            Some(config) => config.names(),
+            _ => vec![],
+        };
+        let builtin = self.builtin_aliases(simple).into_keys().map(Type::Alias);
+
+        let mut combined: HashSet<Type> = config_names.into_iter().chain(builtin).collect();
+        combined.extend(templates.keys().cloned());
+
+        combined
+    }
+
+    /// Fetches the templates from every source and writes the merged result
+    /// to the cache for easy future retrieval, later sources override earlier
+    /// ones. Sources we already have templates from are only downloaded again
+    /// if they have changed, returns the number of bytes downloaded or `None`
+    /// if none of them have.
+    ///
+    /// When there are multiple sources a failing one is skipped with a warning
+    /// and whatever we had cached from it is kept.
+    fn fetch_gitignore(&self) -> Result<Option<u64>> {
+        let preserved = || {
+            if self.cache_exists() {
+                format!(
+                    "Could not update templates, the previous cache at {} was preserved. Pass '--offline' to use it without updating",
+                    self.cache_file().display()
+                )
+            } else {
+                "Could not download templates and none are cached, check your connection and try again".to_string()
+            }
+        };
+
+        let previous = self
+            .parse_cache()
+            .ok()
+            .and_then(Result::ok)
+            .unwrap_or_default();
+        let mut meta = self.read_meta();
+        let mut templates = HashMap::new();
+        let mut changed = false;
+        let mut fetched = 0;
+        let mut size = 0;
+
+        for source in &self.sources {
+            let cached = previous
+                .iter()
+                .filter(|(_, language)| language.source.as_ref() == Some(&source.url))
+                .map(|(name, language)| (name.clone(), language.clone()))
+                .collect::<HashMap<_, _>>();
+            let conditional = meta.sources.get(&source.url).filter(|_| !cached.is_empty());
+
+            let result = retry(MAX_RETRIES, self.retry_backoff, || {
+                self.fetch_source(source, conditional)
+            });
+
+            match result {
+                Ok(Some(download)) => {
+                    changed = true;
+                    fetched += 1;
+                    size += download.size;
+                    templates.extend(download.templates.into_iter().map(|(name, mut language)| {
+                        language.source = Some(source.url.clone());
+                        (name, language)
+                    }));
+                    meta.sources.insert(source.url.clone(), download.meta);
+                }
+                Ok(None) => {
+                    fetched += 1;
+                    templates.extend(cached);
+                }
+                Err(err) if self.sources.len() > 1 => {
+                    warning!("Could not fetch templates from {}: {:#}", source, err);
+                    templates.extend(cached);
+                }
+                Err(err) => return Err(err).with_context(preserved),
+            }
+        }
+
+        if fetched == 0 {
+            return Err(anyhow!("Could not fetch templates from any source"))
+                .with_context(preserved);
+        }
+
+        meta.updated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+
+        if changed {
+            self.write_cache(&templates).with_context(preserved)?;
+
+            // Everything is new on the first download, so there's nothing
+            // interesting to report
+            if !previous.is_empty() {
+                self.report_changes(&TemplateDiff::new(&previous, &templates));
+            }
+        }
+        std::fs::write(&self.meta_file, serde_json::to_string(&meta)?)?;
+
+        Ok(changed.then_some(size))
+    }
+
+    /// Prints a summary of what an update changed, listing every changed
+    /// template when running verbosely.
+    fn report_changes(&self, diff: &TemplateDiff) {
+        info!("{}", diff);
+        if self.verbose && !diff.changed.is_empty() {
+            info!("Changed: {}", diff.changed.join(", "));
+        }
+    }
+
+    /// Takes an exclusive lock on the cache so concurrent updates can't
+    /// clobber each other, waiting for up to `LOCK_TIMEOUT` if another process
+    /// holds it. Returns whether we had to wait. The lock is released when
+    /// the returned guard is dropped, and by the OS if the process dies, so a
+    /// crashed update never leaves a stale lock behind.
+    fn lock_update(&self) -> Result<(UpdateLock, bool)> {
+        let path = self.lock_file();
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Could not open lock file '{}'", path.display()))?;
+
+        let start = Instant::now();
+        let mut waited = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok((UpdateLock(file), waited)),
+                Err(TryLockError::WouldBlock) if start.elapsed() < LOCK_TIMEOUT => {
+                    if !waited {
+                        info!("Another process is updating the cache, waiting for it to finish");
+                        waited = true;
+                    }
+                    std::thread::sleep(LOCK_POLL_INTERVAL);
+                }
+                Err(TryLockError::WouldBlock) => bail!(
+                    "Timed out waiting for another process to finish updating the cache at '{}'",
+                    self.cache_dir.display()
+                ),
+                Err(TryLockError::Error(err)) => {
+                    return Err(err).with_context(|| format!("Could not lock '{}'", path.display()))
+                }
+            }
+        }
+    }
+
+    fn lock_file(&self) -> PathBuf {
+        self.cache_dir.join("update.lock")
+    }
+
+    /// Applies our timeouts and proxies to `req`, making it conditional if we
+    /// have headers from a previous download.
+    fn request(&self, req: RequestBuilder, meta: Option<&SourceMeta>) -> Result<RequestBuilder> {
+        let mut req = req
+            .connect_timeout(self.connect_timeout)
+            .read_timeout(self.read_timeout)
+            .proxy_settings(self.proxies.settings());
+        if let Some(meta) = meta {
+            if let Some(etag) = &meta.etag {
+                req = req.try_header(header::IF_NONE_MATCH, etag.as_str())?;
+            }
+            if let Some(last_modified) = &meta.last_modified {
+                req = req.try_header(header::IF_MODIFIED_SINCE, last_modified.as_str())?;
+            }
+        }
+
+        Ok(req)
+    }
+
+    /// Asks each source whether its templates have changed since we cached
+    /// them without downloading anything, returning the first one that has
+    /// newer templates. Sources we have no headers for are only considered
+    /// out of date once the cache is stale.
+    pub fn check_for_update(&self) -> Result<Option<&Source>> {
+        if self.offline {
+            bail!("Cannot check for updates while offline");
+        }
+        self.check_sources()?;
+
+        // Only read, migrating a cache from an older version is left to an
+        // update holding the lock, so one needs updating
+        let Some(previous) = self.parse_index().ok().and_then(Result::ok) else {
+            return Ok(self.sources.first());
+        };
+        let meta = self.read_meta();
+
+        for source in &self.sources {
+            let cached = previous
+                .values()
+                .any(|entry| entry.source.as_ref() == Some(&source.url));
+            if !cached {
+                return Ok(Some(source));
+            }
+
+            let Some(stored) = meta
+                .sources
+                .get(&source.url)
+                .filter(|meta| meta.etag.is_some() || meta.last_modified.is_some())
+            else {
+                if self.cache_is_stale() {
+                    return Ok(Some(source));
+                }
+                continue;
+            };
+
+            let res = self
+                .request(attohttpc::head(&source.url), Some(stored))?
+                .send()
+                .map_err(|err| self.connection_error(source, err.into()))?;
+            if res.status() == StatusCode::NOT_MODIFIED {
+                continue;
+            } else if !res.status().is_success() {
+                bail!("{} responded with {}", source, res.status());
+            }
+
+            // Not every server supports conditional requests, so compare the
+            // headers ourselves as well
+            let current = SourceMeta::from_headers(res.headers());
+            let unchanged = match (&stored.etag, &current.etag) {
+                (Some(stored), Some(current)) => stored == current,
+                _ => {
+                    stored.last_modified.is_some() && stored.last_modified == current.last_modified
+                }
+            };
+            if !unchanged {
+                return Ok(Some(source));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Checks that every source can be reached with a `HEAD` request.
+    pub fn reach_sources(&self) -> Vec<(&Source, Result<()>)> {
+        self.sources
+            .iter()
+            .map(|source| {
+                let reached = self
+                    .request(attohttpc::head(&source.url), None)
+                    .and_then(|req| {
+                        let res = req
+                            .send()
+                            .map_err(|err| self.connection_error(source, err.into()))?;
+                        if !res.status().is_success() {
+                            bail!("{} responded with {}", source, res.status());
+                        }
+                        Ok(())
+                    });
+                (source, reached)
+            })
+            .collect()
+    }
+
+    /// Downloads the templates from a single source, converting them to the
+    /// gitignore.io format if needed. If we have headers from a previous
+    /// download the request is made conditional, returning `None` if the
+    /// templates have not changed.
+    fn fetch_source(&self, source: &Source, meta: Option<&SourceMeta>) -> Result<Option<Download>> {
+        trace!("Downloading {}", source.url);
+        let res = self
+            .request(attohttpc::get(&source.url), meta)?
+            .send()
+            .map_err(|err| Transient(self.connection_error(source, err.into())))?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            trace!("{} has not changed", source.url);
+            return Ok(None);
+        }
+
+        let (status, headers, mut reader) = res.split();
+        let total = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse().ok());
+
+        let download_file = self.download_file("list");
+        let result = self
+            .download(source, &mut reader, total, &download_file)
+            .and_then(|(size, head)| {
+                // Error pages are read in full so we can show what the server said
+                if !status.is_success() {
+                    let err = anyhow!("{} responded with {}: {}", source, status, excerpt(&head));
+                    return Err(if status.is_server_error() {
+                        Transient(err).into()
+                    } else {
+                        err
+                    });
+                }
+
+                let html = headers
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|value| value.contains("text/html"));
+                let body = BufReader::new(File::open(&download_file)?);
+                let templates = match source.kind {
+                    TemplateSource::GitignoreIo if html => bail!(
+                        "{} responded with an HTML page instead of templates: {}",
+                        source,
+                        excerpt(&head)
+                    ),
+                    TemplateSource::GitignoreIo => {
+                        serde_json::from_reader(body).with_context(|| {
+                            format!(
+                                "{} responded with invalid templates: {}",
+                                source,
+                                excerpt(&head)
+                            )
+                        })?
+                    }
+                    TemplateSource::Github => templates_from_tarball(body)?,
+                };
+
+                Ok(Some(Download {
+                    templates,
+                    meta: SourceMeta::from_headers(&headers),
+                    size,
+                }))
+            });
+
+        let _ = std::fs::remove_file(&download_file);
+        result
+    }
+
+    /// Downloads a single template from `url` for `template add --from-url`,
+    /// with the same timeouts, proxies, retries and size limit as the
+    /// templates.
+    pub fn download_template(&self, url: &str) -> Result<TemplateContents> {
+        if self.offline {
+            bail!("Cannot download '{}' while offline", url);
+        }
+        let source = Source::new(TemplateSource::GitignoreIo, url)?;
+        std::fs::create_dir_all(&self.cache_dir)
+            .map_err(|e| file_error(e, "create", &self.cache_dir))?;
+
+        retry(MAX_RETRIES, self.retry_backoff, || {
+            self.fetch_template(&source)
+        })
+    }
+
+    /// Downloads the body of `source`, failing unless it's text.
+    fn fetch_template(&self, source: &Source) -> Result<TemplateContents> {
+        trace!("Downloading {}", source.url);
+        let res = self
+            .request(attohttpc::get(&source.url), None)?
+            .send()
+            .map_err(|err| Transient(self.connection_error(source, err.into())))?;
+
+        let (status, headers, mut reader) = res.split();
+        let total = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse().ok());
+
+        let download_file = self.download_file("template");
+        let result = self
+            .download(source, &mut reader, total, &download_file)
+            .and_then(|(_, head)| {
+                if !status.is_success() {
+                    let err = anyhow!("{} responded with {}: {}", source, status, excerpt(&head));
+                    return Err(if status.is_server_error() {
+                        Transient(err).into()
+                    } else {
+                        err
+                    });
+                }
+
+                let html = headers
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|value| value.contains("text/html"));
+                if html {
+                    bail!(
+                        "{} responded with an HTML page instead of a template: {}",
+                        source,
+                        excerpt(&head)
+                    );
+                }
+
+                let mut contents =
+                    TemplateContents::new(source.url.clone(), std::fs::read(&download_file)?)?;
+                contents.url = Some(source.url.clone());
+                Ok(contents)
+            });
+
+        let _ = std::fs::remove_file(&download_file);
+        result
+    }
+
+    /// Where response bodies for `what` are streamed to before they are
+    /// parsed. Single templates are downloaded without the update lock, so the
+    /// file is named after the process to never be shared with another run.
+    fn download_file(&self, what: &str) -> PathBuf {
+        self.cache_dir
+            .join(format!("{}.{}.download", what, std::process::id()))
+    }
+
+    /// Streams a response body into `file`, giving up as soon as it grows
+    /// past the maximum size. Returns the size of the body and its first few
+    /// bytes for error messages.
+    fn download<R: Read>(
+        &self,
+        source: &Source,
+        reader: &mut R,
+        total: Option<u64>,
+        file: &Path,
+    ) -> Result<(u64, Vec<u8>)> {
+        let too_large = || {
+            anyhow!(
+                "Response from {} exceeded the maximum size of {}, raise 'max_download_mb' in the config if this is expected",
+                source,
+                format_size(self.max_download)
+            )
+        };
+        if total.is_some_and(|total| total > self.max_download) {
+            return Err(too_large());
+        }
+
+        let mut progress = Progress::new(format!("Downloading from {}", source), total);
+        let mut writer = BufWriter::new(File::create(file)?);
+        let mut head = Vec::new();
+        let mut size = 0;
+        let mut buf = [0; 8192];
+        loop {
+            let read = reader.read(&mut buf).map_err(|err| Transient(err.into()))?;
+            if read == 0 {
+                break;
+            }
+
+            size += read as u64;
+            if size > self.max_download {
+                return Err(too_large());
+            }
+
+            let keep = read.min(HEAD_LEN - head.len());
+            head.extend_from_slice(&buf[..keep]);
+            writer.write_all(&buf[..read])?;
+            progress.advance(read);
+        }
+        writer.flush()?;
+
+        Ok((size, head))
+    }
+
+    /// Explains why we could not connect to a source, telling a broken proxy
+    /// apart from the server being unreachable.
+    fn connection_error(&self, source: &Source, err: anyhow::Error) -> anyhow::Error {
+        match self.proxies.for_url(&source.url) {
+            Some(proxy) if !is_reachable(proxy, self.connect_timeout) => {
+                err.context(format!("Proxy {} is unreachable", Redacted(proxy)))
+            }
+            Some(proxy) => err.context(format!(
+                "Could not reach {} through proxy {}",
+                source,
+                Redacted(proxy)
+            )),
+            None => err.context(format!("Could not reach {}", source)),
+        }
+    }
+
+    /// Writes every template to a compressed file of its own in `templates/`
+    /// and their names and sources to `index.json`. Everything is written next
+    /// to the current cache first and only moved into place once complete, so
+    /// a failure never leaves us without a usable cache. The single file used
+    /// by older versions is removed once it has been replaced.
+    fn write_cache(&self, templates: &HashMap<String, Language>) -> Result<()> {
+        trace!(
+            "Writing {} templates to '{}'",
+            templates.len(),
+            self.cache_dir.display()
+        );
+        let tmp_dir = self.templates_dir.with_extension("tmp");
+        let old_dir = self.templates_dir.with_extension("old");
+        let tmp_index = self.index_file.with_extension("json.tmp");
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+
+        let result = (|| -> Result<()> {
+            std::fs::create_dir_all(&tmp_dir)?;
+            let mut index = HashMap::new();
+            for (key, language) in templates {
+                let file = File::create(tmp_dir.join(template_file_name(key)))?;
+                let mut encoder = GzEncoder::new(file, Compression::default());
+                encoder.write_all(language.contents.as_bytes())?;
+                encoder.finish()?;
+
+                let mut entry = Entry::from(language.clone());
+                entry.contents = None;
+                index.insert(key.clone(), entry);
+            }
+            std::fs::write(&tmp_index, serde_json::to_vec(&index)?)?;
+
+            let _ = std::fs::remove_dir_all(&old_dir);
+            if self.templates_dir.exists() {
+                std::fs::rename(&self.templates_dir, &old_dir)?;
+            }
+            std::fs::rename(&tmp_dir, &self.templates_dir)?;
+            std::fs::rename(&tmp_index, &self.index_file)?;
+            Ok(())
+        })();
+
+        let _ = std::fs::remove_dir_all(&old_dir);
+        if result.is_err() {
+            let _ = std::fs::remove_dir_all(&tmp_dir);
+            let _ = std::fs::remove_file(&tmp_index);
+        } else {
+            let _ = std::fs::remove_file(&self.ignore_file);
+            let _ = std::fs::remove_file(&self.compressed_file);
+        }
+
+        result
+    }
+
+    /// Reads the information stored from the last update, a missing or broken
+    /// file just means the next update won't be conditional.
+    fn read_meta(&self) -> CacheMeta {
+        read_to_string(&self.meta_file)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Whether `name` is a downloaded template, so an alias or user template
+    /// with that name hides it. `None` if nothing is cached to tell.
+    pub fn shadows_template(&self, name: &str) -> Option<bool> {
+        if !self.cache_exists() {
+            return None;
+        }
+        let templates = self.read_file().ok()?;
+        Some(templates.contains_key(&Type::Normal(name.to_lowercase())))
+    }
+
+    /// Returns true if templates are cached in either the current or an older
+    /// layout, false otherwise.
+    pub fn cache_exists(&self) -> bool {
+        self.index_file.exists() || self.legacy_file().exists()
+    }
+
+    /// Returns the file listing the cached templates, which is a cache from an
+    /// older version until it has been migrated.
+    fn cache_file(&self) -> &Path {
+        if self.index_file.exists() {
+            &self.index_file
+        } else {
+            self.legacy_file()
+        }
+    }
+
+    /// Returns the single file templates were cached in by older versions,
+    /// preferring the compressed one.
+    fn legacy_file(&self) -> &Path {
+        if self.compressed_file.exists() {
+            &self.compressed_file
+        } else {
+            &self.ignore_file
+        }
+    }
+
+    /// Returns the directory the templates are cached in.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Returns the config file given instead of the default one, if any.
+    pub fn config_file(&self) -> Option<&Path> {
+        self.config_file.as_deref()
+    }
+
+    /// Gathers information about the cached templates, returning `None` if
+    /// nothing has been cached yet.
+    pub fn cache_info(&self) -> Result<Option<CacheInfo>> {
+        if !self.cache_exists() {
+            return Ok(None);
+        }
+
+        let templates = self.read_file()?;
+        let mut size = std::fs::metadata(&self.index_file)?.len();
+        for entry in read_dir(&self.templates_dir)? {
+            size += entry?.metadata()?.len();
+        }
+
+        Ok(Some(CacheInfo {
+            path: self.cache_dir.clone(),
+            size,
+            uncompressed: templates.values().map(|entry| entry.size).sum(),
+            templates: templates.len(),
+            updated: self.cache_updated(),
+        }))
+    }
+
+    /// Counts the cached templates, aliases and user templates. Nothing is
+    /// downloaded, the cache counts are zero if there is no cache.
+    pub fn stats(&self) -> Result<Stats> {
+        let mut stats = Stats {
+            cache_dir: self.cache_dir.clone(),
+            ..Stats::default()
+        };
+        if let Some(config) = &self.config {
+            stats.aliases = config.aliases.len();
+            stats.user_templates = config.templates.len();
+            stats.config = Some(config.path.clone());
+        }
+
+        let Some(info) = self.cache_info()? else {
+            return Ok(stats);
+        };
+        stats.templates = info.templates;
+        stats.cache_size = info.size;
+        stats.updated = info
+            .updated
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs());
+        for entry in self.read_file()?.values() {
+            let source = self.source_name(entry.source.as_deref());
+            *stats.sources.entry(source).or_default() += 1;
+        }
+
+        Ok(stats)
+    }
+
+    /// How the source a template was downloaded from is shown, named if it's
+    /// one of the configured ones.
+    fn source_name(&self, url: Option<&str>) -> String {
+        match url {
+            Some(url) => self
+                .sources
+                .iter()
+                .find(|source| source.url == url)
+                .map_or_else(|| url.to_string(), ToString::to_string),
+            None => "unknown".to_string(),
+        }
+    }
+
+    /// Reads the cache index without downloading it again if it's corrupted,
+    /// returning how many templates are cached.
+    pub fn verify_cache(&self) -> Result<usize> {
+        let index = self
+            .read_index()?
+            .with_context(|| format!("'{}' is corrupted", self.cache_file().display()))?;
+
+        Ok(index.len())
+    }
+
+    /// Writes the cached templates and when they were last updated to `file`,
+    /// returns the number of templates exported.
+    pub fn export_cache(&self, file: &Path) -> Result<usize> {
+        if !self.cache_exists() {
+            bail!("No cached templates found, run with '-u' to download them first");
+        }
+
+        let templates = self.parse_cache()?.with_context(|| {
+            format!(
+                "Cached templates at '{}' are corrupted, run with '-u' to download them again",
+                self.cache_file().display()
+            )
+        })?;
+        let count = templates.len();
+        let export = CacheExport {
+            meta: self.read_meta(),
+            templates,
+        };
+
+        std::fs::write(file, serde_json::to_vec(&export)?)
+            .with_context(|| format!("Could not write '{}'", file.display()))?;
+
+        Ok(count)
+    }
+
+    /// Installs templates exported with [`Core::export_cache`] from `file`,
+    /// replacing the current cache. Returns the number of templates imported.
+    pub fn import_cache(&self, file: &Path) -> Result<usize> {
+        let content =
+            read_to_string(file).with_context(|| format!("Could not read '{}'", file.display()))?;
+        let export: CacheExport = serde_json::from_str(&content)
+            .with_context(|| format!("'{}' is not a template cache export", file.display()))?;
+        if export.templates.is_empty() {
+            bail!("'{}' does not contain any templates", file.display());
+        }
+
+        self.create_dirs()?;
+        self.write_cache(&export.templates)?;
+        std::fs::write(&self.meta_file, serde_json::to_string(&export.meta)?)?;
+
+        Ok(export.templates.len())
+    }
+
+    /// Removes the cached templates and their metadata, and the cache
+    /// directory itself if nothing else is left in it. Only our own files are
+    /// removed since the directory can be pointed anywhere with `--cache-dir`.
+    /// Does nothing if there is no cache.
+    pub fn clear_cache(&self) -> std::io::Result<()> {
+        let ignore = |result: std::io::Result<()>| match result {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+
+        for dir in [
+            &self.templates_dir,
+            &self.templates_dir.with_extension("tmp"),
+            &self.templates_dir.with_extension("old"),
+        ] {
+            ignore(std::fs::remove_dir_all(dir))?;
+        }
+        for file in [
+            &self.index_file,
+            &self.index_file.with_extension("json.tmp"),
+            &self.compressed_file,
+            &self.ignore_file,
+            &self.meta_file,
+            &self.lock_file(),
+        ] {
+            ignore(std::fs::remove_file(file))?;
+        }
+        // Left behind by runs that were killed while downloading
+        if let Ok(entries) = read_dir(&self.cache_dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "download") {
+                    ignore(std::fs::remove_file(path))?;
+                }
+            }
+        }
+        let _ = std::fs::remove_dir(&self.cache_dir);
+
+        Ok(())
+    }
+
+    /// How long the cache can be used before it is automatically refreshed, a
+    /// zero duration disables the refreshing.
+    pub fn cache_ttl(&self) -> Duration {
+        let days = self
+            .config
+            .as_ref()
+            .and_then(|config| config.cache_ttl_days)
+            .unwrap_or(DEFAULT_CACHE_TTL_DAYS);
+
+        Duration::from_secs(days * 86_400)
+    }
+
+    /// Returns when the templates were last updated. Caches from before we kept
+    /// track of this fall back to when the cache was written.
+    pub fn cache_updated(&self) -> Option<SystemTime> {
+        if !self.cache_exists() {
+            return None;
+        }
+
+        match self.read_meta().updated_at {
+            Some(secs) => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+            None => std::fs::metadata(self.cache_file())
+                .and_then(|m| m.modified())
+                .ok(),
+        }
+    }
+
+    /// Returns how long ago the templates were last updated, if there are any.
+    pub fn cache_age(&self) -> Option<Duration> {
+        let updated = self.cache_updated()?;
+
+        Some(
+            SystemTime::now()
+                .duration_since(updated)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Returns true if stale templates should be refreshed in the background
+    /// instead of before using them.
+    pub fn background_update(&self) -> bool {
+        self.config
+            .as_ref()
+            .and_then(|config| config.background_update)
+            .unwrap_or(false)
+    }
+
+    /// Reads the cached templates once and uses them for the rest of the run,
+    /// regardless of any updates to the cache in the meantime.
+    pub fn snapshot_templates(&self) -> Result<()> {
+        let mut templates = self.read_file()?;
+        for (key, entry) in &mut templates {
+            entry.contents = Some(self.contents(key.inner(), entry)?);
+        }
+        let _ = self.snapshot.set(templates);
+
+        Ok(())
+    }
+
+    /// Returns true if the cache is older than the configured TTL.
+    pub fn cache_is_stale(&self) -> bool {
+        let ttl = self.cache_ttl();
+        if ttl.is_zero() {
+            return false;
+        }
+
+        self.cache_age().is_none_or(|age| age > ttl)
+    }
+
+    /// Creates the cache dir if it doesn't exist.
+    fn create_dirs(&self) -> std::io::Result<()> {
+        if !self.cache_exists() {
+            std::fs::create_dir_all(&self.cache_dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the cache index and serializes it using Serde to a `HashMap` where
+    /// the keys are each individual template and the value where to find its
+    /// contents (and some other stuff).
+    ///
+    /// If the cache is corrupted we try to download it again once before giving
+    /// up.
+    fn read_file(&self) -> Result<HashMap<Type, Entry>> {
+        if let Some(templates) = self.snapshot.get() {
+            return Ok(templates.clone());
+        }
+
+        // Only used until an update succeeds and writes a real cache
+        let parsed = if self.cache_exists() {
+            self.read_index()?
+        } else {
+            Ok(fallback::templates()
+                .into_iter()
+                .map(|(key, language)| (key, Entry::from(language)))
+                .collect())
+        };
+
+        let result = match parsed {
+            Ok(result) => result,
+            Err(err) if self.offline => {
+                return Err(err).with_context(|| {
+                    format!(
+                        "Cached templates at '{}' are corrupted, run with '-u' when online",
+                        self.cache_file().display()
+                    )
+                })
+            }
+            Err(err) => {
+                warning!(
+                    "Cached templates at '{}' are corrupted ({}), attempting update.",
+                    self.cache_file().display(),
+                    err
+                );
+                // The stored headers belong to the broken file, so make sure
+                // the server actually sends us a fresh copy.
+                let _ = std::fs::remove_file(&self.meta_file);
+                self.update()
+                    .and_then(|_| self.read_index()?.map_err(Into::into))
+                    .with_context(|| {
+                        format!(
+                            "Could not recover the template cache, delete '{}' and run with '-u'",
+                            self.cache_file().display()
+                        )
+                    })?
+            }
+        };
+
+        let result: HashMap<Type, Entry> = result
+            .into_iter()
+            .map(|(k, v)| (Type::Normal(k), v))
+            .collect();
+
+        Ok(result)
+    }
+
+    /// Reads and parses the index of cached templates, I/O errors are returned
+    /// as is while parse errors are returned separately so we can attempt to
+    /// recover from them. A cache from an older version is split up into the
+    /// current layout the first time it is read.
+    fn read_index(&self) -> Result<serde_json::Result<HashMap<String, Entry>>> {
+        if !self.index_file.exists() {
+            match self.read_legacy()? {
+                Ok(templates) => self.write_cache(&templates)?,
+                Err(err) => return Ok(Err(err)),
+            }
+        }
+
+        self.parse_index()
+    }
+
+    /// Reads the index without migrating a cache from an older version first.
+    fn parse_index(&self) -> Result<serde_json::Result<HashMap<String, Entry>>> {
+        let content = read_to_string(&self.index_file)?;
+        Ok(serde_json::from_str(&content))
+    }
+
+    /// Reads every cached template including its contents.
+    fn parse_cache(&self) -> Result<serde_json::Result<HashMap<String, Language>>> {
+        let index = match self.read_index()? {
+            Ok(index) => index,
+            Err(err) => return Ok(Err(err)),
+        };
+
+        let templates = index
+            .into_iter()
+            .map(|(key, entry)| {
+                let contents = self.contents(&key, &entry)?;
+                Ok((key.clone(), entry.into_language(key, contents)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Ok(templates))
+    }
+
+    /// Returns the contents of a template, reading them from the cache unless
+    /// they're already in memory.
+    fn contents(&self, key: &str, entry: &Entry) -> Result<String> {
+        if let Some(contents) = &entry.contents {
+            return Ok(contents.clone());
+        }
+
+        self.read_contents(key)
+    }
+
+    /// Calls `f` with the contents of a cached template, without copying them
+    /// if they're already read.
+    fn with_contents<T>(&self, key: &str, entry: &Entry, f: impl FnOnce(&str) -> T) -> Result<T> {
+        match &entry.contents {
+            Some(contents) => Ok(f(contents)),
+            None => Ok(f(&self.read_contents(key)?)),
+        }
+    }
+
+    fn read_contents(&self, key: &str) -> Result<String> {
+        let file = self.templates_dir.join(template_file_name(key));
+        let mut contents = String::new();
+        File::open(&file)
+            .and_then(|file| GzDecoder::new(file).read_to_string(&mut contents))
+            .with_context(|| {
+                format!(
+                    "Could not read cached template '{}' from '{}', run with '-u' to repair the cache",
+                    key,
+                    file.display()
+                )
+            })?;
+
+        Ok(contents)
+    }
+
+    /// Reads a cache from an older version, decompressing it if needed. A file
+    /// that fails to decompress is treated like one that fails to parse.
+    fn read_legacy(&self) -> Result<serde_json::Result<HashMap<String, Language>>> {
+        let file = self.legacy_file();
+        let content = if file == self.compressed_file {
+            let mut content = String::new();
+            let result = GzDecoder::new(File::open(file)?).read_to_string(&mut content);
+            match result {
+                Ok(_) => content,
+                Err(err) => return Ok(Err(serde_json::Error::io(err))),
+            }
+        } else {
+            read_to_string(file)?
+        };
+
+        Ok(serde_json::from_str(&content))
+    }
+}
+
+/// Name of the file a template is cached in, keys come from the server so make
+/// sure they can't point outside the cache. Characters that could are
+/// percent-encoded, along with `%` itself, so no two keys share a file.
+fn template_file_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len() + 3);
+    for c in key.chars() {
+        match c {
+            '/' | '\\' | '.' | '%' => name.push_str(&format!("%{:02X}", c as u32)),
+            c => name.push(c),
+        }
+    }
+    name + ".gz"
+}
+
+/// Runs `fetch` until it succeeds, fails for a non-transient reason or we run
+/// out of retries, waiting exponentially longer between each attempt.
+fn retry<T>(retries: u32, backoff: Duration, mut fetch: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 0;
+
+    loop {
+        match fetch() {
+            Err(err) if attempt < retries && err.is::<Transient>() => {
+                let delay = backoff * 2u32.pow(attempt);
+                attempt += 1;
+                warning!(
+                    "{}, retrying in {}s ({}/{})",
+                    err,
+                    delay.as_secs(),
+                    attempt,
+                    retries
+                );
+                std::thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// How much of a response body is kept in memory for error messages.
+const HEAD_LEN: usize = 4096;
+
+/// How much of an unexpected response body to show in errors.
+const EXCERPT_LEN: usize = 120;
+
+/// Returns the start of a response body on a single line, for showing what a
+/// server sent instead of templates.
+fn excerpt(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    let body = body.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if body.is_empty() {
+        "<empty body>".to_string()
+    } else if body.chars().count() > EXCERPT_LEN {
+        format!("{}...", body.chars().take(EXCERPT_LEN).collect::<String>())
+    } else {
+        body
+    }
+}
+
+/// Formats a size in bytes using binary units, e.g. `482.9 KiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Formats how long ago something happened, e.g. `12 days ago`.
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    let (count, unit) = match secs {
+        0..=59 => return "just now".to_string(),
+        60..=3_599 => (secs / 60, "minute"),
+        3_600..=86_399 => (secs / 3_600, "hour"),
+        _ => (secs / 86_400, "day"),
+    };
+
+    if count == 1 {
+        format!("1 {} ago", unit)
+    } else {
+        format!("{} {}s ago", count, unit)
+    }
+}
+
+/// Formats a timestamp as `YYYY-MM-DD HH:MM:SS UTC`, we don't need a full blown
+/// date library just to show when the cache was written.
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        fs::File,
+        io::{ErrorKind, Read, Write},
+        net::TcpListener,
+        path::{Path, PathBuf},
+        thread::{self, JoinHandle},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use anyhow::{anyhow, Result};
+    use etcetera::AppStrategy;
+
+    use super::{
+        append_to, apply, backup_file, compact, compile_regex, convert_line_endings, excerpt,
+        exclude_file, excludes_path, expand_alias, file_error, footer, format_age, format_size,
+        format_time, list_entry, long_list, missing_defaults, output_path, parse_footer,
+        plan_merge, plan_remove, plan_write, project_dirs, remove_patterns, replace_atomically,
+        repo_root, requested_names, retry, section, shorten_targets, strip_generated,
+        template_file_name, template_from_gitignore, trim_body, written_templates, Core, Existing,
+        Explanation, Generated, Language, Match, Matching, Member, Origin, Resolution, Source,
+        TemplateDiff, TemplateSource, Transient, Type, Written, ATTRIBUTION, BUILTIN_SOURCE,
+        DEFAULT_SERVER, EXCERPT_LEN,
+    };
+    use crate::config::{resolve_config_file, Config};
+    use crate::coverage::Coverage;
+    use crate::log;
+    use crate::proxy::Proxies;
+
+    fn temp_core(name: &str) -> Core {
+        let _ = std::fs::remove_dir_all(temp_core_in(name).cache_dir());
+        temp_core_in(name)
+    }
+
+    /// Like `temp_core`, but keeps whatever is already cached in the directory.
+    fn temp_core_in(name: &str) -> Core {
+        let dir = std::env::temp_dir().join(format!("git-ignore-test-{}", name));
+        let mut core = Core::with_cache_dir(dir, None);
+        core.retry_backoff = Duration::ZERO;
+        core
+    }
+
+    /// Plans and makes a write in one go, like running without `--dry-run`.
+    fn write_to(
+        path: &Path,
+        contents: &str,
+        existing: Existing,
+        managed: bool,
+        backup: bool,
+    ) -> Result<Written> {
+        apply(
+            path,
+            &plan_write(path, contents, existing, managed)?,
+            backup,
+        )
+    }
+
+    fn merge_into(path: &Path, contents: &str, backup: bool) -> Result<Written> {
+        apply(path, &plan_merge(path, contents)?, backup)
+    }
+
+    #[test]
+    fn cache_info_without_cache() {
+        let core = temp_core("info-empty");
+        assert!(core.cache_info().unwrap().is_none());
+    }
+
+    #[test]
+    fn cache_info_counts_templates() {
+        let core = temp_core("info-count");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/"}}"#,
+        )
+        .unwrap();
+
+        let info = core.cache_info().unwrap().unwrap();
+        assert_eq!(info.templates, 1);
+        assert_eq!(info.path, core.cache_dir());
+        assert!(info.size > 0);
+        assert_eq!(info.uncompressed, 7);
+
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn matches_every_shadowed_entry() {
+        let mut core = temp_core("matches");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/"}}"#,
+        )
+        .unwrap();
+        let mut config: Config = toml::from_str("[aliases]\n[templates]\n").unwrap();
+        config
+            .aliases
+            .insert("Rust".into(), vec!["rust".into(), "elm".into()]);
+        config.templates.insert("rust".into(), "rust.txt".into());
+        core.config = Some(config);
+
+        let matches = core.matches("RUST", false).unwrap();
+        assert_eq!(matches.len(), 3);
+        assert!(matches!(&matches[0], Match::User { file } if file.ends_with("rust.txt")));
+        assert_eq!(
+            matches[1],
+            Match::Alias {
+                members: vec![
+                    ("rust".into(), Member::Template("rust".into())),
+                    ("elm".into(), Member::Missing)
+                ]
+            }
+        );
+        assert!(matches!(&matches[2], Match::Template { key, .. } if key == "rust"));
+
+        // Only the cache is used when the config is ignored
+        let matches = core.matches("rust", true).unwrap();
+        assert!(matches!(&matches[..], [Match::Template { key, .. }] if key == "rust"));
+        assert!(core.matches("elm", false).unwrap().is_empty());
+
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn aliases_include_user_templates() {
+        let mut core = temp_core("alias-user");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config.templates_dir = core.cache_dir().join("user");
+        std::fs::create_dir_all(&config.templates_dir).unwrap();
+        std::fs::write(config.templates_dir.join("work.txt"), "secrets/\n").unwrap();
+        config.templates.insert("work".into(), "work.txt".into());
+        config
+            .aliases
+            .insert("web".into(), vec!["node".into(), "Work".into()]);
+        core.config = Some(config);
+
+        let resolved = core.resolve(&["web".into()], false).unwrap();
+        assert_eq!(resolved[0].templates, ["node", "work"]);
+        assert_eq!(resolved[0].user_templates, ["work"]);
+        assert!(resolved[0].missing.is_empty());
+
+        let generated = core.get_templates(&["web".into()], false).unwrap();
+        assert!(generated.unresolved.is_empty());
+        let combined = &generated.combined;
+        assert!(combined.find("node_modules/").unwrap() < combined.find("secrets/").unwrap());
+
+        let matches = core.matches("web", false).unwrap();
+        let Match::Alias { members } = &matches[0] else {
+            panic!("expected an alias, got {:?}", matches);
+        };
+        assert_eq!(members[0].1, Member::Template("node".into()));
+        assert!(matches!(&members[1].1, Member::User(file) if file.ends_with("work.txt")));
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn greps_template_contents() {
+        let core = temp_core("grep");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"terraform":{"key":"terraform","name":"Terraform","fileName":"Terraform.gitignore","contents":"crash.log\n*.tfstate\n*.tfstate.*\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\njspm_packages/\n"},"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"}}"#,
+        )
+        .unwrap();
+        let grep = |pattern: &str, regex| core.grep(pattern, regex, true);
+
+        let found = grep("*.tfstate", false).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].key, "terraform");
+        assert_eq!(found[0].kind, Origin::Template);
+        assert_eq!(found[0].lines, ["*.tfstate", "*.tfstate.*"]);
+
+        // Literal patterns aren't regular expressions
+        assert!(grep("^target", false).unwrap().is_empty());
+        let found = grep("^(target|node_modules)/$", true).unwrap();
+        let keys: Vec<_> = found.iter().map(|found| found.key.as_str()).collect();
+        assert_eq!(keys, ["node", "rust"]);
+        assert_eq!(found[0].lines, ["node_modules/"]);
+
+        let err = grep("*.tfstate", true).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Invalid pattern '*.tfstate': repetition operator missing expression at position 1"
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn checks_coverage_of_detected_templates() {
+        let core = temp_core("coverage");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"debug/\ntarget/\n*.pdb\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\nnpm-debug.log*\n"},"python":{"key":"python","name":"Python","fileName":"Python.gitignore","contents":"__pycache__/\n*.py[cod]\n"}}"#,
+        )
+        .unwrap();
+        let existing = "### python ###\n### end python ###\n\n/target\ndebug\n.env\n";
+        let detected: Vec<String> = ["node", "python", "Rust", "elm"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        let checked = core.check_coverage(&detected, existing).unwrap();
+        let coverage: Vec<_> = checked
+            .iter()
+            .map(|checked| (checked.template.as_str(), checked.coverage))
+            .collect();
+        assert_eq!(
+            coverage,
+            [
+                ("node", Coverage::Missing),
+                ("python", Coverage::Written),
+                ("Rust", Coverage::Patterns),
+                ("elm", Coverage::Missing),
+            ]
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn explains_lines_from_several_templates() {
+        let core = temp_core("explain");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"python":{"key":"python","name":"Python","fileName":"Python.gitignore","contents":"__pycache__/\npip-wheel-metadata/\n.env\n"},"jupyternotebooks":{"key":"jupyternotebooks","name":"JupyterNotebooks","fileName":"JupyterNotebooks.gitignore","contents":".ipynb_checkpoints\n.env  \n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"#,
+        )
+        .unwrap();
+        let keys = |explanation: &Explanation| -> Vec<String> {
+            explanation
+                .templates
+                .iter()
+                .map(|found| found.key.clone())
+                .collect()
+        };
+
+        // Exact matches win over ones with different whitespace
+        let explanation = core.explain(".env", true).unwrap();
+        assert_eq!(keys(&explanation), ["python"]);
+        assert!(!explanation.trimmed);
+
+        let explanation = core.explain("  .env ", true).unwrap();
+        assert_eq!(keys(&explanation), ["jupyternotebooks", "python"]);
+        assert!(explanation.trimmed);
+        assert_eq!(explanation.templates[0].kind, Origin::Template);
+
+        let explanation = core.explain("dist/", true).unwrap();
+        assert!(explanation.templates.is_empty());
+        assert!(!explanation.trimmed);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn stats_without_cache() {
+        let mut core = temp_core("stats-empty");
+        let mut config: Config = toml::from_str("[aliases]\n[templates]\n").unwrap();
+        config.aliases.insert("web".into(), vec!["node".into()]);
+        core.config = Some(config);
+
+        let stats = core.stats().unwrap();
+        assert_eq!(stats.templates, 0);
+        assert_eq!(stats.aliases, 1);
+        assert_eq!(stats.user_templates, 0);
+        assert!(stats.sources.is_empty());
+        assert!(stats.updated.is_none());
+    }
+
+    #[test]
+    fn stats_count_templates_per_source() {
+        let core = temp_core("stats-sources");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/"}}"#,
+        )
+        .unwrap();
+
+        let stats = core.stats().unwrap();
+        assert_eq!(stats.templates, 2);
+        assert_eq!(stats.sources.values().sum::<usize>(), 2);
+        assert!(stats.cache_size > 0);
+        assert!(stats.config.is_none());
+
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn templates_are_read_lazily() {
+        let core = temp_core("lazy");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/"}}"#,
+        )
+        .unwrap();
+        core.parse_cache().unwrap().unwrap();
+        std::fs::remove_file(core.templates_dir.join("node.gz")).unwrap();
+
+        let output = core
+            .get_templates(&["rust".to_string()], true)
+            .unwrap()
+            .combined;
+        assert!(
+            output.ends_with("\n### rust ###\ntarget/\n### end rust ###\n\n# git-ignore: rust\n")
+        );
+        let list = core
+            .list(&[], true, Matching::Substring, &[], false, false)
+            .unwrap();
+        assert!(list.contains("node"));
+        assert!(core.get_templates(&["node".to_string()], true).is_err());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn traces_resolution() {
+        let mut core = temp_core("trace-resolve");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config
+            .aliases
+            .insert("web".into(), vec!["node".into(), "elm".into()]);
+        core.config = Some(config);
+
+        let names = ["rust", "web", "zig"].map(String::from);
+        let logged = log::capture(1, || {
+            core.get_templates(&names, false).unwrap();
+        });
+        assert_eq!(
+            logged,
+            "Debug: 'rust' found in the cache\n\
+             Debug: 'web' is an alias for node, elm\n\
+             Debug: 'node' found in the cache\n\
+             Debug: 'elm' not found in the cache\n\
+             Debug: 'zig' not found in the cache\n"
+        );
+        assert_eq!(
+            log::capture(0, || {
+                core.get_templates(&names, false).unwrap();
+            }),
+            ""
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn alias_members_stay_on_their_own_lines() {
+        let mut generated = Generated::default();
+        let templates = [
+            ("rust", "target/".to_string()),
+            ("empty", "\n".to_string()),
+            ("node", "\nnode_modules/".to_string()),
+        ];
+        generated.push("web", Origin::Alias, &templates, false);
+        assert_eq!(generated.templates[0].content, "target/\nnode_modules/\n");
+        assert_eq!(generated.combined, "target/\n\nnode_modules/\n");
+    }
+
+    #[test]
+    fn resolves_templates_and_aliases() {
+        let mut core = temp_core("resolve");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config
+            .aliases
+            .insert("web".into(), vec!["node".into(), "elm".into()]);
+        core.config = Some(config);
+
+        let names = ["rust", "web", "zig"].map(String::from);
+        let generated = core.get_templates(&names, false).unwrap();
+
+        let resolved: Vec<_> = generated
+            .templates
+            .iter()
+            .map(|t| (t.key.as_str(), t.source, t.content.as_str()))
+            .collect();
+        assert_eq!(
+            resolved,
+            vec![
+                ("rust", Origin::Template, "target/\n"),
+                ("web", Origin::Alias, "node_modules/\n"),
+            ]
+        );
+        let unresolved: Vec<_> = generated
+            .unresolved
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(unresolved, ["'elm' from the alias 'web'", "'zig'"]);
+        assert_eq!(
+            serde_json::to_value(&generated.unresolved).unwrap(),
+            serde_json::json!(["elm", "zig"])
+        );
+        assert_eq!(
+            generated.combined,
+            "\n\n### Created by https://www.gitignore.io\n\n### rust ###\ntarget/\n### end rust ###\n\n### node ###\nnode_modules/\n### end node ###\n\n# git-ignore: rust,node\n"
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn excludes_templates_after_expanding() {
+        let mut core = temp_core("exclude");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"},"deno":{"key":"deno","name":"Deno","fileName":"Deno.gitignore","contents":".deno/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config
+            .aliases
+            .insert("web".into(), vec!["node".into(), "deno".into()]);
+        core.config = Some(config);
+        core.exclude = vec!["deno".into(), "zig".into()];
+
+        let names = ["web", "rust"].map(String::from);
+        let generated = core.get_templates(&names, false).unwrap();
+        assert_eq!(generated.keys(), ["node", "rust"]);
+        assert_eq!(generated.excluded(), ["deno"]);
+        assert!(generated.unresolved.is_empty());
+        assert!(!generated.combined.contains(".deno/"));
+
+        core.exclude = vec!["rust".into()];
+        let names = ["Rust", "node"].map(String::from);
+        let generated = core.get_templates(&names, false).unwrap();
+        assert_eq!(generated.keys(), ["node"]);
+        assert_eq!(generated.excluded(), ["rust"]);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn resolves_names_without_reading_them() {
+        let mut core = temp_core("resolve-names");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"},"deno":{"key":"deno","name":"Deno","fileName":"Deno.gitignore","contents":".deno/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config.aliases.insert(
+            "web".into(),
+            vec!["Node".into(), "deno".into(), "elm".into()],
+        );
+        config.templates.insert("work".into(), "missing.txt".into());
+        core.config = Some(config);
+        core.exclude = vec!["deno".into(), "go".into()];
+
+        let names = ["web", "Work", "rust", "go", "zig"].map(String::from);
+        let resolved = core.resolve(&names, false).unwrap();
+        let resolution =
+            |name: &str, kind, templates: &[&str], excluded: &[&str], missing: &[&str]| {
+                let strings = |s: &[&str]| s.iter().map(ToString::to_string).collect();
+                Resolution {
+                    name: name.to_string(),
+                    kind,
+                    templates: strings(templates),
+                    excluded: strings(excluded),
+                    missing: strings(missing),
+                    user_templates: Vec::new(),
+                    detected: false,
+                    default: false,
+                    profile: None,
+                }
+            };
+        assert_eq!(
+            resolved,
+            [
+                resolution("web", Some(Origin::Alias), &["node"], &["deno"], &["elm"]),
+                resolution("Work", Some(Origin::User), &["work"], &[], &[]),
+                resolution("rust", Some(Origin::Template), &["rust"], &[], &[]),
+                resolution("go", None, &[], &["go"], &[]),
+                resolution("zig", None, &[], &[], &[]),
+            ]
+        );
+
+        // Aliases and user templates are ignored with `--simple`
+        let resolved = core.resolve(&["web".into()], true).unwrap();
+        assert_eq!(resolved, [resolution("web", None, &[], &[], &[])]);
+        core.clear_cache().unwrap();
+    }
+
+    fn expand(aliases: &[(&str, &[&str])], name: &str) -> Result<Resolution> {
+        let aliases: HashMap<String, Vec<String>> = aliases
+            .iter()
+            .map(|(name, members)| {
+                let members = members.iter().map(ToString::to_string).collect();
+                (name.to_string(), members)
+            })
+            .collect();
+        let cached = |key: &str| ["rust", "node", "java", "kotlin", "gradle"].contains(&key);
+        let excluded = |key: &str| key == "deno";
+
+        let mut resolution = Resolution::default();
+        expand_alias(
+            &mut vec![name.to_string()],
+            &aliases,
+            &HashMap::new(),
+            &cached,
+            &excluded,
+            &mut resolution,
+        )?;
+        Ok(resolution)
+    }
+
+    #[test]
+    fn expands_nested_aliases() {
+        let chain: &[(&str, &[&str])] = &[
+            ("backend", &["rust", "jvm"]),
+            ("jvm", &["java", "Build"]),
+            ("build", &["gradle", "tools"]),
+            ("tools", &["deno", "zig"]),
+        ];
+        let resolution = expand(chain, "backend").unwrap();
+        assert_eq!(resolution.templates, ["rust", "java", "gradle"]);
+        assert_eq!(resolution.excluded, ["deno"]);
+        assert_eq!(resolution.missing, ["zig"]);
+
+        // Shared members are only included once
+        let diamond: &[(&str, &[&str])] = &[
+            ("all", &["web", "jvm"]),
+            ("web", &["node", "base"]),
+            ("jvm", &["java", "base"]),
+            ("base", &["rust", "elm"]),
+        ];
+        let resolution = expand(diamond, "all").unwrap();
+        assert_eq!(resolution.templates, ["node", "rust", "java"]);
+        assert_eq!(resolution.missing, ["elm"]);
+
+        // An alias can still include the template it shadows
+        let shadowing: &[(&str, &[&str])] = &[("node", &["node", "web"]), ("web", &["rust"])];
+        let resolution = expand(shadowing, "node").unwrap();
+        assert_eq!(resolution.templates, ["node", "rust"]);
+    }
+
+    #[test]
+    fn expands_aliases_for_show() {
+        let mut core = temp_core("expand-alias");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/"},"java":{"key":"java","name":"Java","fileName":"Java.gitignore","contents":"*.class"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config
+            .aliases
+            .insert("Backend".into(), vec!["rust".into(), "jvm".into()]);
+        config
+            .aliases
+            .insert("jvm".into(), vec!["java".into(), "kotlin".into()]);
+        // Shadowed by a user template, but still shown
+        config.templates.insert("jvm".into(), "jvm.txt".into());
+        core.config = Some(config);
+        core.exclude = vec!["java".into()];
+
+        let expansion = core.expand_alias("backend").unwrap().unwrap();
+        assert_eq!(expansion.name, "Backend");
+        assert_eq!(expansion.members, ["rust", "jvm"]);
+        assert_eq!(expansion.resolved, ["rust", "java"]);
+        assert_eq!(expansion.missing, ["kotlin"]);
+        assert!(core.expand_alias("jvm").unwrap().is_some());
+        assert!(core.expand_alias("rust").unwrap().is_none());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn reports_alias_cycles() {
+        let cycle: &[(&str, &[&str])] =
+            &[("a", &["rust", "b"]), ("b", &["c"]), ("c", &["node", "B"])];
+        let err = expand(cycle, "a").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The alias 'b' includes itself: b -> c -> b"
+        );
+
+        let err = expand(&[("a", &["b"]), ("b", &["a"])], "a").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The alias 'a' includes itself: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn shows_single_templates() {
+        let mut core = temp_core("show");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"\n# Build\ntarget/\n\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config
+            .aliases
+            .insert("web".into(), vec!["node".into(), "rust".into()]);
+        core.config = Some(config);
+        // Nothing but the template itself, whatever the output options are
+        core.section_headers = false;
+        core.compact = true;
+
+        let shown = core.show("Rust", false).unwrap();
+        assert_eq!(shown.templates[0].source, Origin::Template);
+        assert_eq!(shown.combined, "# Build\ntarget/\n");
+
+        let shown = core.show("web", false).unwrap();
+        assert_eq!(shown.templates[0].source, Origin::Alias);
+        assert_eq!(shown.keys(), ["node", "rust"]);
+        assert_eq!(
+            shown.combined,
+            "### node ###\nnode_modules/\n### end node ###\n\n### rust ###\n# Build\ntarget/\n### end rust ###\n"
+        );
+
+        let shown = core.show("zig", false).unwrap();
+        assert!(shown.templates.is_empty());
+        assert_eq!(shown.unresolved[0].name, "zig");
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn missing_template_files_report_where_they_were_expected() {
+        let core = temp_core("template-show");
+        let dir = core.cache_dir().to_path_buf();
+        let mut config: Config =
+            toml::from_str("[aliases]\n[templates]\nwork = 'work.txt'\ngone = 'gone.txt'\n")
+                .unwrap();
+        config.templates_dir = dir.join("templates");
+        std::fs::create_dir_all(&config.templates_dir).unwrap();
+        std::fs::write(config.templates_dir.join("work.txt"), "secrets/\n").unwrap();
+
+        config.show_template("WORK").unwrap();
+        config.list_templates(false).unwrap();
+        config.list_templates(true).unwrap();
+
+        let err = config.show_template("gone").unwrap_err().to_string();
+        let expected = config.templates_dir.join("gone.txt");
+        assert!(err.contains(&expected.display().to_string()), "{}", err);
+        assert!(config.show_template("nope").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn templates_from_gitignores_leave_out_what_we_added() {
+        let generated = "secrets/\n\n# >>> git-ignore >>>\n\n\n### Created by https://www.gitignore.io\n### Generated from the built-in fallback templates, run `git ignore -u` to get the latest\n\n### rust ###\ntarget/\n### end rust ###\n\n# git-ignore: rust\n# <<< git-ignore <<<\n";
+        assert_eq!(strip_generated(generated), "secrets/\n\ntarget/\n");
+        assert_eq!(strip_generated("# git-ignore: rust\n\n"), "");
+
+        // Only the headers of sections we closed are ours
+        let sections =
+            "### node ###\n### Node ###\nnode_modules/\n### end node ###\n\n### Mine ###\n*.log\n";
+        assert_eq!(
+            strip_generated(sections),
+            "### Node ###\nnode_modules/\n\n### Mine ###\n*.log\n"
+        );
+
+        let dir =
+            std::env::temp_dir().join(format!("git-ignore-from-gitignore-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(".gitignore");
+        let err = template_from_gitignore(&file).unwrap_err().to_string();
+        assert!(err.starts_with("There is no"), "{}", err);
+
+        std::fs::write(&file, "\n").unwrap();
+        let err = template_from_gitignore(&file).unwrap_err().to_string();
+        assert!(err.contains("is empty"), "{}", err);
+        std::fs::write(
+            &file,
+            "### Created by https://www.gitignore.io\n# git-ignore: go\n",
+        )
+        .unwrap();
+        let err = template_from_gitignore(&file).unwrap_err().to_string();
+        assert!(
+            err.contains("only has what git ignore generated"),
+            "{}",
+            err
+        );
+
+        std::fs::write(&file, generated).unwrap();
+        let template = template_from_gitignore(&file).unwrap();
+        assert!(template.contents.starts_with("secrets/\n"));
+        assert_eq!(template.source, file.display().to_string());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_normalized_legacy_aliases() {
+        let mut core = temp_core("legacy-aliases");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"#,
+        )
+        .unwrap();
+        let path = core.cache_dir().join("config.toml");
+        std::fs::write(
+            &path,
+            "[aliases]\nWeb = [' Node ', '', 'NODE', 'Rust']\nweb = ['rust', 'ELM']\n[templates]\n",
+        )
+        .unwrap();
+        core.config = Config::load(Some(&path)).unwrap();
+
+        let generated = core.get_templates(&["web".into()], false).unwrap();
+        assert_eq!(generated.keys(), ["node", "rust"]);
+        let unresolved: Vec<_> = generated
+            .unresolved
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(unresolved, ["'elm' from the alias 'web'"]);
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn resolves_names_ignoring_case() {
+        let mut core = temp_core("case");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"},"visualstudiocode":{"key":"visualstudiocode","name":"VisualStudioCode","fileName":"VisualStudioCode.gitignore","contents":".vscode/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config.path = core.cache_dir().join("config.toml");
+        // Added by older versions with whatever case they were given
+        config
+            .aliases
+            .insert("Web".into(), vec!["Node".into(), "ELM".into()]);
+        config
+            .add_alias("JS".into(), vec!["NODE".into()], false, Some(false))
+            .unwrap();
+        assert_eq!(config.aliases["js"], ["node"]);
+        core.config = Some(config);
+
+        let names = requested_names(
+            ["Rust", "VisualStudioCode", "RUST"]
+                .map(String::from)
+                .into(),
+            vec!["rust".into()],
+        );
+        assert_eq!(names, ["Rust", "VisualStudioCode"]);
+        let generated = core.get_templates(&names, false).unwrap();
+        assert!(generated.unresolved.is_empty());
+        assert_eq!(generated.keys(), ["rust", "visualstudiocode"]);
+
+        let names = ["WEB", "js", "Zig"].map(String::from);
+        let generated = core.get_templates(&names, false).unwrap();
+        let keys: Vec<_> = generated.templates.iter().map(|t| t.key.as_str()).collect();
+        assert_eq!(keys, ["web", "js"]);
+        assert_eq!(generated.keys(), ["node"]);
+        // Errors keep the case the names were given in
+        let unresolved: Vec<_> = generated
+            .unresolved
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(unresolved, ["'ELM' from the alias 'WEB'", "'Zig'"]);
+
+        let list = core
+            .list(&["WEB".into()], false, Matching::Exact, &[], false, true)
+            .unwrap();
+        assert_eq!(list, "Web\n");
+        let list = core
+            .list(
+                &["VisualStudio".into()],
+                false,
+                Matching::Substring,
+                &[],
+                false,
+                true,
+            )
+            .unwrap();
+        assert_eq!(list, "visualstudiocode\n");
+        let list = core
+            .list(&["We".into()], false, Matching::Substring, &[], false, true)
+            .unwrap();
+        assert_eq!(list, "Web\n");
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn sections_replace_template_headers() {
+        assert_eq!(
+            section("rust", "\n### Rust ###\ntarget/\n"),
+            "### rust ###\ntarget/\n### end rust ###"
+        );
+        assert_eq!(
+            section("mine", "build/"),
+            "### mine ###\nbuild/\n### end mine ###"
+        );
+        assert_eq!(
+            section("empty", "\n### empty ###\n"),
+            "### empty ###\n### end empty ###"
+        );
+    }
+
+    #[test]
+    fn section_headers_can_be_disabled() {
+        let mut core = temp_core("no-sections");
+        core.section_headers = false;
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r##"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"# Generated by Cargo\ntarget/"},
+                "node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"\n### Node ###\nnode_modules/\n"},
+                "go":{"key":"go","name":"Go","fileName":"Go.gitignore","contents":"vendor/\n\n"}}"##,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config
+            .aliases
+            .insert("backend".into(), vec!["go".into(), "node".into()]);
+        core.config = Some(config);
+
+        let generated = core.get_templates(&["rust".to_string()], false).unwrap();
+        assert_eq!(
+            generated.combined,
+            "\n\n### Created by https://www.gitignore.io\n\n# Generated by Cargo\ntarget/\n\n# git-ignore: rust\n"
+        );
+
+        let names = ["rust", "backend"].map(String::from);
+        let generated = core.get_templates(&names, false).unwrap();
+        assert_eq!(
+            generated.combined,
+            "\n\n### Created by https://www.gitignore.io\n\n# Generated by Cargo\ntarget/\n\nvendor/\n\n### Node ###\nnode_modules/\n\n# git-ignore: rust,go,node\n"
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn single_template_layout() {
+        let core = temp_core("single-layout");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r##"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"# Generated by Cargo\ntarget/"}}"##,
+        )
+        .unwrap();
+
+        let generated = core.get_templates(&["rust".to_string()], true).unwrap();
+        assert_eq!(
+            generated.combined,
+            "\n\n### Created by https://www.gitignore.io\n\n### rust ###\n# Generated by Cargo\ntarget/\n### end rust ###\n\n# git-ignore: rust\n"
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn compact_strips_comments_and_blank_lines() {
+        let node = include_str!("../data/fallback/Node.gitignore");
+        let compacted = compact(&format!("\n\n{}\n### node ###\n{}", ATTRIBUTION, node));
+        assert!(compacted
+            .starts_with("### Created by https://www.gitignore.io\nlogs\n*.log\nnpm-debug.log*\n"));
+        assert!(compacted.contains("*.pid.lock\n\ncoverage\n*.lcov\n\n.nyc_output\n"));
+        assert!(!compacted.contains("\n\n\n"));
+        assert!(compacted.lines().skip(1).all(|line| !line.starts_with('#')));
+
+        let python = include_str!("../data/fallback/Python.gitignore");
+        let compacted = compact(python);
+        assert!(compacted.starts_with("__pycache__/\n*.py[cod]\n*$py.class\n\n*.so\n"));
+        assert!(!compacted.contains("Created by"));
+    }
+
+    #[test]
+    fn compact_keeps_negations_and_escaped_hashes() {
+        let contents =
+            "# Build output\nbuild/\n!build/keep\n\\#notes#\n\n\n\n# Docs\n  # indented\n";
+        assert_eq!(
+            compact(contents),
+            "build/\n!build/keep\n\\#notes#\n\n  # indented\n"
+        );
+    }
+
+    #[test]
+    fn compact_applies_to_generated_output() {
+        let mut core = temp_core("compact");
+        core.compact = true;
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"\n### Rust ###\n# Cargo output\ntarget/\n"}}"#,
+        )
+        .unwrap();
+
+        let generated = core.get_templates(&["rust".to_string()], true).unwrap();
+        assert_eq!(
+            generated.combined,
+            "### Created by https://www.gitignore.io\n\ntarget/\n\n# git-ignore: rust\n"
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn plain_list_has_bare_names() {
+        let mut core = temp_core("plain-list");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config.aliases.insert("web".into(), vec!["node".into()]);
+        core.config = Some(config);
+
+        let list = core
+            .list(&[], false, Matching::Substring, &[], false, true)
+            .unwrap();
+        assert_eq!(list, "node\nrust\nweb\n");
+        let list = core
+            .list(&["ru".into()], false, Matching::Substring, &[], false, true)
+            .unwrap();
+        assert_eq!(list, "rust\n");
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn searches_display_names() {
+        let core = temp_core("display-names");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"visualstudiocode":{"key":"visualstudiocode","name":"Visual Studio Code","fileName":"VisualStudioCode.gitignore","contents":".vscode/\n"},"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"}}"#,
+        )
+        .unwrap();
+
+        let list = core
+            .list(
+                &["Visual Studio".into()],
+                true,
+                Matching::Substring,
+                &[],
+                false,
+                true,
+            )
+            .unwrap();
+        assert_eq!(list, "visualstudiocode\n");
+        let list = core
+            .list(
+                &["RUST".into()],
+                true,
+                Matching::Substring,
+                &[],
+                false,
+                true,
+            )
+            .unwrap();
+        assert_eq!(list, "rust\n");
+        let list = core
+            .list(
+                &["code".into()],
+                true,
+                Matching::Substring,
+                &[],
+                false,
+                true,
+            )
+            .unwrap();
+        assert_eq!(list, "visualstudiocode\n");
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn lists_exact_and_prefix_matches() {
+        let core = temp_core("exact");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"c":{"key":"c","name":"C","fileName":"C.gitignore","contents":"*.o\n"},"c++":{"key":"c++","name":"C++","fileName":"C++.gitignore","contents":"*.o\n"},"clojure":{"key":"clojure","name":"Clojure","fileName":"Clojure.gitignore","contents":".cpcache/\n"},"objective-c":{"key":"objective-c","name":"Objective-C","fileName":"Objective-C.gitignore","contents":"xcuserdata/\n"}}"#,
+        )
+        .unwrap();
+        let list = |names: &[&str], matching| {
+            let names: Vec<String> = names.iter().map(ToString::to_string).collect();
+            core.list(&names, true, matching, &[], false, true).unwrap()
+        };
+
+        assert_eq!(
+            list(&["c"], Matching::Substring),
+            "c\nc++\nclojure\nobjective-c\n"
+        );
+        assert_eq!(list(&["c"], Matching::Exact), "c\n");
+        assert_eq!(list(&["C++"], Matching::Exact), "c++\n");
+        assert_eq!(list(&["Clojure", "c"], Matching::Exact), "c\nclojure\n");
+        assert_eq!(list(&["clo"], Matching::Exact), "");
+        assert_eq!(list(&["c"], Matching::Prefix), "c\nc++\nclojure\n");
+        assert_eq!(list(&["c+"], Matching::Prefix), "c++\n");
+        assert_eq!(list(&["Objective"], Matching::Prefix), "objective-c\n");
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn compiles_regex_patterns() {
+        let matches = |pattern: &str, text: &str| compile_regex(pattern).unwrap().is_match(text);
+        let error = |pattern: &str| compile_regex(pattern).unwrap_err().to_string();
+
+        // Patterns match anywhere unless anchored
+        assert!(matches("ust", "rust"));
+        assert!(matches("^c", "cmake"));
+        assert!(!matches("^c", "objective-c"));
+        assert!(matches("lang$", "erlang"));
+        assert!(!matches("lang$", "golang-extra"));
+        assert!(matches("^c$", "c"));
+        assert!(!matches("^c$", "cmake"));
+        assert!(matches(r"\bc\b", "objective-c"));
+        assert!(matches(r"^\w+\d$", "python3"));
+
+        // Case matters unless the `i` flag is set, wherever it is
+        assert!(!matches("rust", "Rust"));
+        assert!(matches("(?i)rust", "Rust"));
+        assert!(matches("^visual(?i:studio)$", "visualSTUDIO"));
+        assert!(!matches("^[a-z]+$", "VisualStudio"));
+
+        assert_eq!(
+            error("c[a-"),
+            "Invalid pattern 'c[a-': unclosed character class at position 2"
+        );
+        assert_eq!(
+            error("(rust"),
+            "Invalid pattern '(rust': unclosed group at position 1"
+        );
+        assert!(error("rust)").ends_with("at position 5"));
+        assert!(error("*rust").ends_with("at position 1"));
+        assert!(error("[z-a]").ends_with("at position 2"));
+        assert!(error(r"\q").ends_with("at position 1"));
+    }
+
+    #[test]
+    fn lists_regex_matches() {
+        let core = temp_core("regex");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"c":{"key":"c","name":"C","fileName":"C.gitignore","contents":"*.o\n"},"clojure":{"key":"clojure","name":"Clojure","fileName":"Clojure.gitignore","contents":".cpcache/\n"},"erlang":{"key":"erlang","name":"Erlang","fileName":"Erlang.gitignore","contents":"ebin/\n"},"objective-c":{"key":"objective-c","name":"Objective-C","fileName":"Objective-C.gitignore","contents":"xcuserdata/\n"}}"#,
+        )
+        .unwrap();
+        let list = |names: &[&str]| {
+            let names: Vec<String> = names.iter().map(ToString::to_string).collect();
+            core.list(&names, true, Matching::Regex, &[], false, true)
+        };
+
+        assert_eq!(list(&["^c"]).unwrap(), "c\nclojure\n");
+        assert_eq!(list(&["lang$", "^c$"]).unwrap(), "c\nerlang\n");
+        // Display names are matched as they are
+        assert_eq!(list(&["^Obj"]).unwrap(), "objective-c\n");
+        assert_eq!(list(&["^OBJ"]).unwrap(), "");
+        assert_eq!(list(&["(?i)^OBJ"]).unwrap(), "objective-c\n");
+
+        let err = list(&["c[", "^c", "(erlang"]).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Invalid pattern 'c[': unclosed character class at position 2\n\
+             Invalid pattern '(erlang': unclosed group at position 1"
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn lists_only_given_kinds() {
+        let mut core = temp_core("list-kinds");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config
+            .aliases
+            .insert("web".into(), vec!["node".into(), "deno".into()]);
+        config.templates.insert("work".into(), "work.txt".into());
+        core.config = Some(config);
+        let list = |names: &[&str], kinds: &[Origin]| {
+            let names: Vec<String> = names.iter().map(ToString::to_string).collect();
+            core.list(&names, false, Matching::Substring, kinds, false, true)
+                .unwrap()
+        };
+
+        assert_eq!(list(&[], &[]), "node\nrust\nweb\nwork\n");
+        assert_eq!(list(&[], &[Origin::Alias]), "web\n");
+        assert_eq!(list(&[], &[Origin::User, Origin::Alias]), "web\nwork\n");
+        assert_eq!(list(&[], &[Origin::Template]), "node\nrust\n");
+        assert_eq!(list(&["w"], &[Origin::User]), "work\n");
+        assert_eq!(list(&["rust"], &[Origin::Alias]), "");
+
+        let entries = core
+            .list_entries(&[], false, Matching::Substring, &[Origin::Alias])
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].targets, ["node", "deno"]);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn lists_entries_with_their_kind() {
+        let mut core = temp_core("list-json");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config
+            .aliases
+            .insert("node".into(), vec!["node".into(), "deno".into()]);
+        config.templates.insert("work".into(), "work.txt".into());
+        core.config = Some(config);
+
+        let entries = core
+            .list_entries(&[], false, Matching::Substring, &[])
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&entries).unwrap(),
+            r#"[{"key":"node","kind":"alias","targets":["node","deno"],"shadows":true},{"key":"rust","kind":"template","name":"Rust"},{"key":"work","kind":"user"}]"#
+        );
+        assert_eq!(
+            long_list(&entries),
+            "node  alias     node, deno *shadows remote*\nrust  template  Rust\nwork  user\n"
+        );
+
+        let entries = core
+            .list_entries(&["r".into()], false, Matching::Substring, &[])
+            .unwrap();
+        let keys: Vec<_> = entries.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["rust", "work"]);
+        assert_eq!(core.shadows_template("Node"), Some(true));
+        assert_eq!(core.shadows_template("work"), Some(false));
+        core.clear_cache().unwrap();
+        assert_eq!(core.shadows_template("node"), None);
+    }
+
+    #[test]
+    fn builtin_aliases_can_be_replaced_or_disabled() {
+        let mut core = temp_core("builtin-aliases");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"macos":{"key":"macos","name":"macOS","fileName":"macOS.gitignore","contents":".DS_Store\n"},"linux":{"key":"linux","name":"Linux","fileName":"Linux.gitignore","contents":"*~\n"}}"#,
+        )
+        .unwrap();
+        let oses = |core: &Core| core.resolve(&["oses".into()], false).unwrap().remove(0);
+
+        // Used without a config
+        let resolution = oses(&core);
+        assert_eq!(resolution.kind, Some(Origin::Alias));
+        assert_eq!(resolution.templates, ["macos", "linux"]);
+        assert_eq!(resolution.missing, ["windows"]);
+        let entries = core
+            .list_entries(&["oses".into()], false, Matching::Exact, &[])
+            .unwrap();
+        assert_eq!(entries[0].source.as_deref(), Some(BUILTIN_SOURCE));
+        assert!(core.resolve(&["oses".into()], true).unwrap()[0]
+            .kind
+            .is_none());
+
+        // An alias in the config replaces it
+        let mut config = config_with_ttl(30);
+        config.builtin_aliases = None;
+        config.aliases.insert("OSes".into(), vec!["linux".into()]);
+        core.config = Some(config);
+        assert_eq!(oses(&core).templates, ["linux"]);
+        assert!(!core.builtin_aliases(false).contains_key("oses"));
+        assert!(core.builtin_aliases(false).contains_key("editors"));
+        let entries = core
+            .list_entries(&["oses".into()], false, Matching::Exact, &[])
+            .unwrap();
+        assert_eq!(entries[0].source, None);
+        assert_eq!(core.expand_alias("oses").unwrap().unwrap().name, "OSes");
+
+        // Or all of them can be turned off
+        let config = core.config.as_mut().unwrap();
+        config.aliases.clear();
+        config.builtin_aliases = Some(false);
+        assert!(oses(&core).kind.is_none());
+        assert!(core.expand_alias("oses").unwrap().is_none());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn list_has_no_escapes_without_colors() {
+        let mut core = temp_core("no-colors");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"}}"#,
+        )
+        .unwrap();
+        let mut config = config_with_ttl(30);
+        config.aliases.insert("web".into(), vec!["node".into()]);
+        config.templates.insert("work".into(), "work.txt".into());
+        core.config = Some(config);
+
+        // What `--color never` and piped output set up in `main`
+        colored::control::set_override(false);
+        let list = core
+            .list(&[], false, Matching::Substring, &[], false, false)
+            .unwrap();
+        assert_eq!(list, "  rust\n  web → node\n  work\n");
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn list_entries_are_formatted_in_one_place() {
+        let alias = Type::Alias("web".into());
+        let origin = "github".to_string();
+        assert_eq!(list_entry(&alias, None, &[], false, true), "web");
+        assert_eq!(
+            list_entry(&alias, Some(&origin), &[], false, true),
+            "web\tgithub"
+        );
+        assert!(list_entry(&alias, Some(&origin), &[], false, false).starts_with("  "));
+        // Only marked when it's not meant for scripts
+        assert!(list_entry(&alias, None, &[], true, false).contains("*shadows remote*"));
+        assert_eq!(list_entry(&alias, None, &[], true, true), "web");
+
+        let targets = ["node".to_string(), "deno".to_string()];
+        assert!(list_entry(&alias, None, &targets, false, false).contains("→ node, deno"));
+        assert_eq!(list_entry(&alias, None, &targets, false, true), "web");
+    }
+
+    #[test]
+    fn shortens_long_alias_targets() {
+        let targets: Vec<String> = ["node", "deno", "yarn", "visualstudiocode"]
+            .map(String::from)
+            .into();
+        assert_eq!(
+            shorten_targets(&targets, 40),
+            "node, deno, yarn, visualstudiocode"
+        );
+        assert_eq!(shorten_targets(&targets, 20), "node, deno, yarn, …");
+        assert_eq!(shorten_targets(&targets, 16), "node, deno, …");
+        assert_eq!(shorten_targets(&targets[3..], 10), "…");
+        assert_eq!(shorten_targets(&targets[..1], 4), "node");
+    }
+
+    #[test]
+    fn footer_can_be_disabled() {
+        let mut core = temp_core("no-footer");
+        core.footer = false;
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"}}"#,
+        )
+        .unwrap();
+
+        let generated = core.get_templates(&["rust".to_string()], true).unwrap();
+        assert_eq!(
+            generated.combined,
+            "\n\n### Created by https://www.gitignore.io\n\n### rust ###\ntarget/\n### end rust ###\n"
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn syncs_generated_block() {
+        let rust = |contents: &str| {
+            format!(
+                r#"{{"rust":{{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"{}"}}}}"#,
+                contents
+            )
+        };
+        let core = temp_core("sync");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, rust("target/\\n")).unwrap();
+
+        let repo = core.cache_dir().join("repo");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        let file = repo.join(".gitignore");
+        std::fs::write(&file, "*.log\n").unwrap();
+        let generated = core.get_templates(&["rust".into()], false).unwrap();
+        let change = plan_write(&file, &generated.combined, Existing::Append, true).unwrap();
+        apply(&file, &change, false).unwrap();
+        std::fs::write(&file, std::fs::read_to_string(&file).unwrap() + ".env\n").unwrap();
+
+        let (keys, change) = core.plan_sync(&file).unwrap();
+        assert_eq!(keys, ["rust"]);
+        assert!(change.is_empty());
+
+        // The template changed upstream
+        core.clear_cache().unwrap();
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, rust("target/\\n**/*.rs.bk\\n")).unwrap();
+        let (_, change) = core.plan_sync(&file).unwrap();
+        assert_eq!(change.written, Written::Replaced);
+        assert!(!change.is_empty());
+        apply(&file, &change, false).unwrap();
+
+        let synced = std::fs::read_to_string(&file).unwrap();
+        assert!(synced.starts_with("*.log\n# >>> git-ignore >>>\n"));
+        assert!(synced.contains("target/\n**/*.rs.bk\n"));
+        assert!(synced.ends_with("# git-ignore: rust\n# <<< git-ignore <<<\n.env\n"));
+        assert!(core.plan_sync(&file).unwrap().1.is_empty());
+
+        std::fs::write(&file, "*.log\n").unwrap();
+        let err = core.plan_sync(&file).unwrap_err();
+        assert!(err.to_string().contains("no generated block"));
+
+        std::fs::write(
+            &file,
+            "# >>> git-ignore >>>\ntarget/\n# <<< git-ignore <<<\n",
+        )
+        .unwrap();
+        let err = core.plan_sync(&file).unwrap_err();
+        assert!(err.to_string().contains("--no-footer"));
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn nothing_to_generate_is_an_error() {
+        let core = temp_core("no-content");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},"empty":{"key":"empty","name":"Empty","fileName":"Empty.gitignore","contents":"\n# Nothing\n"}}"#,
+        )
+        .unwrap();
+
+        let generated = core
+            .get_templates(&["rsut".into(), "nod".into()], true)
+            .unwrap();
+        let err = generated.ensure_content(false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Could not find rsut, nod, nothing was generated"
+        );
+
+        // Autodetection finding nothing with `--write`
+        let names = requested_names(Vec::new(), Vec::new());
+        let generated = core.get_templates(&names, true).unwrap();
+        assert!(generated.combined.is_empty());
+        let err = generated.ensure_content(true).unwrap_err();
+        assert!(err.to_string().contains("No templates were detected"));
+
+        let generated = core
+            .get_templates(&["empty".into(), "nod".into()], true)
+            .unwrap();
+        assert!(generated.combined.contains(ATTRIBUTION));
+        assert!(generated.ensure_content(false).is_err());
+
+        let generated = core
+            .get_templates(&["rust".into(), "nod".into()], true)
+            .unwrap();
+        assert!(generated.ensure_content(false).is_ok());
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn finds_written_templates() {
+        let mut core = temp_core("written");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r####"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"### Rust ###\ntarget/\n"},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"}}"####,
+        )
+        .unwrap();
+        let file = core.cache_dir().join(".gitignore");
+        std::fs::write(&file, "*.log\n### Notes ###\n").unwrap();
+        assert!(written_templates("*.log\n### Notes ###\n").is_empty());
+
+        // Appending the same template twice only writes it once
+        let rust = core.get_templates(&["rust".into()], true).unwrap();
+        assert!(!written_templates(&std::fs::read_to_string(&file).unwrap()).contains("rust"));
+        let change = plan_write(&file, &rust.combined, Existing::Append, false).unwrap();
+        apply(&file, &change, false).unwrap();
+        let written = written_templates(&std::fs::read_to_string(&file).unwrap());
+        assert_eq!(written, HashSet::from(["rust".to_string()]));
+
+        // A new template isn't written yet
+        core.footer = false;
+        let node = core.get_templates(&["node".into()], true).unwrap();
+        assert!(!written.contains("node"));
+        let change = plan_write(&file, &node.combined, Existing::Append, false).unwrap();
+        apply(&file, &change, false).unwrap();
+        let written = written_templates(&std::fs::read_to_string(&file).unwrap());
+        assert_eq!(written.len(), 2);
+        assert!(written.contains("node"));
+
+        // Without section headers only the footer lists them
+        let contents = "target/\n\n# git-ignore: Rust, node\n";
+        assert_eq!(
+            written_templates(contents),
+            HashSet::from(["rust".to_string(), "node".to_string()])
+        );
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn parses_footer() {
+        let keys = vec!["rust".to_string(), "node".into(), "c++".into()];
+        let contents = format!("target/\n\n{}\n", footer(&keys));
+        assert_eq!(parse_footer(&contents), Some(keys));
+
+        assert_eq!(
+            parse_footer("# git-ignore: go, , zig \r\nvendor/\n"),
+            Some(vec!["go".to_string(), "zig".into()])
+        );
+        assert_eq!(parse_footer("# git-ignore:\n"), Some(vec![]));
+        assert_eq!(parse_footer("target/\n# git-ignore is great\n"), None);
+    }
+
+    #[test]
+    fn converts_line_endings() {
+        let unix = "\n\n### rust ###\ntarget/\n";
+        let windows = "\r\n\r\n### rust ###\r\ntarget/\r\n";
+        let mixed = "\n\n### rust ###\r\ntarget/\n";
+
+        assert_eq!(convert_line_endings(unix, true), windows);
+        assert_eq!(convert_line_endings(windows, true), windows);
+        assert_eq!(convert_line_endings(mixed, true), windows);
+        assert_eq!(convert_line_endings(windows, false), unix);
+        assert_eq!(convert_line_endings(mixed, false), unix);
+        assert_eq!(
+            convert_line_endings(&convert_line_endings(unix, true), false),
+            unix
+        );
+    }
+
+    #[test]
+    fn template_bodies_are_trimmed() {
+        assert_eq!(trim_body("\r\n\ntarget/  \t\r\n\n"), "target/");
+        assert_eq!(trim_body("file\\ \n"), "file\\ ");
+        assert_eq!(trim_body("file\\\n"), "file\\");
+        assert_eq!(trim_body(" \n\n "), "");
+    }
+
+    #[test]
+    fn resolves_output_paths() {
+        let cwd = Path::new("/projects/app");
+        assert_eq!(
+            output_path(cwd, Path::new("api/.gitignore")),
+            Some(PathBuf::from("/projects/app/api/.gitignore"))
+        );
+        assert_eq!(
+            output_path(cwd, Path::new("/srv/.dockerignore")),
+            Some(PathBuf::from("/srv/.dockerignore"))
+        );
+        assert_eq!(output_path(cwd, Path::new("-")), None);
+    }
+
+    #[test]
+    fn writes_output_files() {
+        let core = temp_core("output");
+        let dir = core.cache_dir().join("sub");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join(".dockerignore");
+        assert_eq!(
+            write_to(&file, "target/\n", Existing::Refuse, false, false).unwrap(),
+            Written::Created
+        );
+        let err = write_to(&file, "node_modules/\n", Existing::Refuse, false, false).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(
+            write_to(&file, "node_modules/\n", Existing::Append, false, false).unwrap(),
+            Written::Appended
+        );
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "target/\nnode_modules/\n"
+        );
+
+        let missing = dir.join("missing").join(".gitignore");
+        let err = write_to(&missing, "target/\n", Existing::Append, false, false).unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+        assert!(!missing.exists());
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn finds_repository_root() {
+        let core = temp_core("repo-root");
+        let root = core.cache_dir().join("repo");
+        let deep = root.join("src").join("deep").join("module");
+        std::fs::create_dir_all(&deep).unwrap();
+        assert_eq!(repo_root(&deep, None), None);
+
+        std::fs::create_dir(root.join(".git")).unwrap();
+        assert_eq!(repo_root(&deep, None).as_deref(), Some(root.as_path()));
+        assert_eq!(repo_root(&root, None).as_deref(), Some(root.as_path()));
+
+        // Worktrees and submodules have a `.git` file pointing elsewhere
+        let worktree = root.join("worktree");
+        std::fs::create_dir_all(worktree.join("src")).unwrap();
+        std::fs::write(
+            worktree.join(".git"),
+            "gitdir: ../.git/worktrees/worktree\n",
+        )
+        .unwrap();
+        assert_eq!(
+            repo_root(&worktree.join("src"), None).as_deref(),
+            Some(worktree.as_path())
+        );
+
+        assert_eq!(
+            repo_root(&deep, Some(Path::new("../../../.git"))).as_deref(),
+            Some(deep.join("../../..").as_path())
+        );
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn writes_local_excludes() {
+        let core = temp_core("local-exclude");
+        let repo = core.cache_dir().join("repo");
+        std::fs::create_dir_all(repo.join("src")).unwrap();
+        git(&repo, &["init", "-q"]);
+        std::fs::remove_dir_all(repo.join(".git").join("info")).unwrap();
+
+        let root = repo_root(&repo.join("src"), None).unwrap();
+        let exclude = exclude_file(&root).unwrap();
+        assert_eq!(exclude, repo.join(".git").join("info").join("exclude"));
+        assert_eq!(
+            write_to(&exclude, "target/\n", Existing::Refuse, true, false).unwrap(),
+            Written::Created
+        );
+        assert_eq!(
+            merge_into(&exclude, "target/\n*.log\n", false).unwrap(),
+            Written::Merged(1)
+        );
+        assert_eq!(
+            git(&repo, &["check-ignore", "target/", "debug.log"]),
+            "target/\ndebug.log\n"
+        );
+
+        // Worktrees share the exclude file of the main repository
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        git(&repo, &["worktree", "add", "-q", "../worktree"]);
+        let worktree = core.cache_dir().join("worktree");
+        let root = repo_root(&worktree, None).unwrap();
+        assert_eq!(root, worktree);
+        assert_eq!(
+            exclude_file(&root).unwrap().canonicalize().unwrap(),
+            exclude.canonicalize().unwrap()
+        );
+        assert_eq!(git(&worktree, &["check-ignore", "target/"]), "target/\n");
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn finds_global_excludes() {
+        let home = Path::new("/home/user");
+        assert_eq!(
+            excludes_path(Some("~/.gitignore_global"), None, Some(home)),
+            Some(home.join(".gitignore_global"))
+        );
+        assert_eq!(
+            excludes_path(Some("/etc/gitignore"), None, Some(home)),
+            Some(PathBuf::from("/etc/gitignore"))
+        );
+        assert_eq!(
+            excludes_path(None, Some(PathBuf::from("/xdg")), Some(home)),
+            Some(PathBuf::from("/xdg/git/ignore"))
+        );
+        // Relative XDG paths are invalid and ignored
+        assert_eq!(
+            excludes_path(Some(""), Some(PathBuf::from("xdg")), Some(home)),
+            Some(home.join(".config/git/ignore"))
+        );
+        assert_eq!(excludes_path(None, None, None), None);
+    }
+
+    #[test]
+    fn merging_into_global_excludes_is_idempotent() {
+        let core = temp_core("global-excludes");
+        let path = excludes_path(None, Some(core.cache_dir().to_path_buf()), None).unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let generated = "### macos ###\n.DS_Store\n### end macos ###\n\n### vscode ###\n.vscode/*\n!.vscode/settings.json\n### end vscode ###\n";
+
+        assert_eq!(
+            merge_into(&path, generated, false).unwrap(),
+            Written::Created
+        );
+        let first = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            merge_into(&path, generated, false).unwrap(),
+            Written::Unchanged
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), first);
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn removes_template_patterns() {
+        let existing = "# Mine\n*.log\n__pycache__/\n\n### python ###\n# Byte-compiled\n__pycache__/\n*.py[cod]\n*.log\n### end python ###\n\n### django ###\n*.log\nlocal_settings.py\n### end django ###\n\n# git-ignore: python,django\n";
+        let python =
+            "### python ###\n# Byte-compiled\n__pycache__/\n*.py[cod]\n*.log\n### end python ###\n";
+
+        let (after, count) = remove_patterns(existing, &["python".into()], python);
+        assert_eq!(count, 2);
+        assert_eq!(
+            after,
+            "# Mine\n*.log\n__pycache__/\n\n### python ###\n# Byte-compiled\n*.log\n### end python ###\n\n### django ###\n*.log\nlocal_settings.py\n### end django ###\n\n# git-ignore: django\n"
+        );
+
+        // Once django goes too nothing claims `*.log` and both sections are empty
+        let django = "### django ###\n*.log\nlocal_settings.py\n### end django ###\n";
+        let both = format!("{}\n{}", python, django);
+        let (after, count) = remove_patterns(existing, &["python".into(), "django".into()], &both);
+        assert_eq!(count, 5);
+        assert_eq!(after, "# Mine\n*.log\n__pycache__/\n");
+    }
+
+    #[test]
+    fn removes_patterns_without_sections() {
+        let existing = "*.log\n__pycache__/\n*.py[cod]\n!keep.pyc\n";
+        let (after, count) =
+            remove_patterns(existing, &["python".into()], "__pycache__/\n*.py[cod]\n");
+        assert_eq!(count, 2);
+        assert_eq!(after, "*.log\n!keep.pyc\n");
+
+        let (after, count) = remove_patterns(existing, &["rust".into()], "target/\n");
+        assert_eq!((after.as_str(), count), (existing, 0));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn replaces_files_atomically() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let core = temp_core("atomic");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        let file = core.cache_dir().join(".gitignore");
+        std::fs::write(&file, "*.log\n*.log\n").unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o640)).unwrap();
+        let link = core.cache_dir().join("link");
+        symlink(&file, &link).unwrap();
+
+        replace_atomically(&link, "*.log\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "*.log\n");
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert!(!core.cache_dir().join(".gitignore.tmp").exists());
+
+        assert!(replace_atomically(&core.cache_dir().join("missing"), "").is_err());
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn explains_write_errors() {
+        let path = Path::new("/repo/.gitignore");
+        let error = |kind| {
+            let err = file_error(std::io::Error::from(kind), "write to", path);
+            format!("{:#}", err)
+        };
+
+        assert_eq!(
+            error(ErrorKind::PermissionDenied),
+            "Could not write to '/repo/.gitignore', permission denied, pass '--output' to write somewhere else: permission denied"
+        );
+        assert_eq!(
+            error(ErrorKind::ReadOnlyFilesystem),
+            "Could not write to '/repo/.gitignore', the file system is read-only, check how it's mounted or pass '--output' to write somewhere else: read-only filesystem or storage medium"
+        );
+        assert_eq!(
+            error(ErrorKind::Other),
+            "Could not write to '/repo/.gitignore': other error"
+        );
+
+        // The cause may be wrapped in context already
+        let err = anyhow!(std::io::Error::from(ErrorKind::PermissionDenied)).context("inner");
+        let err = file_error(err, "append to", path);
+        assert!(err
+            .to_string()
+            .ends_with("permission denied, pass '--output' to write somewhere else"));
+    }
+
+    #[test]
+    fn refuses_to_write_to_directories() {
+        let core = temp_core("directory");
+        let dir = core.cache_dir().join(".gitignore");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for existing in [Existing::Refuse, Existing::Append, Existing::Overwrite] {
+            let err = plan_write(&dir, "target/\n", existing, true).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Could not write to '{}', it is a directory, pass '--output' to write to a file instead",
+                    dir.display()
+                )
+            );
+        }
+        assert!(plan_merge(&dir, "target/\n").is_err());
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn plans_changes_without_writing() {
+        let core = temp_core("plan");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        let file = core.cache_dir().join(".gitignore");
+
+        let created = plan_write(&file, "target/\n", Existing::Refuse, false).unwrap();
+        assert_eq!(created.written, Written::Created);
+        assert_eq!(
+            (created.before.as_str(), created.after.as_str()),
+            ("", "target/\n")
+        );
+        assert!(!file.exists());
+
+        std::fs::write(&file, "*.log").unwrap();
+        let appended = plan_write(&file, "target/\n", Existing::Append, false).unwrap();
+        assert_eq!(appended.written, Written::Appended);
+        assert_eq!(appended.after, "*.log\ntarget/\n");
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "*.log");
+        assert_eq!(apply(&file, &appended, false).unwrap(), Written::Appended);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), appended.after);
+
+        let merged = plan_merge(&file, "*.log\nnode_modules/\ntarget/\n").unwrap();
+        assert_eq!(merged.written, Written::Merged(1));
+        assert_eq!(merged.after, "*.log\ntarget/\nnode_modules/\n");
+        assert!(!merged.is_empty());
+
+        let unchanged = plan_merge(&file, "target/\n").unwrap();
+        assert_eq!(unchanged.written, Written::Unchanged);
+        assert!(unchanged.is_empty());
+
+        let removed = plan_remove(&file, &["rust".into()], "target/\n").unwrap();
+        assert_eq!(removed.written, Written::Removed(1));
+        assert_eq!(removed.after, "*.log\n");
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "*.log\ntarget/\n");
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn overwrites_existing_files() {
+        let core = temp_core("overwrite");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        let file = core.cache_dir().join(".gitignore");
+        std::fs::write(&file, "*.log\nnode_modules/\nmore/\nlines/\n").unwrap();
+
+        assert_eq!(
+            write_to(&file, "target/\n", Existing::Overwrite, false, true).unwrap(),
+            Written::Overwritten
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "target/\n");
+        assert_eq!(
+            std::fs::read_to_string(core.cache_dir().join(".gitignore.bak")).unwrap(),
+            "*.log\nnode_modules/\nmore/\nlines/\n"
+        );
+
+        // Overwriting replaces everything, not just the generated block
+        std::fs::write(
+            &file,
+            "*.log\n# >>> git-ignore >>>\nold/\n# <<< git-ignore <<<\n",
+        )
+        .unwrap();
+        assert_eq!(
+            write_to(&file, "target/\n", Existing::Overwrite, true, false).unwrap(),
+            Written::Overwritten
+        );
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "# >>> git-ignore >>>\ntarget/\n# <<< git-ignore <<<\n"
+        );
+
+        let missing = core.cache_dir().join("new.gitignore");
+        assert_eq!(
+            write_to(&missing, "target/\n", Existing::Overwrite, false, false).unwrap(),
+            Written::Created
+        );
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn regenerates_managed_block() {
+        let core = temp_core("managed");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        let file = core.cache_dir().join(".gitignore");
+
+        assert_eq!(
+            write_to(&file, "\n\ntarget/\n", Existing::Refuse, true, false).unwrap(),
+            Written::Created
+        );
+        std::fs::write(
+            &file,
+            format!(
+                "*.log\n\n{}\n.env\n",
+                std::fs::read_to_string(&file).unwrap()
+            ),
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                write_to(&file, "\n\nnode_modules/\n", Existing::Refuse, true, false).unwrap(),
+                Written::Replaced
+            );
+            assert_eq!(
+                std::fs::read_to_string(&file).unwrap(),
+                "*.log\n\n# >>> git-ignore >>>\nnode_modules/\n# <<< git-ignore <<<\n\n.env\n"
+            );
+        }
+
+        std::fs::write(&file, "*.log").unwrap();
+        assert!(write_to(&file, "target/\n", Existing::Refuse, true, false).is_err());
+        assert_eq!(
+            write_to(&file, "target/\n", Existing::Append, true, false).unwrap(),
+            Written::Appended
+        );
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "*.log\n# >>> git-ignore >>>\ntarget/\n# <<< git-ignore <<<\n"
+        );
+
+        std::fs::write(&file, "# >>> git-ignore >>>\ntarget/\n").unwrap();
+        let err = write_to(&file, "target/\n", Existing::Append, true, false).unwrap_err();
+        assert!(format!("{:#}", err).contains("has no '# <<< git-ignore <<<'"));
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn backs_up_before_changing_files() {
+        let core = temp_core("backup");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        let file = core.cache_dir().join(".gitignore");
+        let original = b"*.log\r\n# mine\n\xff\n";
+        std::fs::write(&file, original).unwrap();
+
+        assert_eq!(
+            write_to(&file, "target/\n", Existing::Append, false, true).unwrap(),
+            Written::Appended
+        );
+        let first = core.cache_dir().join(".gitignore.bak");
+        assert_eq!(std::fs::read(&first).unwrap(), original);
+
+        let before = std::fs::read(&file).unwrap();
+        let second = backup_file(&file).unwrap();
+        assert_ne!(second, first);
+        assert!(second.to_string_lossy().ends_with(".bak"));
+        assert_eq!(std::fs::read(&second).unwrap(), before);
+        assert_eq!(std::fs::read(&first).unwrap(), original);
+
+        let third = backup_file(&file).unwrap();
+        assert!(third != first && third != second);
+
+        let err = write_to(&file, "node_modules/\n", Existing::Refuse, false, true).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(std::fs::read_dir(core.cache_dir()).unwrap().count(), 4);
+
+        // The backup's name is too long, so the write must not happen
+        let long = core.cache_dir().join("a".repeat(252));
+        std::fs::write(&long, "*.log\n").unwrap();
+        let err = write_to(&long, "target/\n", Existing::Append, false, true).unwrap_err();
+        assert!(err.to_string().contains("Could not back up"));
+        let err = merge_into(&long, "target/\n", true).unwrap_err();
+        assert!(err.to_string().contains("Could not back up"));
+        assert_eq!(std::fs::read_to_string(&long).unwrap(), "*.log\n");
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn merges_only_missing_patterns() {
+        let core = temp_core("merge");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        let generated = "\n\n### Created by https://www.gitignore.io\n\n### rust ###\n# Build output\ntarget/\n\n# Lockfile\nCargo.lock\n### end rust ###\n\n### node ###\nnode_modules/\n!keep.js\n### end node ###\n\n# git-ignore: rust,node\n";
+
+        let file = core.cache_dir().join(".gitignore");
+        std::fs::write(&file, "").unwrap();
+        assert_eq!(
+            merge_into(&file, generated, false).unwrap(),
+            Written::Merged(4)
+        );
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "### rust ###\n# Build output\ntarget/\n\n# Lockfile\nCargo.lock\n### end rust ###\n\n### node ###\nnode_modules/\n!keep.js\n### end node ###\n"
+        );
+
+        std::fs::write(&file, "*.log\ntarget/   \nkeep.js\nnode_modules/").unwrap();
+        assert_eq!(
+            merge_into(&file, generated, false).unwrap(),
+            Written::Merged(2)
+        );
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "*.log\ntarget/   \nkeep.js\nnode_modules/\n### rust ###\n# Lockfile\nCargo.lock\n### end rust ###\n\n### node ###\n!keep.js\n### end node ###\n"
+        );
+
+        let covered = std::fs::read_to_string(&file).unwrap();
+        assert_eq!(
+            merge_into(&file, generated, false).unwrap(),
+            Written::Unchanged
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), covered);
+
+        let missing = core.cache_dir().join("new.gitignore");
+        assert_eq!(
+            merge_into(&missing, generated, false).unwrap(),
+            Written::Created
+        );
+        assert_eq!(std::fs::read_to_string(&missing).unwrap(), generated);
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn appending_twice_never_glues_lines() {
+        let core = temp_core("append");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/   "},"node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"\nnode_modules/\n\n\n"}}"#,
+        )
+        .unwrap();
+
+        let gitignore = core.cache_dir().join(".gitignore");
+        std::fs::write(&gitignore, "*.log").unwrap();
+        let names = ["rust", "node"].map(String::from);
+        for _ in 0..2 {
+            let generated = core.get_templates(&names, true).unwrap();
+            assert!(generated.combined.ends_with("rust,node\n"));
+            append_to(&gitignore, &generated.combined).unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&gitignore).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines[0], "*.log");
+        assert_eq!(lines.iter().filter(|l| **l == "target/").count(), 2);
+        assert_eq!(lines.iter().filter(|l| **l == "node_modules/").count(), 2);
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| l.starts_with("### Created by"))
+                .count(),
+            2
+        );
+        assert!(contents.ends_with("### end node ###\n\n# git-ignore: rust,node\n"));
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn requested_names_keep_first_occurrence() {
+        let names = requested_names(
+            vec!["rust".into(), "node".into(), "rust".into()],
+            vec!["go".into(), "node".into(), "python".into()],
+        );
+        assert_eq!(names, ["rust", "node", "go", "python"]);
+    }
+
+    #[test]
+    fn defaults_come_after_requested_names() {
+        let requested = vec!["rust".to_string(), "MacOS".to_string()];
+        let defaults = ["macos", "direnv", "editors", "Direnv"].map(String::from);
+        assert_eq!(
+            missing_defaults(&requested, defaults.to_vec()),
+            ["direnv", "editors"]
+        );
+
+        let config: Config = toml::from_str(
+            "default_templates = [\"macos\", \"editors\"]\n[aliases]\n[templates]\n",
+        )
+        .unwrap();
+        let mut core = temp_core("defaults");
+        core.config = Some(config);
+        assert_eq!(core.default_templates(false), ["macos", "editors"]);
+        assert!(core.default_templates(true).is_empty());
+    }
+
+    #[test]
+    fn generated_output_follows_requested_order() {
+        let core = temp_core("ordering");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"},
+                "node":{"key":"node","name":"Node","fileName":"Node.gitignore","contents":"node_modules/\n"},
+                "go":{"key":"go","name":"Go","fileName":"Go.gitignore","contents":"vendor/\n"}}"#,
+        )
+        .unwrap();
+
+        let names = requested_names(
+            vec!["node".into(), "rust".into()],
+            vec!["go".into(), "rust".into()],
+        );
+        let first = core.get_templates(&names, true).unwrap().combined;
+        let second = core.get_templates(&names, true).unwrap().combined;
+        assert_eq!(first, second);
+
+        let node = first.find("### node ###").unwrap();
+        let rust = first.find("### rust ###").unwrap();
+        let go = first.find("### go ###").unwrap();
+        assert!(node < rust && rust < go);
+        assert_eq!(first.matches("### rust ###").count(), 1);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn template_file_names_stay_in_cache() {
+        assert_eq!(template_file_name("rust"), "rust.gz");
+        assert_eq!(
+            template_file_name("../../etc/passwd"),
+            "%2E%2E%2F%2E%2E%2Fetc%2Fpasswd.gz"
+        );
+        assert_eq!(template_file_name("a\\b%"), "a%5Cb%25.gz");
+
+        // Keys that only differ in the characters we replace get their own file
+        let keys = ["a_b", "a.b", "a/b", "a%2Eb"];
+        let names: HashSet<_> = keys.iter().map(|key| template_file_name(key)).collect();
+        assert_eq!(names.len(), keys.len());
+    }
+
+    #[test]
+    fn similar_keys_are_cached_separately() {
+        let core = temp_core("similar-keys");
+        let mut templates = HashMap::new();
+        for (key, contents) in [("a_b", "underscore/\n"), ("a.b", "dot/\n")] {
+            let language: Language = serde_json::from_value(serde_json::json!({
+                "key": key, "name": key, "fileName": format!("{}.gitignore", key), "contents": contents,
+            }))
+            .unwrap();
+            templates.insert(key.to_string(), language);
+        }
+        core.write_cache(&templates).unwrap();
+
+        assert_eq!(core.read_contents("a_b").unwrap(), "underscore/\n");
+        assert_eq!(core.read_contents("a.b").unwrap(), "dot/\n");
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn clear_cache_is_quiet_without_cache() {
+        let core = temp_core("clear-empty");
+        assert!(core.clear_cache().is_ok());
+        assert!(!core.cache_exists());
+    }
+
+    #[test]
+    fn clear_cache_keeps_unrelated_files() {
+        let core = temp_core("clear-shared");
+        write_cache(&core, SystemTime::now());
+        let other = core.cache_dir().join("other.txt");
+        std::fs::write(&other, "keep me").unwrap();
+        // From another run that was killed while downloading
+        let download = core.cache_dir().join("template.1.download");
+        std::fs::write(&download, "partial").unwrap();
+
+        core.clear_cache().unwrap();
+        assert!(!core.cache_exists());
+        assert!(other.exists());
+        assert!(!download.exists());
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let source = temp_core("export");
+        std::fs::create_dir_all(source.cache_dir()).unwrap();
+        std::fs::write(
+            &source.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/"}}"#,
+        )
+        .unwrap();
+        std::fs::write(&source.meta_file, r#"{"updated_at":1700000000}"#).unwrap();
+        let file = source.cache_dir().join("export.json");
+        assert_eq!(source.export_cache(&file).unwrap(), 1);
+
+        let target = temp_core("import");
+        assert_eq!(target.import_cache(&file).unwrap(), 1);
+        let templates = target.parse_cache().unwrap().unwrap();
+        assert_eq!(templates["rust"].contents, "target/");
+        assert_eq!(target.read_meta().updated_at, Some(1_700_000_000));
+
+        std::fs::remove_dir_all(source.cache_dir()).unwrap();
+        target.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn import_rejects_invalid_files() {
+        let core = temp_core("import-invalid");
+        let dir = std::env::temp_dir().join("git-ignore-test-import-invalid-files");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("cache.json");
+        std::fs::write(&file, r#"{"rust":{"key":"rust"}}"#).unwrap();
+        assert!(core.import_cache(&file).is_err());
+        std::fs::write(&file, r#"{"meta":{},"templates":{}}"#).unwrap();
+        assert!(core.import_cache(&file).is_err());
+        assert!(!core.cache_exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn export_without_cache_fails() {
+        let core = temp_core("export-empty");
+        assert!(core
+            .export_cache(&core.cache_dir().join("out.json"))
+            .is_err());
+    }
+
+    fn write_cache(core: &Core, modified: SystemTime) {
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        let file = File::create(&core.ignore_file).unwrap();
+        file.set_modified(modified).unwrap();
+    }
+
+    fn config_with_ttl(days: u64) -> Config {
+        Config {
+            path: PathBuf::new(),
+            templates_dir: PathBuf::new(),
+            aliases: HashMap::new(),
+            templates: HashMap::new(),
+            template_urls: HashMap::new(),
+            default_templates: Vec::new(),
+            profiles: HashMap::new(),
+            cache_ttl_days: Some(days),
+            server: None,
+            source: None,
+            sources: Vec::new(),
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            proxy: None,
+            background_update: None,
+            max_download_mb: None,
+            line_endings: None,
+            backup: None,
+            // Lists only have what the tests add
+            builtin_aliases: Some(false),
+        }
+    }
+
+    #[test]
+    fn fresh_cache_is_not_stale() {
+        let core = temp_core("ttl-fresh");
+        write_cache(&core, SystemTime::now());
+        assert!(!core.cache_is_stale());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn old_cache_is_stale() {
+        let core = temp_core("ttl-old");
+        write_cache(&core, SystemTime::now() - Duration::from_secs(31 * 86_400));
+        assert!(core.cache_is_stale());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn age_prefers_recorded_update_time() {
+        let core = temp_core("age-meta");
+        write_cache(&core, SystemTime::now());
+        let updated = SystemTime::now() - Duration::from_secs(12 * 86_400);
+        let secs = updated.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        std::fs::write(&core.meta_file, format!(r#"{{"updated_at":{}}}"#, secs)).unwrap();
+
+        assert_eq!(core.cache_age().unwrap().as_secs() / 86_400, 12);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn zero_ttl_disables_refresh() {
+        let mut core = temp_core("ttl-zero");
+        core.config = Some(config_with_ttl(0));
+        write_cache(&core, SystemTime::now() - Duration::from_secs(365 * 86_400));
+        assert!(!core.cache_is_stale());
+        core.clear_cache().unwrap();
+    }
+
+    /// Serves a single canned HTTP response, returning a source pointing to it
+    /// and a handle that yields the raw request that was received.
+    fn fake_source(response: &'static str) -> (Source, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/api/list?format=json",
+            listener.local_addr().unwrap()
+        );
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8_lossy(&request).to_lowercase()
+        });
+
+        (
+            Source::new(TemplateSource::GitignoreIo, &url).unwrap(),
+            handle,
+        )
+    }
+
+    #[test]
+    fn update_stores_etag() {
+        let mut core = temp_core("fetch-200");
+        let (source, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+        );
+        core.sources = vec![source.clone()];
+
+        core.update().unwrap();
+        let request = handle.join().unwrap();
+
+        assert!(!request.contains("if-none-match"));
+        assert!(core.index_file.exists());
+        assert!(core.parse_cache().unwrap().unwrap().is_empty());
+        let meta = core.read_meta();
+        assert_eq!(meta.sources[&source.url].etag.as_deref(), Some("\"abc\""));
+        assert!(meta.updated_at.is_some());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn update_replaces_legacy_cache() {
+        let mut core = temp_core("fetch-legacy");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, "{}").unwrap();
+        let (source, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{\"rust\":{\"key\":\"rust\",\"name\":\"Rust\",\"fileName\":\"Rust.gitignore\",\"contents\":\"target/\"}}",
+        );
+        core.sources = vec![source];
+
+        core.update().unwrap();
+        handle.join().unwrap();
+
+        assert!(!core.ignore_file.exists());
+        assert!(core.templates_dir.join("rust.gz").exists());
+        let info = core.cache_info().unwrap().unwrap();
+        assert_eq!(info.uncompressed, 7);
+        assert_eq!(info.templates, 1);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn waits_for_concurrent_update() {
+        let winner = temp_core("lock-wait");
+        std::fs::create_dir_all(winner.cache_dir()).unwrap();
+        let (lock, waited) = winner.lock_update().unwrap();
+        assert!(!waited);
+
+        let loser = thread::spawn(|| {
+            let mut core = temp_core_in("lock-wait");
+            // Unreachable, so this only passes if the cache is reused
+            core.sources =
+                vec![Source::new(TemplateSource::GitignoreIo, "http://127.0.0.1:1/").unwrap()];
+            core.update()
+        });
+
+        thread::sleep(Duration::from_millis(300));
+        std::fs::write(
+            &winner.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/"}}"#,
+        )
+        .unwrap();
+        std::fs::write(&winner.meta_file, r#"{"updated_at":1700000000}"#).unwrap();
+        drop(lock);
+
+        loser.join().unwrap().unwrap();
+        assert_eq!(winner.parse_cache().unwrap().unwrap().len(), 1);
+        winner.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn lock_is_released_on_error() {
+        let mut core = temp_core("lock-error");
+        core.sources =
+            vec![Source::new(TemplateSource::GitignoreIo, "http://127.0.0.1:1/").unwrap()];
+
+        assert!(core.update().is_err());
+        let (_lock, waited) = core.lock_update().unwrap();
+        assert!(!waited);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn truncated_download_preserves_cache() {
+        let mut core = temp_core("fetch-truncated");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, "{}").unwrap();
+        let (source, handle) =
+            fake_source("HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{\"rust\":{\"key\":\"ru");
+        core.sources = vec![source.clone()];
+
+        let err = core.update().unwrap_err();
+        handle.join().unwrap();
+
+        assert!(err.to_string().contains("previous cache"));
+        assert!(core.parse_cache().unwrap().unwrap().is_empty());
+        assert!(!core.templates_dir.with_extension("tmp").exists());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn endless_response_is_aborted() {
+        let mut core = temp_core("fetch-endless");
+        core.max_download = 1024 * 1024;
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, "{}").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/list", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let chunk = [b' '; 8192];
+            while stream.write_all(&chunk).is_ok() {}
+        });
+        core.sources = vec![Source::new(TemplateSource::GitignoreIo, &url).unwrap()];
+
+        let err = core.update().unwrap_err();
+        handle.join().unwrap();
+
+        assert!(format!("{:#}", err).contains("exceeded the maximum size of 1.0 MiB"));
+        assert!(core.parse_cache().unwrap().unwrap().is_empty());
+        assert!(!core.download_file("list").exists());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn downloads_templates_from_urls() {
+        let core = temp_core("from-url");
+        let (source, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 9\r\nConnection: close\r\n\r\nBinaries/",
+        );
+        let contents = core.download_template(&source.url).unwrap();
+        handle.join().unwrap();
+        assert_eq!(contents.contents, "Binaries/");
+        assert_eq!(contents.url.as_deref(), Some(source.url.as_str()));
+        assert!(!core.download_file("template").exists());
+        assert_ne!(core.download_file("template"), core.download_file("list"));
+        let pid = std::process::id().to_string();
+        assert!(core
+            .download_file("template")
+            .to_string_lossy()
+            .contains(&pid));
+
+        let dir = core.cache_dir().to_path_buf();
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        let mut config = config_with_ttl(30);
+        config.path = dir.join("config.toml");
+        config.templates_dir = dir.join("templates");
+        config
+            .add_template(
+                "unreal".into(),
+                "unreal".into(),
+                false,
+                None,
+                Some(contents),
+            )
+            .unwrap();
+        assert_eq!(config.template_urls["unreal"], source.url);
+        let written = std::fs::read_to_string(&config.path).unwrap();
+        assert!(written.contains("[template_urls]"));
+        config.remove_template("unreal", false, None).unwrap();
+        assert!(config.template_urls.is_empty());
+
+        let (source, handle) = fake_source(
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\nConnection: close\r\n\r\nNot Found",
+        );
+        let err = core.download_template(&source.url).unwrap_err();
+        handle.join().unwrap();
+        assert!(format!("{:#}", err).contains("responded with 404 Not Found: Not Found"));
+
+        let (source, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 6\r\nConnection: close\r\n\r\n<html>",
+        );
+        let err = core.download_template(&source.url).unwrap_err();
+        handle.join().unwrap();
+        assert!(err.to_string().contains("an HTML page"));
+
+        let (source, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n\u{0}\u{1}",
+        );
+        assert!(core.download_template(&source.url).is_err());
+        handle.join().unwrap();
+        assert!(core.download_template("file:///etc/passwd").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn oversized_content_length_is_rejected() {
+        let mut core = temp_core("fetch-oversized");
+        core.max_download = 1024;
+        let (source, handle) =
+            fake_source("HTTP/1.1 200 OK\r\nContent-Length: 4096\r\nConnection: close\r\n\r\n");
+        core.sources = vec![source];
+
+        let err = core.update().unwrap_err();
+        handle.join().unwrap();
+
+        assert!(format!("{:#}", err).contains("exceeded the maximum size"));
+        assert!(!core.cache_exists());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn not_found_preserves_cache() {
+        let mut core = temp_core("fetch-404");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, "{}").unwrap();
+        let (source, handle) =
+            fake_source("HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\nNo such page\n");
+        core.sources = vec![source];
+
+        let err = core.update().unwrap_err();
+        handle.join().unwrap();
+
+        let err = format!("{:#}", err);
+        assert!(err.contains("404 Not Found: No such page"), "{}", err);
+        assert!(core.parse_cache().unwrap().unwrap().is_empty());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn html_response_preserves_cache() {
+        let mut core = temp_core("fetch-html");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, "{}").unwrap();
+        let (source, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n<html>\n  <body>Maintenance</body>\n</html>",
+        );
+        core.sources = vec![source];
+
+        let err = core.update().unwrap_err();
+        handle.join().unwrap();
+
+        let err = format!("{:#}", err);
+        assert!(
+            err.contains("<html> <body>Maintenance</body> </html>"),
+            "{}",
+            err
+        );
+        assert!(core.parse_cache().unwrap().unwrap().is_empty());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn valid_response_replaces_cache() {
+        let mut core = temp_core("fetch-valid");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, "{}").unwrap();
+        let (source, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n{\"rust\":{\"key\":\"rust\",\"name\":\"Rust\",\"fileName\":\"Rust.gitignore\",\"contents\":\"target/\"}}",
+        );
+        core.sources = vec![source];
+
+        core.update().unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(core.parse_cache().unwrap().unwrap().len(), 1);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn excerpts_long_bodies() {
+        assert_eq!(excerpt(b""), "<empty body>");
+        assert_eq!(excerpt(b"a\n  b"), "a b");
+        let long = excerpt("x".repeat(500).as_bytes());
+        assert_eq!(long.len(), EXCERPT_LEN + 3);
+    }
+
+    #[test]
+    fn corrupted_cache_is_downloaded_again() {
+        let mut core = temp_core("corrupted-recover");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, "garbage").unwrap();
+        std::fs::write(&core.meta_file, r#"{"etag":"\"abc\""}"#).unwrap();
+        let (source, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{\"rust\":{\"key\":\"rust\",\"name\":\"Rust\",\"fileName\":\"Rust.gitignore\",\"contents\":\"target/\"}}",
+        );
+        core.sources = vec![source.clone()];
+
+        let templates = core.read_file().unwrap();
+        let request = handle.join().unwrap();
+
+        assert!(!request.contains("if-none-match"));
+        assert_eq!(templates.len(), 1);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn corrupted_compressed_cache_is_reported() {
+        let mut core = temp_core("corrupted-gz");
+        core.offline = true;
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.compressed_file, "not gzip").unwrap();
+
+        let err = core.read_file().unwrap_err();
+        assert!(err.to_string().contains("ignore.json.gz"));
+        assert!(err.to_string().contains("corrupted"));
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn corrupted_cache_names_file_to_delete() {
+        let mut core = temp_core("corrupted-fail");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, "garbage").unwrap();
+        let (source, handle) =
+            fake_source("HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nmore garbage");
+        core.sources = vec![source.clone()];
+
+        let err = core.read_file().unwrap_err();
+        handle.join().unwrap();
+
+        assert!(err.to_string().contains("delete"));
+        assert!(err.to_string().contains("ignore.json"));
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn falls_back_to_embedded_templates() {
+        let core = temp_core("fallback");
+
+        let output = core
+            .get_templates(&["rust".to_string()], true)
+            .unwrap()
+            .combined;
+        assert!(output.contains("built-in fallback templates"));
+        assert!(output.contains("### rust ###\n# Generated by Cargo"));
+
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"\ntarget/\n"}}"#,
+        )
+        .unwrap();
+        let output = core
+            .get_templates(&["rust".to_string()], true)
+            .unwrap()
+            .combined;
+        assert!(!output.contains("built-in fallback templates"));
+        assert!(output.ends_with("\ntarget/\n### end rust ###\n\n# git-ignore: rust\n"));
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn offline_never_updates() {
+        let mut core = temp_core("offline");
+        core.offline = true;
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, "garbage").unwrap();
+
+        assert!(core.update().is_err());
+        assert!(core.read_file().is_err());
+        assert_eq!(
+            std::fs::read_to_string(&core.ignore_file).unwrap(),
+            "garbage"
+        );
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn update_keeps_cache_when_not_modified() {
+        let mut core = temp_core("fetch-304");
+        let (source, handle) =
+            fake_source("HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n");
+        core.sources = vec![source.clone()];
+        let cache = format!(
+            r#"{{"rust":{{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/","source":"{}"}}}}"#,
+            source.url
+        );
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(&core.ignore_file, &cache).unwrap();
+        let meta = format!(
+            r#"{{"updated_at":0,"sources":{{"{}":{{"etag":"\"abc\""}}}}}}"#,
+            source.url
+        );
+        std::fs::write(&core.meta_file, meta).unwrap();
+
+        assert!(core.fetch_gitignore().unwrap().is_none());
+        let request = handle.join().unwrap();
+
+        assert!(request.contains("if-none-match: \"abc\""));
+        let templates = core.parse_cache().unwrap().unwrap();
+        assert_eq!(templates["rust"].contents, "target/");
+        assert!(core.cache_age().unwrap() < Duration::from_secs(60));
+        core.clear_cache().unwrap();
+    }
+
+    /// Caches a single template from `source` along with its ETag.
+    fn cache_from(core: &Core, source: &Source, etag: &str) {
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        let template = format!(
+            r#"{{"rust":{{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"","source":"{}"}}}}"#,
+            source.url
+        );
+        std::fs::write(&core.ignore_file, template).unwrap();
+        core.parse_cache().unwrap().unwrap();
+        let meta = format!(
+            r#"{{"updated_at":{},"sources":{{"{}":{{"etag":"{}"}}}}}}"#,
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            source.url,
+            etag.replace('"', "\\\""),
+        );
+        std::fs::write(&core.meta_file, meta).unwrap();
+    }
+
+    #[test]
+    fn check_reports_up_to_date() {
+        let mut core = temp_core("check-fresh");
+        let (source, handle) =
+            fake_source("HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n");
+        cache_from(&core, &source, "\"abc\"");
+        core.sources = vec![source];
+
+        assert!(core.check_for_update().unwrap().is_none());
+        let request = handle.join().unwrap();
+
+        assert!(request.starts_with("head "));
+        assert!(request.contains("if-none-match: \"abc\""));
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn check_reports_available_update() {
+        let mut core = temp_core("check-outdated");
+        let (source, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nETag: \"def\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        cache_from(&core, &source, "\"abc\"");
+        core.sources = vec![source.clone()];
+        let cached = std::fs::read_to_string(&core.index_file).unwrap();
+
+        assert_eq!(core.check_for_update().unwrap(), Some(&source));
+        handle.join().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&core.index_file).unwrap(), cached);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn check_leaves_legacy_caches_alone() {
+        let core = temp_core("check-legacy");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"target/\n"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(core.check_for_update().unwrap(), core.sources().first());
+        assert!(core.ignore_file.exists());
+        assert!(!core.index_file.exists());
+        std::fs::remove_dir_all(core.cache_dir()).unwrap();
+    }
+
+    #[test]
+    fn check_fails_without_connection() {
+        let mut core = temp_core("check-offline");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/list", listener.local_addr().unwrap());
+        drop(listener);
+        let source = Source::new(TemplateSource::GitignoreIo, &url).unwrap();
+        cache_from(&core, &source, "\"abc\"");
+        core.sources = vec![source];
+
+        assert!(core.check_for_update().is_err());
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn later_sources_take_precedence() {
+        let mut core = temp_core("fetch-merge");
+        let (first, first_handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{\"rust\":{\"key\":\"rust\",\"name\":\"Rust\",\"fileName\":\"Rust.gitignore\",\"contents\":\"first\"},\"go\":{\"key\":\"go\",\"name\":\"Go\",\"fileName\":\"Go.gitignore\",\"contents\":\"first\"}}",
+        );
+        let (second, second_handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{\"rust\":{\"key\":\"rust\",\"name\":\"Rust\",\"fileName\":\"Rust.gitignore\",\"contents\":\"second\"}}",
+        );
+        core.sources = vec![first.clone(), second.clone()];
+
+        core.update().unwrap();
+        first_handle.join().unwrap();
+        second_handle.join().unwrap();
+
+        let templates = core.parse_cache().unwrap().unwrap();
+        assert_eq!(templates["rust"].contents, "second");
+        assert_eq!(templates["rust"].source.as_ref(), Some(&second.url));
+        assert_eq!(templates["go"].source.as_ref(), Some(&first.url));
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn failing_source_is_skipped() {
+        let mut core = temp_core("fetch-partial");
+        let (working, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{\"rust\":{\"key\":\"rust\",\"name\":\"Rust\",\"fileName\":\"Rust.gitignore\",\"contents\":\"target/\"}}",
+        );
+        let broken = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            Source::new(TemplateSource::GitignoreIo, &url).unwrap()
+        };
+        core.sources = vec![broken, working];
+
+        core.update().unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(core.parse_cache().unwrap().unwrap().len(), 1);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn update_through_proxy() {
+        let mut core = temp_core("proxy");
+        let (proxy, handle) = fake_source(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{\"rust\":{\"key\":\"rust\",\"name\":\"Rust\",\"fileName\":\"Rust.gitignore\",\"contents\":\"target/\"}}",
+        );
+        let url = "http://templates.invalid/api/list";
+        core.sources = vec![Source::new(TemplateSource::GitignoreIo, url).unwrap()];
+        core.proxies = Proxies::new(|_| None, Some(&proxy.url)).unwrap();
+
+        core.update().unwrap();
+        let request = handle.join().unwrap();
+
+        assert!(request.starts_with("get http://templates.invalid/api/list"));
+        assert_eq!(core.parse_cache().unwrap().unwrap().len(), 1);
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn unreachable_proxy_is_reported() {
+        let mut core = temp_core("proxy-unreachable");
+        let proxy = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        core.proxies = Proxies::new(|_| None, Some(&proxy)).unwrap();
+        core.sources =
+            vec![Source::new(TemplateSource::GitignoreIo, "http://templates.invalid/").unwrap()];
+
+        let err = core.fetch_gitignore().unwrap_err();
+        assert!(format!("{:#}", err).contains("Proxy http://127.0.0.1"));
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        let mut attempts = 0;
+        let result = retry(3, Duration::ZERO, || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(Transient(anyhow!("connection reset")).into())
+            } else {
+                Ok(attempts)
+            }
+        });
+
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_retries() {
+        let mut attempts = 0;
+        let result: Result<()> = retry(3, Duration::ZERO, || {
+            attempts += 1;
+            Err(Transient(anyhow!("connection reset")).into())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 4);
+    }
+
+    #[test]
+    fn does_not_retry_permanent_failures() {
+        let mut attempts = 0;
+        let result: Result<()> = retry(3, Duration::ZERO, || {
+            attempts += 1;
+            Err(anyhow!("invalid JSON"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn cache_dir_precedence() {
+        let flag = Some(PathBuf::from("/tmp/flag"));
+        let env = Some("/tmp/env".into());
+
+        assert_eq!(
+            Core::resolve_cache_dir(flag, env.clone()),
+            PathBuf::from("/tmp/flag")
+        );
+        assert_eq!(
+            Core::resolve_cache_dir(None, env),
+            PathBuf::from("/tmp/env")
+        );
+        assert_eq!(
+            Core::resolve_cache_dir(None, Some("".into())),
+            project_dirs().cache_dir()
+        );
+    }
+
+    #[test]
+    fn reads_config_from_given_file() {
+        let flag = Some(PathBuf::from("/tmp/flag.toml"));
+        let env = Some("/tmp/env.toml".into());
+        assert_eq!(
+            resolve_config_file(flag, env.clone()),
+            Some("/tmp/flag.toml".into())
+        );
+        assert_eq!(resolve_config_file(None, env), Some("/tmp/env.toml".into()));
+        assert_eq!(resolve_config_file(None, Some("".into())), None);
+
+        let dir = temp_core("config-file").cache_dir().join("work");
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        let file = dir.join("work.toml");
+        assert!(Config::load(Some(&file)).unwrap().is_none());
+
+        std::fs::write(
+            &file,
+            "[aliases]\nweb = [\"node\"]\n[templates]\nwork = \"work.txt\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("templates").join("work.txt"), "secrets/\n").unwrap();
+
+        let config = Config::load(Some(&file)).unwrap().unwrap();
+        assert_eq!(config.path, file);
+        assert_eq!(config.templates_dir, dir.join("templates"));
+        assert_eq!(config.aliases["web"], ["node"]);
+        assert_eq!(config.read_template("work.txt").unwrap(), "secrets/\n");
+    }
+
+    #[test]
+    fn snapshot_ignores_later_updates() {
+        let core = temp_core("snapshot");
+        std::fs::create_dir_all(core.cache_dir()).unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"old"}}"#,
+        )
+        .unwrap();
+
+        core.snapshot_templates().unwrap();
+        std::fs::write(
+            &core.ignore_file,
+            r#"{"rust":{"key":"rust","name":"Rust","fileName":"Rust.gitignore","contents":"new"}}"#,
+        )
+        .unwrap();
+
+        let output = core
+            .get_templates(&["rust".to_string()], true)
+            .unwrap()
+            .combined;
+        assert!(output.contains("\nold\n"));
+        core.clear_cache().unwrap();
+    }
+
+    #[test]
+    fn source_precedence() {
+        let mut config = config_with_ttl(30);
+        config.server = Some("https://config.example.com/list".to_string());
+        let env = Some("https://env.example.com/list".to_string());
+
+        let sources = Core::resolve_sources(env, Some(&config)).unwrap();
+        assert_eq!(sources[0].url, "https://env.example.com/list");
+        let sources = Core::resolve_sources(None, Some(&config)).unwrap();
+        assert_eq!(sources[0].url, "https://config.example.com/list");
+
+        config.sources = vec!["github".into(), "https://internal.example.com/list".into()];
+        let sources = Core::resolve_sources(None, Some(&config)).unwrap();
+        assert_eq!(sources[0], TemplateSource::Github.into());
+        assert_eq!(sources[1].url, "https://internal.example.com/list");
+
+        let sources = Core::resolve_sources(None, None).unwrap();
+        assert_eq!(sources, vec![TemplateSource::GitignoreIo.into()]);
+        assert_eq!(sources[0].url, DEFAULT_SERVER);
+    }
+
+    #[test]
+    fn invalid_server_is_rejected() {
+        let env = Some("not a url".to_string());
+        assert!(Core::resolve_sources(env, None).is_err());
+        let mut config = config_with_ttl(30);
+        config.sources = vec!["ftp://example.com".into()];
+        assert!(Core::resolve_sources(None, Some(&config)).is_err());
+    }
+
+    #[test]
+    fn invalid_sources_only_fail_when_fetching() {
+        let core = temp_core("invalid-sources");
+        let dir = core.cache_dir().to_path_buf();
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("config.toml");
+        std::fs::write(
+            &config,
+            "sources = ['ftp://example.com']\n[aliases]\n[templates]\n",
+        )
+        .unwrap();
+
+        let core = Core::new(Some(dir.join("cache")), Some(config)).unwrap();
+        assert!(core.config.is_some());
+        let err = core.check_sources().unwrap_err().to_string();
+        assert!(err.contains("ftp://example.com"), "{}", err);
+        assert_eq!(core.update().unwrap_err().to_string(), err);
+        assert_eq!(core.check_for_update().unwrap_err().to_string(), err);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn formats_age() {
+        assert_eq!(format_age(Duration::from_secs(10)), "just now");
+        assert_eq!(format_age(Duration::from_secs(60)), "1 minute ago");
+        assert_eq!(format_age(Duration::from_secs(5 * 3_600)), "5 hours ago");
+        assert_eq!(format_age(Duration::from_secs(12 * 86_400)), "12 days ago");
+    }
+
+    fn languages(entries: &[(&str, &str)]) -> HashMap<String, Language> {
+        entries
+            .iter()
+            .map(|(key, contents)| {
+                let language = Language {
+                    key: key.to_string(),
+                    name: key.to_string(),
+                    file_name: format!("{}.gitignore", key),
+                    contents: contents.to_string(),
+                    source: None,
+                };
+                (key.to_string(), language)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diffs_templates() {
+        let old = languages(&[("rust", "target/"), ("jekyll", "_site/"), ("go", "*.exe")]);
+        let new = languages(&[
+            ("rust", "target/\n*.pdb"),
+            ("zig", "zig-out/"),
+            ("go", "*.exe"),
+        ]);
+
+        let diff = TemplateDiff::new(&old, &new);
+        assert_eq!(diff.added, vec!["zig"]);
+        assert_eq!(diff.removed, vec!["jekyll"]);
+        assert_eq!(diff.changed, vec!["rust"]);
+        assert_eq!(
+            diff.to_string(),
+            "1 template added (zig), 1 removed (jekyll), 1 changed"
+        );
+        assert_eq!(
+            TemplateDiff::new(&old, &old).to_string(),
+            "No templates changed"
+        );
+    }
+
+    #[test]
+    fn diff_summary_truncates_long_lists() {
+        let keys = (0..12).map(|i| format!("t{:02}", i)).collect::<Vec<_>>();
+        let new = languages(&keys.iter().map(|k| (k.as_str(), "")).collect::<Vec<_>>());
+
+        let diff = TemplateDiff::new(&HashMap::new(), &new);
+        assert!(diff
+            .to_string()
+            .starts_with("12 templates added (t00, t01,"));
+        assert!(diff.to_string().ends_with("t09 and 2 more)"));
+    }
+
+    #[test]
+    fn formats_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(494_500), "482.9 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn formats_time() {
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01 00:00:00 UTC");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(format_time(time), "2024-02-29 12:34:56 UTC");
     }
 }
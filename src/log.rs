@@ -0,0 +1,231 @@
+use std::{
+    fmt::{Arguments, Display},
+    io::{stderr, Write},
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+
+use crate::cli::ColorChoice;
+
+/// Whether messages on `stderr` are colored, decided separately from `stdout`
+/// since only one of them may be a terminal.
+static COLORS: AtomicBool = AtomicBool::new(false);
+
+/// Whether informational messages are hidden by `--quiet`.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// How many times `-v` was passed.
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+pub const GREEN: &str = "1;32";
+pub const RED: &str = "1;31";
+pub const EMPHASIS: &str = "3;34";
+pub const BLUE: &str = "1;34";
+pub const DIM: &str = "2";
+
+/// Decides whether to use colors for a stream, `auto` colors terminals unless
+/// `NO_COLOR` is set.
+pub fn use_colors(choice: ColorChoice, terminal: bool, no_color: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => terminal && !no_color,
+    }
+}
+
+pub fn init(colors: bool, quiet: bool, verbosity: u8) {
+    COLORS.store(colors, Ordering::Relaxed);
+    QUIET.store(quiet, Ordering::Relaxed);
+    VERBOSITY.store(verbosity, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// What we're doing and why, shown with `-vv`
+    Trace,
+    /// How names are resolved and what happens to the cache, shown with `-v`
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Messages logged by the current test and the verbosity to log them at.
+    static CAPTURED: std::cell::RefCell<Option<(u8, Vec<u8>)>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Prints a message to `stderr`, used through the `trace!`, `debug!`,
+/// `info!`, `warning!` and `error!` macros.
+pub fn log(level: Level, args: Arguments) {
+    #[cfg(test)]
+    if let Some(()) = CAPTURED.with_borrow_mut(|captured| {
+        captured.as_mut().map(|(verbosity, out)| {
+            let _ = write(out, level, args, false, *verbosity);
+        })
+    }) {
+        return;
+    }
+
+    let _ = write(
+        &mut stderr(),
+        level,
+        args,
+        is_quiet(),
+        VERBOSITY.load(Ordering::Relaxed),
+    );
+}
+
+/// Runs `f` and returns what it logged at the given verbosity.
+#[cfg(test)]
+pub fn capture(verbosity: u8, f: impl FnOnce()) -> String {
+    CAPTURED.set(Some((verbosity, Vec::new())));
+    f();
+    let (_, out) = CAPTURED.take().unwrap_or_default();
+    String::from_utf8(out).unwrap()
+}
+
+fn write(
+    out: &mut impl Write,
+    level: Level,
+    args: Arguments,
+    quiet: bool,
+    verbosity: u8,
+) -> std::io::Result<()> {
+    let label = match level {
+        Level::Trace if quiet || verbosity < 2 => return Ok(()),
+        Level::Debug if quiet || verbosity < 1 => return Ok(()),
+        Level::Info if quiet => return Ok(()),
+        Level::Trace => paint("Trace", DIM),
+        Level::Debug => paint("Debug", BLUE),
+        Level::Info => paint("Info", GREEN),
+        Level::Warning => paint("Warning", RED),
+        Level::Error => paint("Error", RED),
+    };
+
+    writeln!(out, "{}: {}", label, args)
+}
+
+/// Wraps `text` in the ANSI `style` if messages are colored.
+pub fn paint(text: impl Display, style: &str) -> String {
+    if COLORS.load(Ordering::Relaxed) {
+        format!("\x1b[{}m{}\x1b[0m", style, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Prints what we're doing to `stderr` when `-vv` is passed.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Trace, format_args!($($arg)*))
+    };
+}
+
+/// Prints how a decision was made to `stderr` when `-v` is passed.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Debug, format_args!($($arg)*))
+    };
+}
+
+/// Prints an informational message to `stderr`, unless `--quiet` is passed.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Info, format_args!($($arg)*))
+    };
+}
+
+/// Prints a warning to `stderr`.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Warning, format_args!($($arg)*))
+    };
+}
+
+/// Prints an error to `stderr`.
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Error, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use debug;
+pub(crate) use error;
+pub(crate) use info;
+pub(crate) use trace;
+pub(crate) use warning;
+
+#[cfg(test)]
+mod tests {
+    use super::{capture, paint, use_colors, write, Level, GREEN};
+    use crate::cli::ColorChoice;
+
+    #[test]
+    fn colors_only_terminals_by_default() {
+        assert!(use_colors(ColorChoice::Auto, true, false));
+        assert!(!use_colors(ColorChoice::Auto, false, false));
+        assert!(!use_colors(ColorChoice::Auto, true, true));
+        assert!(use_colors(ColorChoice::Always, false, true));
+        assert!(!use_colors(ColorChoice::Never, true, false));
+    }
+
+    fn output(level: Level, quiet: bool) -> String {
+        verbose_output(level, quiet, 0)
+    }
+
+    fn verbose_output(level: Level, quiet: bool, verbosity: u8) -> String {
+        let mut out = Vec::new();
+        write(
+            &mut out,
+            level,
+            format_args!("{} templates", 3),
+            quiet,
+            verbosity,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn verbosity_is_stackable() {
+        assert_eq!(verbose_output(Level::Debug, false, 0), "");
+        assert_eq!(
+            verbose_output(Level::Debug, false, 1),
+            "Debug: 3 templates\n"
+        );
+        assert_eq!(verbose_output(Level::Trace, false, 1), "");
+        assert_eq!(
+            verbose_output(Level::Trace, false, 2),
+            "Trace: 3 templates\n"
+        );
+        assert_eq!(verbose_output(Level::Debug, true, 2), "");
+    }
+
+    #[test]
+    fn captures_messages() {
+        let logged = capture(1, || {
+            debug!("resolved");
+            trace!("hidden");
+            info!("done");
+        });
+        assert_eq!(logged, "Debug: resolved\nInfo: done\n");
+    }
+
+    #[test]
+    fn quiet_hides_only_info() {
+        assert_eq!(output(Level::Info, false), "Info: 3 templates\n");
+        assert_eq!(output(Level::Info, true), "");
+        assert_eq!(output(Level::Warning, true), "Warning: 3 templates\n");
+        assert_eq!(output(Level::Error, true), "Error: 3 templates\n");
+    }
+
+    #[test]
+    fn piped_messages_have_no_escapes() {
+        assert_eq!(paint("Info", GREEN), "Info");
+    }
+}
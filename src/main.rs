@@ -1,157 +1,1602 @@
 #![doc = include_str!("../README.md")]
 #![forbid(unsafe_code)]
 
+mod block;
+mod builtin;
 mod cli;
+mod completion;
 mod config;
+mod coverage;
 mod detector;
+mod dialect;
+mod diff;
+mod doctor;
+mod editor;
+mod fallback;
+mod fuzzy;
+mod git_alias;
+mod github;
 mod ignore;
+mod log;
+mod pager;
+mod picker;
+mod progress;
+mod prompt;
+mod proxy;
+mod tidy;
 
 use std::{
-    collections::HashSet,
-    fs::{File, OpenOptions},
-    io::{self, Write},
+    collections::{BTreeMap, HashSet},
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
 };
 
-use anyhow::Result;
-use clap::{CommandFactory, Parser};
-use cli::{print_completion, AliasCmd, Cli, Cmds, TemplateCmd};
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, ValueEnum};
+use clap_complete::Shell;
+use cli::{
+    print_completion, AliasCmd, CacheCmd, Cli, Cmds, EntryType, Format, ProfileCmd, TemplateCmd,
+};
 use colored::Colorize;
-use config::Config;
-use ignore::Core;
+use config::{Config, TemplateContents};
+use coverage::Coverage;
+use doctor::Status;
+use ignore::{
+    apply, backup_file, convert_line_endings, exclude_file, format_age, format_size, format_time,
+    global_excludes_file, long_list, missing_defaults, output_path, plan_merge, plan_remove,
+    plan_write, replace_atomically, repo_root, requested_names, template_from_gitignore,
+    written_templates, Core, Existing, Explanation, Generated, ListEntry, Match, Matching, Member,
+    Origin, Written,
+};
+use log::{debug, error, info, use_colors, warning};
+use prompt::confirm;
 
 macro_rules! config_or {
     ($sel:ident, $fun:ident) => {{
         if let Some(config) = $sel.config {
             config.$fun();
         } else {
-            eprintln!(
-                "{}",
-                "No config found, run `git ignore init` to create it."
-                    .bold()
-                    .yellow()
-            );
-        }
-
-        return Ok(());
-    }};
-    ($sel:ident, $fun:ident, $name:expr) => {{
-        if let Some(mut config) = $sel.config {
-            config.$fun($name)?;
-        } else {
-            eprintln!(
-                "{}",
-                "No config found, run `git ignore init` to create it."
-                    .bold()
-                    .yellow()
-            );
+            warning!("No config found, run `git ignore init` to create it.");
         }
 
-        return Ok(());
+        return Ok(0);
     }};
-    ($sel:ident, $fun:ident, $name:expr, $vals:expr) => {{
-        if let Some(mut config) = $sel.config {
-            config.$fun($name, $vals)?;
+    ($sel:ident, $fun:ident, $($args:expr),+) => {{
+        if let Some(config) = $sel.config.as_mut() {
+            config.$fun($($args),+)?;
         } else {
-            eprintln!(
-                "{}",
-                "No config found, run `git ignore init` to create it."
-                    .bold()
-                    .yellow()
-            );
+            warning!("No config found, run `git ignore init` to create it.");
         }
 
-        return Ok(());
+        return Ok(0);
     }};
 }
 
 fn main() -> Result<()> {
-    let opt = Cli::parse();
-    let app = Core::new();
+    let code = run()?;
+    if code != 0 {
+        std::process::exit(code);
+    }
+
+    Ok(())
+}
+
+/// Runs `git ignore`, returning the code to exit with.
+fn run() -> Result<i32> {
+    let mut opt = Cli::parse_args();
+    // `generate` is the same as passing its options and templates directly
+    if let Some(Cmds::Generate { args, templates }) =
+        opt.cmd.take_if(|cmd| matches!(cmd, Cmds::Generate { .. }))
+    {
+        opt.generate = args;
+        opt.templates = templates;
+    }
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    colored::control::set_override(use_colors(opt.color, io::stdout().is_terminal(), no_color));
+    log::init(
+        use_colors(opt.color, io::stderr().is_terminal(), no_color),
+        opt.quiet,
+        opt.verbose,
+    );
 
-    match opt.cmd {
-        Some(Cmds::Init { force, migrate }) => return Config::create(force, migrate),
+    let mut app = Core::new(opt.cache_dir.clone(), opt.config.clone())?;
+    app.offline = opt.offline;
+    app.verbose = opt.verbose > 0;
+    let profile = match &mut opt.cmd {
+        Some(Cmds::Resolve { names, profile, .. }) => {
+            opt.templates.append(names);
+            profile.take()
+        }
+        _ => opt.generate.profile.take(),
+    };
+    // The templates the profile adds, after the ones that were given
+    let profile = match profile {
+        Some(name) => {
+            if opt.list {
+                bail!("'--profile' can't be used with '--list'");
+            }
+            let Some(config) = &app.config else {
+                bail!("No config found, run `git ignore init` to create it");
+            };
+            let profile = config.profile(&name)?;
+            profile.apply(&mut opt.generate);
+            let added = missing_defaults(&opt.templates, profile.templates.clone());
+            opt.templates.extend(added.iter().cloned());
+            Some((name, added))
+        }
+        None => None,
+    };
+    app.section_headers = !opt.generate.no_section_headers;
+    app.compact = opt.generate.compact;
+    app.footer = !opt.generate.no_footer;
+    // `'!name'` excludes a template just like `--exclude name`
+    for name in opt
+        .templates
+        .iter()
+        .filter_map(|name| name.strip_prefix('!'))
+    {
+        opt.generate.exclude.push(name.to_string());
+    }
+    opt.templates.retain(|name| !name.starts_with('!'));
+    app.exclude = requested_names(std::mem::take(&mut opt.generate.exclude), Vec::new())
+        .iter()
+        .map(|name| name.to_lowercase())
+        .collect();
+
+    let mut deferred = None;
+    match opt.cmd.take() {
+        Some(Cmds::Init {
+            git_alias: true,
+            completions,
+            force,
+            dry_run,
+            ..
+        }) => return register_git_alias(completions, force, dry_run).map(|()| 0),
+        Some(Cmds::Init { force, migrate, .. }) => {
+            return Config::create(app.config_file().map(Path::to_path_buf), force, migrate)
+                .map(|()| 0);
+        }
         Some(Cmds::Alias(cmd)) => match cmd {
-            AliasCmd::List => config_or!(app, list_aliases),
-            AliasCmd::Add { name, aliases } => config_or!(app, add_alias, name, aliases),
-            AliasCmd::Remove { name } => config_or!(app, remove_alias, &name),
+            AliasCmd::List => {
+                list_aliases(&app);
+                return Ok(0);
+            }
+            AliasCmd::Add {
+                name,
+                aliases,
+                force,
+            } => {
+                let shadows = app.shadows_template(&name);
+                config_or!(app, add_alias, name, aliases, force, shadows)
+            }
+            AliasCmd::Remove { name, cascade } => {
+                let downloaded = app.shadows_template(&name);
+                config_or!(app, remove_alias, &name, cascade, downloaded)
+            }
+            // Run after the cache is updated, like `which`
+            cmd @ AliasCmd::Show { .. } => deferred = Some(Cmds::Alias(cmd)),
+            AliasCmd::Rename { old, new, force } => {
+                let shadows = app.shadows_template(&new);
+                config_or!(app, rename_alias, &old, &new, force, shadows)
+            }
+            AliasCmd::Export { file } => return export_aliases(&app, file.as_deref()).map(|()| 0),
+            AliasCmd::Import { file, replace, .. } => {
+                config_or!(app, import_aliases, &file, replace)
+            }
+            AliasCmd::Append { name, members } => {
+                config_or!(app, append_to_alias, &name, members)
+            }
+            AliasCmd::RemoveMember {
+                name,
+                members,
+                delete_empty,
+            } => {
+                let downloaded = app.shadows_template(&name);
+                config_or!(
+                    app,
+                    remove_alias_members,
+                    &name,
+                    &members,
+                    delete_empty,
+                    downloaded
+                )
+            }
         },
         Some(Cmds::Template(cmd)) => match cmd {
-            TemplateCmd::List => config_or!(app, list_templates),
-            TemplateCmd::Add { name, file_name } => config_or!(app, add_template, name, file_name),
-            TemplateCmd::Remove { name } => config_or!(app, remove_template, &name),
+            TemplateCmd::List { content } => config_or!(app, list_templates, content),
+            TemplateCmd::Show { name } => config_or!(app, show_template, &name),
+            TemplateCmd::Add {
+                name,
+                file_name,
+                force,
+                from_file,
+                from_url,
+                from_gitignore,
+            } => {
+                let shadows = app.shadows_template(&name);
+                // Read before anything is changed, so a failure leaves no trace
+                let contents = match (from_file, from_url, from_gitignore) {
+                    (Some(path), ..) => Some(TemplateContents::read(&path)?),
+                    (_, Some(url), _) => Some(app.download_template(&url)?),
+                    (.., Some(path)) => Some(template_from_gitignore(&path)?),
+                    (None, None, None) => None,
+                };
+                config_or!(app, add_template, name, file_name, force, shadows, contents)
+            }
+            TemplateCmd::Edit { name } => return edit_template(&app, &name),
+            TemplateCmd::Rename { old, new, force } => {
+                let shadows = app.shadows_template(&new);
+                config_or!(app, rename_template, &old, &new, force, shadows)
+            }
+            TemplateCmd::Remove { name, cascade } => {
+                let downloaded = app.shadows_template(&name);
+                config_or!(app, remove_template, &name, cascade, downloaded)
+            }
         },
-        Some(Cmds::Completion { shell }) => {
-            let mut app = Cli::command();
-            print_completion(shell, &mut app);
-            return Ok(());
+        Some(Cmds::Profile(cmd)) => match cmd {
+            ProfileCmd::List => config_or!(app, list_profiles),
+            ProfileCmd::Show { name } => config_or!(app, show_profile, &name),
+        },
+        Some(Cmds::Cache(cmd)) => {
+            match cmd {
+                CacheCmd::Info => match app.cache_info()? {
+                    Some(info) => {
+                        println!("{}: {}", "Path".bold(), info.path.display());
+                        let sources = app.sources().iter().map(ToString::to_string);
+                        println!(
+                            "{}: {}",
+                            "Sources".bold(),
+                            sources.collect::<Vec<_>>().join(", ")
+                        );
+                        println!(
+                            "{}: {} ({} uncompressed)",
+                            "Size".bold(),
+                            format_size(info.size),
+                            format_size(info.uncompressed)
+                        );
+                        println!("{}: {}", "Templates".bold(), info.templates);
+                        if let Some(updated) = info.updated {
+                            println!("{}: {}", "Last updated".bold(), format_time(updated));
+                        }
+                    }
+                    None => info!("No cache found, pass '-u' to create it"),
+                },
+                CacheCmd::Clear => {
+                    app.clear_cache()?;
+                    info!("Cache cleared");
+                }
+                CacheCmd::Path => println!("{}", app.cache_dir().display()),
+                CacheCmd::Check => match app.check_for_update() {
+                    Ok(None) => info!("Templates are up to date"),
+                    Ok(Some(source)) => {
+                        info!(
+                            "Newer templates are available from {}, pass '-u' to update",
+                            source
+                        );
+                        return Ok(1);
+                    }
+                    Err(err) => {
+                        error!("Could not check for updates: {:#}", err);
+                        return Ok(2);
+                    }
+                },
+                CacheCmd::Export { file } => {
+                    let count = app.export_cache(&file)?;
+                    info!("Exported {} templates to '{}'", count, file.display());
+                }
+                CacheCmd::Import { file } => {
+                    let count = app.import_cache(&file)?;
+                    info!("Installed {} templates from '{}'", count, file.display());
+                }
+            }
+            return Ok(0);
         }
+        Some(Cmds::Purge { yes, dry_run }) => return purge(&app, yes, dry_run).map(|()| 0),
+        Some(Cmds::Doctor { offline }) => return doctor(&app, offline || opt.offline),
+        Some(Cmds::Stats { format }) => return stats(&app, format).map(|()| 0),
+        Some(Cmds::Tidy {
+            sort,
+            dry_run,
+            file,
+        }) => return tidy_file(&app, file, sort, dry_run, !opt.generate.no_pager),
+        // Run after the cache is updated like it would be when generating
+        Some(
+            cmd @ (Cmds::Sync { .. }
+            | Cmds::Search { .. }
+            | Cmds::Grep { .. }
+            | Cmds::Check { .. }
+            | Cmds::Explain { .. }
+            | Cmds::Resolve { .. }
+            | Cmds::Show { .. }
+            | Cmds::Which { .. }),
+        ) => deferred = Some(cmd),
+        Some(Cmds::Man { command, roff }) => return man(command.as_deref(), roff).map(|()| 0),
+        Some(Cmds::Completion {
+            shell,
+            out,
+            install,
+            force,
+        }) => return completions(shell, out, install, force).map(|()| 0),
         _ => {}
     };
 
-    if opt.update {
+    let mut background = false;
+    match app.cache_age() {
+        Some(age) if app.cache_exists() => debug!(
+            "Cache at '{}' was updated {}",
+            app.cache_dir().display(),
+            format_age(age)
+        ),
+        _ => debug!("No cache at '{}'", app.cache_dir().display()),
+    }
+
+    if opt.offline {
+        debug!("Not updating, running offline");
+        if !app.cache_exists() {
+            bail!("No cached templates found, run with '-u' when you are online");
+        }
+    } else if opt.update {
+        debug!("Updating, '-u' was passed");
         app.update()?;
+    } else if app.cache_exists() && app.cache_is_stale() && app.background_update() {
+        info!(
+            "Cache is older than {} days, updating in the background.",
+            app.cache_ttl().as_secs() / 86_400
+        );
+        // Read the templates before the update can replace them
+        app.snapshot_templates()?;
+        background = true;
+    } else if app.cache_exists() && app.cache_is_stale() {
+        info!(
+            "Cache is older than {} days, attempting update.",
+            app.cache_ttl().as_secs() / 86_400
+        );
+        if let Err(err) = app.update() {
+            warning!("Could not update cache, using stale results: {}\n", err);
+        }
     } else if app.cache_exists() {
-        eprintln!(
-            "{}: You are using cached results, pass '-u' to update the cache\n",
-            "Info".bold().green(),
+        let age = app.cache_age().map(format_age);
+        info!(
+            "You are using cached results from {}, pass '-u' to update the cache\n",
+            age.as_deref().unwrap_or("an unknown time ago"),
         );
     } else {
-        eprintln!(
-            "{}: Cache directory or ignore file not found, attempting update.",
-            "Warning".bold().red(),
-        );
-        app.update()?;
+        warning!("Cache directory or ignore file not found, attempting update.");
+        if let Err(err) = app.update() {
+            warning!("{:#}, using the built-in fallback templates instead\n", err);
+        }
     }
 
-    let mut all_templates: HashSet<String> = opt.templates.into_iter().collect();
-    if opt.auto {
-        for template in app.autodetect_templates()? {
-            all_templates.insert(template);
+    // Handlers return their exit code instead of exiting, so a background
+    // update is joined and reported before we do
+    std::thread::scope(|scope| {
+        let update = background.then(|| scope.spawn(|| app.update()));
+        let result = match deferred {
+            Some(Cmds::Sync { check, here, file }) => sync(&app, file, here, check),
+            Some(Cmds::Search {
+                query,
+                limit,
+                threshold,
+                regex,
+            }) => search(&app, &query, limit, threshold, regex, opt.simple).map(|()| 0),
+            Some(Cmds::Grep {
+                pattern,
+                regex,
+                names_only,
+            }) => grep(&app, &pattern, regex, names_only, opt.simple),
+            Some(Cmds::Check { path, format }) => check(&app, path, format),
+            Some(Cmds::Explain {
+                pattern,
+                file,
+                line,
+                format,
+            }) => explain(&app, pattern, file, line, format, opt.simple),
+            Some(Cmds::Resolve {
+                auto,
+                no_defaults,
+                format,
+                ..
+            }) => {
+                let defaults = if no_defaults {
+                    Vec::new()
+                } else {
+                    app.default_templates(opt.simple)
+                };
+                let auto = auto || opt.generate.auto;
+                resolve(
+                    &app,
+                    opt.templates,
+                    auto,
+                    defaults,
+                    profile,
+                    format,
+                    opt.simple,
+                )
+                .map(|()| 0)
+            }
+            Some(Cmds::Show { name }) => {
+                show(&app, &name, opt.simple, !opt.generate.no_pager).map(|()| 0)
+            }
+            Some(Cmds::Which { name }) => which(&app, &name, opt.simple).map(|()| 0),
+            Some(Cmds::Alias(AliasCmd::Show { name, format })) => {
+                show_alias(&app, &name, format).map(|()| 0)
+            }
+            _ => output(&app, opt),
+        };
+
+        if let Some(update) = update {
+            match update.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => warning!("Background update failed: {:#}", err),
+                Err(_) => warning!("Background update crashed"),
+            }
         }
+
+        result
+    })
+}
+
+/// Regenerates the generated block in `file`, or `.gitignore` where `--write`
+/// puts it, from the templates recorded in it. With `check` nothing is
+/// written and 1 is returned if the file is out of date.
+fn sync(app: &Core, file: Option<PathBuf>, here: bool, check: bool) -> Result<i32> {
+    let file = match file {
+        Some(file) => std::env::current_dir()?.join(file),
+        None => gitignore_dir(here)?.join(".gitignore"),
+    };
+
+    let (keys, change) = app.plan_sync(&file)?;
+    if change.is_empty() {
+        info!("'{}' is up to date", file.display());
+        return Ok(0);
+    }
+
+    let (added, removed) = diff::stat(&change.before, &change.after);
+    if check {
+        info!(
+            "'{}' is out of date, {} lines would be added and {} removed",
+            file.display(),
+            added,
+            removed
+        );
+        return Ok(1);
     }
 
-    let templates: Vec<String> = all_templates.iter().cloned().collect();
+    let backup = app.config.as_ref().and_then(|c| c.backup).unwrap_or(false);
+    apply(&file, &change, backup)?;
+    info!(
+        "Synced {} in '{}', {} lines added and {} removed",
+        keys.join(", "),
+        file.display(),
+        added,
+        removed
+    );
 
-    if opt.update && templates.is_empty() {
+    Ok(0)
+}
+
+/// Prints the templates best matching `query`, with their kinds and scores,
+/// or the ones matching it as a regular expression by name when `regex`.
+fn search(
+    app: &Core,
+    query: &str,
+    limit: usize,
+    threshold: u32,
+    regex: bool,
+    simple: bool,
+) -> Result<()> {
+    let matches: Vec<(Option<u32>, ListEntry)> = if regex {
+        let entries = app.list_entries(&[query.to_string()], simple, Matching::Regex, &[])?;
+        entries.into_iter().map(|entry| (None, entry)).collect()
+    } else {
+        let entries = app.list_entries(&[], simple, Matching::Substring, &[])?;
+        fuzzy::search(query, entries, threshold)
+            .into_iter()
+            .map(|(score, entry)| (Some(score), entry))
+            .collect()
+    };
+    if matches.is_empty() {
+        info!("No templates match '{}'", query);
         return Ok(());
     }
 
-    let str = if opt.list {
-        app.list(templates.as_slice(), opt.simple)?
-    } else if templates.is_empty() {
-        let mut app = Cli::command();
-        app.render_help().to_string()
+    let matches = &matches[..limit.min(matches.len())];
+    let width = matches.iter().map(|(_, e)| e.key.chars().count()).max();
+    for (score, entry) in matches {
+        let line = format!(
+            "{:width$}  {:8}",
+            entry.key,
+            entry.kind.name(),
+            width = width.unwrap_or_default()
+        );
+        match score {
+            Some(score) => println!("{}  {:>3}", line, score),
+            None => println!("{}", line.trim_end()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints every line of the templates matching `pattern` as `key: line`, or
+/// only the keys with `names_only`, returning 1 if none match.
+fn grep(app: &Core, pattern: &str, regex: bool, names_only: bool, simple: bool) -> Result<i32> {
+    let found = app.grep(pattern, regex, simple)?;
+    if found.is_empty() {
+        info!("No templates contain '{}'", pattern);
+        return Ok(1);
+    }
+
+    let mut stdout = io::stdout().lock();
+    for found in &found {
+        let key = match found.kind {
+            Origin::User => found.key.blue(),
+            _ => found.key.magenta(),
+        };
+        if names_only {
+            writeln!(stdout, "{}", key)?;
+            continue;
+        }
+        for line in &found.lines {
+            writeln!(stdout, "{}: {}", key, line)?;
+        }
+    }
+
+    Ok(0)
+}
+
+/// Prints the templates that have `pattern`, or line `line` of `file`, and
+/// whether the file has it too, returning 1 if no template has it.
+fn explain(
+    app: &Core,
+    pattern: Option<String>,
+    file: Option<PathBuf>,
+    line: Option<usize>,
+    format: Format,
+    simple: bool,
+) -> Result<i32> {
+    let cwd = std::env::current_dir()?;
+    let file = match file {
+        Some(file) => cwd.join(file),
+        None => {
+            let git_dir = std::env::var_os("GIT_DIR").map(PathBuf::from);
+            repo_root(&cwd, git_dir.as_deref())
+                .unwrap_or(cwd)
+                .join(".gitignore")
+        }
+    };
+    let contents = std::fs::read_to_string(&file).ok();
+
+    let text = match (pattern, line) {
+        (Some(pattern), _) => pattern,
+        (None, line) => {
+            let n = line.unwrap_or_default();
+            let contents = contents
+                .as_deref()
+                .with_context(|| format!("Could not read '{}'", file.display()))?;
+            let Some(text) = n.checked_sub(1).and_then(|i| contents.lines().nth(i)) else {
+                bail!("'{}' has no line {}", file.display(), n);
+            };
+            if text.trim().is_empty() {
+                bail!("Line {} of '{}' is empty", n, file.display());
+            }
+            text.to_string()
+        }
+    };
+
+    let mut explanation = app.explain(&text, simple)?;
+    explanation.in_gitignore = contents
+        .as_deref()
+        .map(|contents| contents.lines().any(|line| line.trim() == text.trim()));
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&explanation)?);
+    } else {
+        print_explanation(&explanation, &file);
+    }
+
+    Ok(if explanation.templates.is_empty() {
+        1
     } else {
-        app.get_templates(templates.as_slice(), opt.simple)?
+        0
+    })
+}
+
+fn print_explanation(explanation: &Explanation, file: &Path) {
+    let text = explanation.line.trim().bold();
+    if explanation.templates.is_empty() {
+        println!("{} isn't in any template", text);
+    } else {
+        let templates: Vec<String> = explanation
+            .templates
+            .iter()
+            .map(|found| match found.kind {
+                Origin::User => format!("{} {}", found.key, "(user)".dimmed()),
+                _ => found.key.clone(),
+            })
+            .collect();
+        println!("{} is in {}", text, templates.join(", "));
+        if explanation.trimmed {
+            info!("Only found with different whitespace");
+        }
+    }
+
+    match explanation.in_gitignore {
+        Some(true) => println!("'{}' has it", file.display()),
+        Some(false) => println!("'{}' doesn't have it", file.display()),
+        None => {}
+    }
+}
+
+/// Checks that the templates detected in `path`, or the current directory,
+/// are in the `.gitignore` files that apply to it, returning 1 if any
+/// aren't.
+fn check(app: &Core, path: Option<PathBuf>, format: Format) -> Result<i32> {
+    let cwd = std::env::current_dir()?;
+    let dir = match path {
+        Some(path) => cwd
+            .join(&path)
+            .canonicalize()
+            .with_context(|| format!("Could not find '{}'", path.display()))?,
+        None => cwd,
+    };
+    let detected = app.detect_templates(&dir)?;
+
+    // Git reads every `.gitignore` from the directory up to the repository
+    let dirs: Vec<&Path> = match repo_root(&dir, None) {
+        Some(root) => dir
+            .ancestors()
+            .take_while(|ancestor| ancestor.starts_with(&root))
+            .collect(),
+        None => vec![&dir],
     };
+    let mut contents = String::new();
+    for ancestor in dirs {
+        let file = ancestor.join(".gitignore");
+        if file.exists() {
+            debug!("Checking '{}'", file.display());
+            let read = std::fs::read_to_string(&file)
+                .with_context(|| format!("Could not read '{}'", file.display()))?;
+            contents.push_str(&read);
+            contents.push('\n');
+        }
+    }
 
-    if opt.write {
-        let file = std::env::current_dir()?.join(".gitignore");
-        if !file.exists() {
-            eprintln!(
-                "{}: no '.gitignore' file found, creating...",
-                "Info".bold().green()
+    let checked = app.check_coverage(&detected, &contents)?;
+    let missing: Vec<&str> = checked
+        .iter()
+        .filter(|checked| !checked.coverage.is_covered())
+        .map(|checked| checked.template.as_str())
+        .collect();
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&checked)?);
+    } else if checked.is_empty() {
+        info!("No templates detected in '{}'", dir.display());
+    } else {
+        let width = checked.iter().map(|c| c.template.len()).max();
+        for checked in &checked {
+            let status = match checked.coverage {
+                Coverage::Written => "covered".green(),
+                Coverage::Patterns => "covered by its patterns".green(),
+                Coverage::Missing => "missing".red(),
+            };
+            println!(
+                "{:width$}  {}",
+                checked.template,
+                status,
+                width = width.unwrap_or_default()
             );
-            let mut file = File::create(&file)?;
-            file.write_all(str.as_bytes())?;
-        } else if file.exists() && !opt.force {
-            eprintln!(
-                "{}: '.gitignore' already exists, use '-f' to force write",
-                "Warning".bold().red()
+        }
+        if !missing.is_empty() {
+            error!(
+                "The .gitignore doesn't cover {}, run `git ignore {} --merge` to add it",
+                missing.join(", "),
+                missing.join(" ")
+            );
+        }
+    }
+
+    Ok(if missing.is_empty() { 0 } else { 1 })
+}
+
+/// Prints what each of `names` expands to, along with the templates `auto`
+/// detects, in the order they would be written.
+fn resolve(
+    app: &Core,
+    names: Vec<String>,
+    auto: bool,
+    defaults: Vec<String>,
+    profile: Option<(String, Vec<String>)>,
+    format: Format,
+    simple: bool,
+) -> Result<()> {
+    let detected = if auto {
+        app.autodetect_templates()?
+    } else {
+        Vec::new()
+    };
+    let given: HashSet<String> = names.iter().map(|name| name.to_lowercase()).collect();
+    let names = requested_names(names, detected);
+    if names.is_empty() && auto {
+        bail!("No templates were given or detected in the current directory");
+    } else if names.is_empty() {
+        bail!("No templates were given");
+    }
+    let found: HashSet<String> = names.iter().map(|name| name.to_lowercase()).collect();
+    let names = requested_names(names, defaults);
+
+    let mut resolutions = app.resolve(&names, simple)?;
+    for resolution in &mut resolutions {
+        let name = resolution.name.to_lowercase();
+        resolution.default = !found.contains(&name);
+        resolution.detected = !resolution.default && !given.contains(&name);
+        if let Some((profile, added)) = &profile {
+            if added.iter().any(|added| added.eq_ignore_ascii_case(&name)) {
+                resolution.profile = Some(profile.clone());
+            }
+        }
+    }
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&resolutions)?);
+        return Ok(());
+    }
+
+    let width = resolutions.iter().map(|r| r.name.chars().count()).max();
+    for resolution in &resolutions {
+        let mut notes = Vec::new();
+        if resolution.detected {
+            notes.push("detected".to_string());
+        } else if resolution.default {
+            notes.push("default from the config".to_string());
+        } else if let Some(profile) = &resolution.profile {
+            notes.push(format!("from the profile '{}'", profile));
+        }
+        let kind = match resolution.kind {
+            Some(kind) => {
+                let excluded = resolution.excluded.iter();
+                notes.extend(excluded.map(|key| format!("excluded {}", key)));
+                kind.name()
+            }
+            None if resolution.excluded.is_empty() => "missing",
+            None => "excluded",
+        };
+        let missing = resolution.missing.iter();
+        notes.extend(missing.map(|name| format!("{} not found", name)));
+        let mut line = format!(
+            "{:width$}  {:8}  {}",
+            resolution.name,
+            kind,
+            resolution.templates.join(", "),
+            width = width.unwrap_or_default()
+        );
+        if !notes.is_empty() {
+            line = format!("{} ({})", line.trim_end(), notes.join(", "));
+        }
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
+
+/// The error for a name that isn't a template or alias, suggesting ones that
+/// are close to it.
+fn unknown_name(app: &Core, name: &str, simple: bool) -> anyhow::Error {
+    suggest(app, name, simple, &[], "template")
+}
+
+/// Like `unknown_name`, but only suggests other entries of `kinds` and calls
+/// `name` a `what`.
+fn suggest(app: &Core, name: &str, simple: bool, kinds: &[Origin], what: &str) -> anyhow::Error {
+    let entries = match app.list_entries(&[], simple, Matching::Substring, kinds) {
+        Ok(entries) => entries,
+        Err(err) => return err,
+    };
+    let close: Vec<_> = fuzzy::search(name, entries, 70)
+        .into_iter()
+        .take(3)
+        .map(|(_, entry)| entry.key)
+        .collect();
+    if close.is_empty() {
+        return anyhow::anyhow!("No {} named '{}'", what, name);
+    }
+    anyhow::anyhow!(
+        "No {} named '{}', did you mean {}?",
+        what,
+        name,
+        close.join(", ")
+    )
+}
+
+/// Opens the file of the user template `name` in the editor, offering to
+/// create it first if it's missing. Returns the editor's exit code if it fails.
+fn edit_template(app: &Core, name: &str) -> Result<i32> {
+    let Some(config) = &app.config else {
+        bail!("No config found, run `git ignore init` to create it");
+    };
+    let file = config.template_file(name)?;
+    if !file.exists() {
+        let question = format!("'{}' doesn't exist, create it?", file.display());
+        if !confirm(&question)? {
+            bail!(
+                "The file of template '{}' is missing, expected it at '{}'",
+                name,
+                file.display()
             );
-        } else if file.exists() && opt.force {
-            eprintln!(
-                "{}: appending results to '.gitignore'",
-                "Info".bold().green()
+        }
+        config::write_stub(&name.to_lowercase(), &file)?;
+    }
+
+    let editor = editor::editor_command(|name| std::env::var(name).ok());
+    let status = editor::edit(&editor, &file)?;
+    if !status.success() {
+        error!("The editor '{}' failed, {}", editor, status);
+        return Ok(status.code().unwrap_or(1));
+    }
+
+    let contents = std::fs::read_to_string(&file)
+        .with_context(|| format!("Could not read '{}'", file.display()))?;
+    println!(
+        "Saved template {} to {}: {} lines, {} bytes",
+        name.blue(),
+        file.to_str().unwrap_or_default().yellow(),
+        contents.lines().count(),
+        contents.len()
+    );
+
+    Ok(0)
+}
+
+/// Prints the aliases in the config, followed by the built-in ones they don't
+/// replace.
+fn list_aliases(app: &Core) {
+    let aliases = app.config.as_ref().map(|config| &config.aliases);
+    let aliases: BTreeMap<_, _> = aliases.into_iter().flatten().collect();
+    let builtin: BTreeMap<_, _> = app.builtin_aliases(false).into_iter().collect();
+    if aliases.is_empty() && builtin.is_empty() {
+        return println!("{}", "No aliases defined".blue());
+    }
+
+    println!("{}", "Available aliases:".bold().green());
+    for (name, members) in aliases {
+        println!("{} => {:?}", name.blue(), members);
+    }
+    for (name, members) in builtin {
+        println!("{} => {:?} {}", name.blue(), members, "(built-in)".dimmed());
+    }
+}
+
+/// Prints the aliases in the config, or writes them to `file`.
+fn export_aliases(app: &Core, file: Option<&Path>) -> Result<()> {
+    let Some(config) = &app.config else {
+        bail!("No config found, run `git ignore init` to create it");
+    };
+    let exported = config.export_aliases()?;
+    match file {
+        Some(file) => {
+            std::fs::write(file, exported)
+                .with_context(|| format!("Could not write '{}'", file.display()))?;
+            info!(
+                "Exported {} aliases to '{}'",
+                config.aliases.len(),
+                file.display()
             );
-            let mut file = OpenOptions::new().append(true).open(&file)?;
-            file.write_all(str.as_bytes())?;
         }
+        None => print!("{}", exported),
+    }
+
+    Ok(())
+}
+
+/// Prints the members of the alias `name` and the templates it expands to,
+/// with members that aren't found in red and nested aliases in yellow.
+fn show_alias(app: &Core, name: &str, format: Format) -> Result<()> {
+    let Some(expansion) = app.expand_alias(name)? else {
+        return Err(suggest(app, name, false, &[Origin::Alias], "alias"));
+    };
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&expansion)?);
+        return Ok(());
+    }
+
+    let members: Vec<String> = expansion
+        .members
+        .iter()
+        .map(|member| {
+            if expansion.missing.contains(member) {
+                member.red().to_string()
+            } else if expansion.resolved.contains(&member.to_lowercase()) {
+                member.to_string()
+            } else {
+                member.yellow().to_string()
+            }
+        })
+        .collect();
+    println!("{}: {}", "Members".bold(), members.join(", "));
+    println!("{}: {}", "Templates".bold(), expansion.resolved.join(", "));
+    if !expansion.missing.is_empty() {
+        let missing: Vec<_> = expansion
+            .missing
+            .iter()
+            .map(|m| m.red().to_string())
+            .collect();
+        println!("{}: {}", "Not found".bold(), missing.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Prints the contents of the template, alias or user template `name`, with
+/// what it is noted on stderr.
+fn show(app: &Core, name: &str, simple: bool, paging: bool) -> Result<()> {
+    let shown = app.show(name, simple)?;
+    let Some(template) = shown.templates.first() else {
+        return Err(unknown_name(app, name, simple));
+    };
+
+    match template.source {
+        Origin::Alias => info!(
+            "'{}' is an alias for {}",
+            template.key,
+            shown.keys().join(", ")
+        ),
+        Origin::User => info!("'{}' is a user template", template.key),
+        Origin::Template => info!("'{}' is a template", template.key),
+    }
+    for name in &shown.unresolved {
+        error!("No template named {}", name);
+    }
+
+    pager::print(&shown.combined, paging)
+}
+
+/// Prints what `name` refers to, followed by anything it shadows.
+fn which(app: &Core, name: &str, simple: bool) -> Result<()> {
+    let matches = app.matches(name, simple)?;
+    let Some((used, shadowed)) = matches.split_first() else {
+        return Err(unknown_name(app, name, simple));
+    };
+
+    let describe = |found: &Match| match found {
+        Match::User { file } => format!("user template read from '{}'", file.display()),
+        Match::Alias { members } => {
+            let members: Vec<_> = members.iter().map(|(member, _)| member.as_str()).collect();
+            format!("alias for {}", members.join(", "))
+        }
+        Match::Template { key, source } => {
+            format!("template cached as '{}' from {}", key, source)
+        }
+    };
+    match used {
+        Match::Alias { members } => {
+            println!("{} is an alias for", name.bold());
+            let width = members.iter().map(|(member, _)| member.len()).max();
+            for (member, found) in members {
+                let resolved = match found {
+                    Member::Alias => "alias, expanded in its place".to_string(),
+                    Member::User(file) => format!("user template read from '{}'", file.display()),
+                    Member::Template(key) => format!("template cached as '{}'", key),
+                    Member::Missing => "not found".red().to_string(),
+                };
+                println!(
+                    "  {:width$}  {}",
+                    member,
+                    resolved,
+                    width = width.unwrap_or(0)
+                );
+            }
+        }
+        _ => println!("{} is a {}", name.bold(), describe(used)),
+    }
+    for found in shadowed {
+        println!("{}", format!("Shadows the {}", describe(found)).dimmed());
+    }
+
+    Ok(())
+}
+
+/// Removes duplicate patterns and extra blank lines from `file`, or
+/// `.gitignore` where `--write` puts it. Returns 1 if `dry_run` finds anything
+/// to change.
+fn tidy_file(
+    app: &Core,
+    file: Option<PathBuf>,
+    sort: bool,
+    dry_run: bool,
+    paging: bool,
+) -> Result<i32> {
+    let file = match file {
+        Some(file) => std::env::current_dir()?.join(file),
+        None => gitignore_dir(false)?.join(".gitignore"),
+    };
+
+    let contents = std::fs::read_to_string(&file)
+        .with_context(|| format!("Could not read '{}'", file.display()))?;
+    let tidied = tidy::tidy(&contents, sort);
+    if tidied.contents == contents {
+        info!("'{}' is already tidy", file.display());
+        return Ok(0);
+    }
+
+    if dry_run {
+        let diff = diff::unified(&contents, &tidied.contents, &file.display().to_string());
+        pager::print(&diff, paging)?;
+        return Ok(1);
+    }
+
+    if app.config.as_ref().and_then(|c| c.backup).unwrap_or(false) {
+        backup_file(&file)?;
+    }
+    replace_atomically(&file, &tidied.contents)?;
+    info!(
+        "Tidied '{}', removed {} duplicate patterns and {} blank lines{}",
+        file.display(),
+        tidied.duplicates,
+        tidied.blank_lines,
+        if tidied.sorted { " and sorted it" } else { "" }
+    );
+
+    Ok(0)
+}
+
+/// Prints the completions for `shell`, or writes them to `out` or where the
+/// shell looks for them with `install`.
+fn completions(
+    shell: Option<Shell>,
+    out: Option<PathBuf>,
+    install: bool,
+    force: bool,
+) -> Result<()> {
+    let dir = match (shell, out) {
+        (_, Some(out)) => std::env::current_dir()?.join(out),
+        (Some(shell), None) if install => {
+            completion::install_dir(shell, |name| std::env::var(name).ok())?
+        }
+        (Some(shell), None) => {
+            print_completion(shell, &mut Cli::command());
+            return Ok(());
+        }
+        (None, None) => bail!("No shell was given"),
+    };
+
+    let shells = match shell {
+        Some(shell) => vec![shell],
+        None => Shell::value_variants().to_vec(),
+    };
+    for file in completion::write(&shells, &dir, force)? {
+        info!("Wrote '{}'", file.display());
+    }
+    if let Some(hint) = shell.and_then(|shell| completion::setup_hint(shell, &dir)) {
+        info!("{}", hint);
+    }
+
+    Ok(())
+}
+
+/// Registers `git ignore` as a git alias and installs completions for
+/// `shell`, reporting what changed so it can be undone. With `dry_run` the
+/// changes are only printed.
+fn register_git_alias(shell: Option<Shell>, force: bool, dry_run: bool) -> Result<()> {
+    let path = std::env::var_os("PATH");
+    match git_alias::find_on_path("git-ignore", path.as_deref()) {
+        Some(bin) => debug!("Found git-ignore at '{}'", bin.display()),
+        None => warning!(
+            "git-ignore isn't on your PATH, add '{}' to it for the alias to work",
+            std::env::current_exe()?
+                .parent()
+                .unwrap_or(Path::new(""))
+                .display()
+        ),
+    }
+
+    let command = git_alias::command(git_alias::VALUE);
+    match git_alias::plan(git_alias::current()?.as_deref(), force)? {
+        git_alias::Plan::Unchanged => info!("The git alias 'ignore' is already set up"),
+        git_alias::Plan::Set { .. } if dry_run => info!("Would run `{}`", command),
+        git_alias::Plan::Set { previous } => {
+            git_alias::set()?;
+            info!("Ran `{}`", command);
+            match previous {
+                Some(previous) => info!(
+                    "It was '{}', run `{}` to restore it",
+                    previous,
+                    git_alias::command(&previous)
+                ),
+                None => info!(
+                    "Run `git config --global --unset {}` to remove it",
+                    git_alias::KEY
+                ),
+            }
+        }
+    }
+
+    let Some(shell) = shell else {
+        return Ok(());
+    };
+    let dir = completion::install_dir(shell, |name| std::env::var(name).ok())?;
+    if dry_run {
+        info!("Would write '{}'", completion::file(shell, &dir).display());
+        return Ok(());
+    }
+    for file in completion::write(&[shell], &dir, force)? {
+        info!(
+            "Wrote '{}', remove it to uninstall the completions",
+            file.display()
+        );
+    }
+    if let Some(hint) = completion::setup_hint(shell, &dir) {
+        info!("{}", hint);
+    }
+
+    Ok(())
+}
+
+/// Shows the manual page for `command`, or the main one, with `man`. With
+/// `print_roff` the roff is printed instead.
+fn man(command: Option<&str>, print_roff: bool) -> Result<()> {
+    let pages = cli::man_pages(Cli::command());
+    let name = match command {
+        Some(command) => format!("git-ignore-{}.1", command),
+        None => "git-ignore.1".to_string(),
+    };
+    let Some((_, page)) = pages.iter().find(|(page, _)| *page == name) else {
+        let commands: Vec<_> = pages
+            .iter()
+            .filter_map(|(page, _)| page.strip_prefix("git-ignore-")?.strip_suffix(".1"))
+            .collect();
+        bail!(
+            "There is no manual page for '{}', there is one for {}",
+            command.unwrap_or_default(),
+            commands.join(", ")
+        );
+    };
+
+    let mut roff = Vec::new();
+    page.render(&mut roff)?;
+    if print_roff {
+        return pager::print(&String::from_utf8_lossy(&roff), false);
+    }
+
+    let mut child = std::process::Command::new("man")
+        .args(["-l", "-"])
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .context("Could not run 'man', pass '--roff' to print the page instead")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // `man` may quit before reading all of it, which is fine
+        let _ = stdin.write_all(&roff);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!(
+            "'man' exited with {}, pass '--roff' to print the page instead",
+            status
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the result of every check and returns the exit code of the worst one.
+fn doctor(app: &Core, offline: bool) -> Result<i32> {
+    let checks = doctor::run(app, offline);
+    for check in &checks {
+        let label = match check.status {
+            Status::Skipped => "skip".dimmed(),
+            Status::Passed => "pass".green(),
+            Status::Warning => "warn".yellow(),
+            Status::Failed => "fail".red(),
+        };
+        println!("{}  {}", label.bold(), check.message);
+        if let Some(hint) = &check.hint {
+            println!("      {}", hint.dimmed());
+        }
+    }
+
+    let worst = checks.iter().map(|check| check.status).max();
+    Ok(worst.map_or(0, Status::exit_code))
+}
+
+fn stats(app: &Core, format: Format) -> Result<()> {
+    let stats = app.stats()?;
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    let sources: Vec<String> = stats
+        .sources
+        .iter()
+        .map(|(source, count)| format!("{} {}", source, count))
+        .collect();
+    if sources.is_empty() {
+        println!("{}: {}", "Templates".bold(), stats.templates);
     } else {
-        let stdout = io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(str.as_bytes())?;
+        println!(
+            "{}: {} ({})",
+            "Templates".bold(),
+            stats.templates,
+            sources.join(", ")
+        );
+    }
+    println!("{}: {}", "Aliases".bold(), stats.aliases);
+    println!("{}: {}", "User templates".bold(), stats.user_templates);
+    let age = app.cache_age().map(format_age);
+    match (stats.cache_size, age) {
+        (0, _) => println!("{}: {}", "Cache".bold(), stats.cache_dir.display()),
+        (size, Some(age)) => println!(
+            "{}: {} ({}, updated {})",
+            "Cache".bold(),
+            stats.cache_dir.display(),
+            format_size(size),
+            age
+        ),
+        (size, None) => println!(
+            "{}: {} ({})",
+            "Cache".bold(),
+            stats.cache_dir.display(),
+            format_size(size)
+        ),
+    }
+    match &stats.config {
+        Some(config) => println!("{}: {}", "Config".bold(), config.display()),
+        None => println!("{}: {}", "Config".bold(), "none".dimmed()),
+    }
+
+    if !app.cache_exists() {
+        info!("No cache found, pass '-u' to create it");
     }
 
     Ok(())
 }
+
+/// Removes everything we have written, i.e. the cache, config and user
+/// templates, after listing the paths and asking for confirmation.
+fn purge(app: &Core, yes: bool, dry_run: bool) -> Result<()> {
+    let cache = app.cache_exists().then(|| app.cache_dir().to_path_buf());
+    let paths: Vec<PathBuf> = config::state_paths(app.config_file())
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect();
+
+    if cache.is_none() && paths.is_empty() {
+        info!("Nothing to purge");
+        return Ok(());
+    }
+
+    let header = if dry_run { "Would remove" } else { "Removing" };
+    let listed: Vec<String> = cache
+        .iter()
+        .chain(&paths)
+        .map(|path| format!("  {}", path.display()))
+        .collect();
+    warning!("{}:\n{}", header, listed.join("\n"));
+
+    if dry_run {
+        return Ok(());
+    }
+    if !yes && !confirm("Remove all of the above?")? {
+        info!("Nothing was removed");
+        return Ok(());
+    }
+
+    if cache.is_some() {
+        app.clear_cache()?;
+    }
+    for path in &paths {
+        if path.is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        }
+        .with_context(|| format!("Could not remove '{}'", path.display()))?;
+
+        // Only succeeds if we were the last thing in there
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::remove_dir(parent);
+        }
+    }
+
+    info!(
+        "Removed {} paths",
+        paths.len() + usize::from(cache.is_some())
+    );
+
+    Ok(())
+}
+
+/// Where `--write` puts `.gitignore`, the root of the repository unless `here`
+/// is set or we're not in one.
+fn gitignore_dir(here: bool) -> Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    if here {
+        return Ok(cwd);
+    }
+
+    let git_dir = std::env::var_os("GIT_DIR").map(PathBuf::from);
+    match repo_root(&cwd, git_dir.as_deref()) {
+        Some(root) => {
+            debug!("Found the repository root at '{}'", root.display());
+            Ok(root)
+        }
+        None => {
+            info!("Not in a git repository, writing to the current directory");
+            Ok(cwd)
+        }
+    }
+}
+
+/// Where `--local-exclude` writes to, fails outside of a repository.
+fn local_exclude() -> Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    let git_dir = std::env::var_os("GIT_DIR").map(PathBuf::from);
+    let Some(root) = repo_root(&cwd, git_dir.as_deref()) else {
+        bail!("Not in a git repository, '--local-exclude' needs one to write to");
+    };
+
+    exclude_file(&root)
+}
+
+/// Prints or writes the requested templates.
+fn output(app: &Core, opt: Cli) -> Result<i32> {
+    let args = &opt.generate;
+    let detected = if args.auto {
+        app.autodetect_templates()?
+    } else {
+        Vec::new()
+    };
+    // Without names to go on, let the user pick them when there's someone to ask
+    let picking = opt.templates.is_empty()
+        && !opt.list
+        && !opt.update
+        && io::stdin().is_terminal()
+        && io::stdout().is_terminal();
+    let mut templates = requested_names(opt.templates, detected);
+    if picking {
+        let entries = app.list_entries(&[], opt.simple, Matching::Substring, &[])?;
+        templates = picker::pick(&entries, templates)?;
+        if templates.is_empty() {
+            info!("No templates were picked");
+            return Ok(0);
+        }
+    }
+
+    if opt.update && templates.is_empty() {
+        return Ok(0);
+    }
+
+    // Defaults come after everything asked for, but never on their own
+    let defaults = if args.no_defaults || args.remove || templates.is_empty() {
+        Vec::new()
+    } else {
+        missing_defaults(&templates, app.default_templates(opt.simple))
+    };
+    templates.extend(defaults.iter().cloned());
+
+    let writing = args.output.is_some()
+        || args.write
+        || args.merge
+        || args.remove
+        || args.dry_run
+        || args.local_exclude
+        || args.global;
+
+    // Generates the output for `names`, along with the templates in it
+    let generate = |names: &[String]| -> Result<(String, Generated)> {
+        let mut generated = app.get_templates(names, opt.simple)?;
+        // A default that can't be found shouldn't fail every run
+        generated.unresolved.retain(|unresolved| {
+            let name = unresolved.alias.as_ref().unwrap_or(&unresolved.name);
+            let default = defaults.iter().any(|d| d.eq_ignore_ascii_case(name));
+            if default {
+                warning!(
+                    "Could not find {} from default_templates in the config",
+                    unresolved
+                );
+            }
+            !default
+        });
+        for name in &generated.unresolved {
+            error!("No template named {}", name);
+        }
+        if args.strict && !generated.unresolved.is_empty() {
+            let done = if writing { "written" } else { "printed" };
+            bail!("Not every template was found, nothing was {}", done);
+        }
+        generated.ensure_content(args.auto)?;
+
+        let str = match args.format {
+            Format::Plain => {
+                let endings = args
+                    .line_endings
+                    .or_else(|| app.config.as_ref().and_then(|c| c.line_endings))
+                    .unwrap_or_default();
+                let (translated, warnings) = dialect::translate(&generated.combined, args.kind);
+                for message in warnings {
+                    warning!("{}", message);
+                }
+                convert_line_endings(&translated, endings.is_crlf())
+            }
+            Format::Json => serde_json::to_string_pretty(&generated)? + "\n",
+        };
+        Ok((str, generated))
+    };
+
+    let mut keys = Vec::new();
+    let mut missing = Vec::new();
+    let matching = if opt.exact {
+        Matching::Exact
+    } else if opt.prefix {
+        Matching::Prefix
+    } else if opt.regex {
+        Matching::Regex
+    } else {
+        Matching::Substring
+    };
+    let kinds: Vec<Origin> = opt
+        .types
+        .iter()
+        .map(|kind| match kind {
+            EntryType::Template => Origin::Template,
+            EntryType::Alias => Origin::Alias,
+            EntryType::User => Origin::User,
+        })
+        .collect();
+    let str = if opt.list {
+        let str = if args.format == Format::Json {
+            let entries = app.list_entries(&templates, opt.simple, matching, &kinds)?;
+            if entries.is_empty() && !templates.is_empty() {
+                String::new()
+            } else {
+                serde_json::to_string_pretty(&entries)? + "\n"
+            }
+        } else if opt.long {
+            long_list(&app.list_entries(&templates, opt.simple, matching, &kinds)?)
+        } else {
+            let plain = opt.plain || !io::stdout().is_terminal();
+            app.list(&templates, opt.simple, matching, &kinds, opt.origin, plain)?
+        };
+
+        if str.is_empty() && !templates.is_empty() {
+            bail!("No templates match {}", templates.join(", "));
+        }
+        str
+    } else if templates.is_empty() && !args.auto && !writing {
+        let mut app = Cli::command();
+        app.render_help().to_string()
+    } else {
+        let (str, generated) = generate(&templates)?;
+        for name in &app.exclude {
+            if !generated.excluded().contains(name) {
+                info!("'{}' wasn't going to be included, nothing to exclude", name);
+            }
+        }
+        keys = generated.keys().to_vec();
+        missing = generated.unresolved;
+        str
+    };
+    // What's found is still printed or written, but scripts should notice
+    let found_all = || {
+        if missing.is_empty() {
+            return Ok(0);
+        }
+        let names: Vec<_> = missing.iter().map(|u| u.name.as_str()).collect();
+        bail!("Could not find {}", names.join(", "))
+    };
+
+    let output = match (
+        &args.output,
+        args.write || args.merge || args.remove || args.dry_run,
+    ) {
+        (Some(path), _) => output_path(&std::env::current_dir()?, path),
+        (None, _) if args.local_exclude => Some(local_exclude()?),
+        (None, _) if args.global => Some(global_excludes_file()?),
+        (None, true) => Some(gitignore_dir(args.here)?.join(args.kind.file_name())),
+        (None, false) => None,
+    };
+
+    match output {
+        Some(file) => {
+            debug!("Writing to '{}'", file.display());
+            let backup = args.backup || app.config.as_ref().and_then(|c| c.backup).unwrap_or(false);
+            // Without a terminal to ask on we refuse to touch existing files
+            let ask = !(args.overwrite || args.append || args.force || args.yes || args.dry_run)
+                && io::stdin().is_terminal();
+            let mut change = if args.remove {
+                plan_remove(&file, &keys, &str)?
+            } else if args.merge {
+                plan_merge(&file, &str)?
+            } else {
+                let existing = if args.overwrite {
+                    Existing::Overwrite
+                } else if args.append || args.force || args.yes || ask {
+                    Existing::Append
+                } else {
+                    Existing::Refuse
+                };
+                plan_write(&file, &str, existing, args.format == Format::Plain)?
+            };
+
+            // Don't stack another copy of templates that are already there
+            if change.written == Written::Appended && !args.force_duplicate {
+                let written = written_templates(&change.before);
+                let (skipped, left): (Vec<_>, Vec<_>) = keys
+                    .iter()
+                    .cloned()
+                    .partition(|key| written.contains(&key.to_lowercase()));
+                let name = file
+                    .file_name()
+                    .unwrap_or(file.as_os_str())
+                    .to_string_lossy();
+                for key in &skipped {
+                    info!("{} already in {}, skipping", key, name);
+                }
+
+                if left.is_empty() {
+                    info!("Nothing was written");
+                    return found_all();
+                } else if !skipped.is_empty() {
+                    let (regenerated, _) = generate(&left)?;
+                    change = plan_write(
+                        &file,
+                        &regenerated,
+                        Existing::Append,
+                        args.format == Format::Plain,
+                    )?;
+                }
+            }
+
+            if ask && change.written == Written::Appended {
+                let lines = change.after.lines().count() - change.before.lines().count();
+                warning!(
+                    "'{}' already exists, {} lines would be appended to it",
+                    file.display(),
+                    lines
+                );
+                if !confirm("Proceed?")? {
+                    info!("Nothing was written");
+                    return found_all();
+                }
+            }
+
+            if args.dry_run {
+                if change.is_empty() {
+                    info!("'{}' is up to date", file.display());
+                    return found_all();
+                }
+                pager::print(
+                    &diff::unified(&change.before, &change.after, &file.display().to_string()),
+                    !args.no_pager,
+                )?;
+                return Ok(1);
+            }
+
+            let written = apply(&file, &change, backup)?;
+            match written {
+                Written::Created => info!("Created '{}'", file.display()),
+                Written::Appended => info!("Appended results to '{}'", file.display()),
+                Written::Overwritten => info!("Overwrote '{}'", file.display()),
+                Written::Replaced => info!("Updated the generated block in '{}'", file.display()),
+                Written::Merged(count) => info!(
+                    "Appended {} missing patterns to '{}'",
+                    count,
+                    file.display()
+                ),
+                Written::Removed(count) => {
+                    info!("Removed {} patterns from '{}'", count, file.display())
+                }
+                Written::Unchanged if args.remove => info!(
+                    "'{}' has none of the patterns, nothing was written",
+                    file.display()
+                ),
+                Written::Unchanged => info!(
+                    "'{}' already has every pattern, nothing was written",
+                    file.display()
+                ),
+            }
+        }
+        None => pager::print(&str, !args.no_pager)?,
+    }
+
+    found_all()
+}
@@ -0,0 +1,146 @@
+use std::{
+    io::{stdout, ErrorKind, IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+use anyhow::Result;
+
+/// Used when neither `GIT_PAGER`, `core.pager` or `PAGER` is set, quits right
+/// away if the output fits on the screen and keeps colors.
+const DEFAULT_PAGER: &str = "less -FRX";
+
+/// Prints `output` to stdout, through a pager if stdout is a terminal and the
+/// output doesn't fit on it. Falls back to printing directly if the pager
+/// can't be started.
+pub fn print(output: &str, paging: bool) -> Result<()> {
+    if paging && stdout().is_terminal() && !fits(output, screen_height()) {
+        let pager = pager_command(|name| std::env::var(name).ok(), core_pager);
+        if pager.is_some_and(|pager| page(&pager, output).is_ok()) {
+            return Ok(());
+        }
+    }
+
+    let mut handle = stdout().lock();
+    match handle.write_all(output.as_bytes()) {
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        res => Ok(res?),
+    }
+}
+
+/// Finds the pager to use the same way git does, `None` if paging is turned
+/// off by setting it to an empty string or `cat`.
+fn pager_command<E, C>(env: E, core_pager: C) -> Option<String>
+where
+    E: Fn(&str) -> Option<String>,
+    C: FnOnce() -> Option<String>,
+{
+    let pager = env("GIT_PAGER")
+        .or_else(core_pager)
+        .or_else(|| env("PAGER"))
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+
+    match pager.trim() {
+        "" | "cat" => None,
+        pager => Some(pager.to_string()),
+    }
+}
+
+/// Reads `core.pager` from the git config, if git is installed.
+fn core_pager() -> Option<String> {
+    let output = Command::new("git")
+        .args(["config", "--get", "core.pager"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The height of the terminal, only known if the shell exports `LINES`.
+fn screen_height() -> Option<usize> {
+    std::env::var("LINES").ok()?.parse().ok()
+}
+
+/// Whether the output fits on the screen without paging, when the height is
+/// unknown we leave it to the pager to decide.
+fn fits(output: &str, height: Option<usize>) -> bool {
+    height.is_some_and(|height| output.lines().count() < height)
+}
+
+/// Runs `cmd` through the shell like git does, so it can have arguments, e.g.
+/// `less -R` or `code --wait`. Arguments added to the command are passed on to
+/// it. Windows has no `sh`, so `cmd` is split on whitespace instead.
+pub fn shell_command(cmd: &str) -> Command {
+    if cfg!(windows) {
+        let mut parts = cmd.split_whitespace();
+        let mut command = Command::new(parts.next().unwrap_or_default());
+        command.args(parts);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.args(["-c", &format!("{} \"$@\"", cmd), cmd]);
+        command
+    }
+}
+
+/// Looks up variables in `vars` instead of the environment.
+#[cfg(test)]
+pub fn env(vars: &[(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: std::collections::HashMap<_, _> = vars.iter().cloned().collect();
+    move |name| vars.get(name).map(|v| v.to_string())
+}
+
+fn page(pager: &str, output: &str) -> std::io::Result<()> {
+    let mut child = shell_command(pager).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager closing early, e.g. by quitting `less`, is not an error
+        match stdin.write_all(output.as_bytes()) {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+    child.wait()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{env, fits, pager_command, DEFAULT_PAGER};
+
+    #[test]
+    fn pager_follows_git_precedence() {
+        let vars = env(&[("GIT_PAGER", "most"), ("PAGER", "more")]);
+        assert_eq!(
+            pager_command(&vars, || Some("bat".into())).as_deref(),
+            Some("most")
+        );
+
+        let vars = env(&[("PAGER", "more")]);
+        assert_eq!(
+            pager_command(&vars, || Some("bat".into())).as_deref(),
+            Some("bat")
+        );
+        assert_eq!(pager_command(&vars, || None).as_deref(), Some("more"));
+        assert_eq!(
+            pager_command(env(&[]), || None).as_deref(),
+            Some(DEFAULT_PAGER)
+        );
+    }
+
+    #[test]
+    fn pager_can_be_disabled() {
+        assert_eq!(pager_command(env(&[("GIT_PAGER", "cat")]), || None), None);
+        assert_eq!(pager_command(env(&[("PAGER", "")]), || None), None);
+    }
+
+    #[test]
+    fn short_output_fits() {
+        assert!(fits("a\nb\n", Some(24)));
+        assert!(!fits(&"a\n".repeat(30), Some(24)));
+        assert!(!fits("a\n", None));
+    }
+}
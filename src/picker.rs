@@ -0,0 +1,172 @@
+use std::io::{stderr, stdin, BufRead, Write};
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::{
+    fuzzy,
+    ignore::{ListEntry, Origin},
+};
+
+/// How many matches are shown for a search.
+const SHOWN: usize = 15;
+
+/// The lowest fuzzy score a template needs to be shown for a search.
+const THRESHOLD: u32 = 50;
+
+/// Lets the user pick templates from `entries` on the terminal by searching
+/// for them and toggling them by number, starting with `selected` picked.
+/// Returns the picked names in the order they were picked, or nothing if
+/// cancelled.
+pub fn pick(entries: &[ListEntry], selected: Vec<String>) -> Result<Vec<String>> {
+    run(entries, selected, &mut stdin().lock(), &mut stderr())
+}
+
+fn run(
+    entries: &[ListEntry],
+    mut selected: Vec<String>,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Vec<String>> {
+    writeln!(
+        output,
+        "Type to search, numbers to pick or unpick templates, enter when done or ctrl-d to cancel"
+    )?;
+    let mut shown: Vec<ListEntry> = entries
+        .iter()
+        .filter(|entry| selected.contains(&entry.key))
+        .cloned()
+        .collect();
+
+    loop {
+        for (i, entry) in shown.iter().enumerate() {
+            let mark = if selected.contains(&entry.key) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            let badge = match entry.kind {
+                Origin::Template => String::new(),
+                kind => format!(" {}", format!("({})", kind.name()).dimmed()),
+            };
+            writeln!(output, "{:>3}. {} {}{}", i + 1, mark, entry.key, badge)?;
+        }
+        if !selected.is_empty() {
+            writeln!(output, "{} {}", "Picked:".bold(), selected.join(", "))?;
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(Vec::new());
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(selected);
+        }
+
+        let numbers: Option<Vec<usize>> = line
+            .split([' ', ','])
+            .filter(|n| !n.is_empty())
+            .map(|n| n.parse().ok())
+            .collect();
+        match numbers {
+            Some(numbers) => {
+                for n in numbers {
+                    let Some(entry) = n.checked_sub(1).and_then(|i| shown.get(i)) else {
+                        writeln!(output, "There is no template numbered {}", n)?;
+                        continue;
+                    };
+                    match selected.iter().position(|key| *key == entry.key) {
+                        Some(i) => drop(selected.remove(i)),
+                        None => selected.push(entry.key.clone()),
+                    }
+                }
+            }
+            None => {
+                shown = fuzzy::search(line, entries.to_vec(), THRESHOLD)
+                    .into_iter()
+                    .take(SHOWN)
+                    .map(|(_, entry)| entry)
+                    .collect();
+                if shown.is_empty() {
+                    writeln!(output, "No templates match '{}'", line)?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::ignore::{ListEntry, Origin};
+
+    fn entries() -> Vec<ListEntry> {
+        let entry = |key: &str, kind| ListEntry {
+            key: key.to_string(),
+            kind,
+            name: None,
+            targets: Vec::new(),
+            source: None,
+            shadows: false,
+        };
+        vec![
+            entry("python", Origin::Template),
+            entry("rust", Origin::Template),
+            entry("rustrover", Origin::Template),
+            entry("web", Origin::Alias),
+            entry("notes", Origin::User),
+        ]
+    }
+
+    fn pick(selected: &[&str], input: &str) -> (Vec<String>, String) {
+        colored::control::set_override(false);
+        let selected = selected.iter().map(ToString::to_string).collect();
+        let mut output = Vec::new();
+        let picked = run(&entries(), selected, &mut input.as_bytes(), &mut output).unwrap();
+        (picked, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn picks_searched_templates() {
+        let (picked, output) = pick(&[], "rust\n1\nweb\n1\n\n");
+        assert_eq!(picked, ["rust", "web"]);
+        assert!(output.contains("  1. [ ] rust\n  2. [ ] rustrover\n"));
+        assert!(output.contains("  1. [x] web (alias)\n"));
+        assert!(output.contains("Picked: rust, web\n"));
+    }
+
+    #[test]
+    fn toggles_picks() {
+        let (picked, _) = pick(&[], "rust\n1 2\n1\n\n");
+        assert_eq!(picked, ["rustrover"]);
+
+        let (picked, output) = pick(&["python"], "1,\n\n");
+        assert!(picked.is_empty());
+        assert!(output.contains("  1. [x] python\n"));
+    }
+
+    #[test]
+    fn starts_with_detected_templates() {
+        let (picked, output) = pick(&["python", "notes"], "\n");
+        assert_eq!(picked, ["python", "notes"]);
+        assert!(output.contains("  1. [x] python\n  2. [x] notes (user)\n"));
+    }
+
+    #[test]
+    fn reports_bad_input() {
+        let (picked, output) = pick(&[], "3\nxyzzy\n\n");
+        assert!(picked.is_empty());
+        assert!(output.contains("There is no template numbered 3\n"));
+        assert!(output.contains("No templates match 'xyzzy'\n"));
+    }
+
+    #[test]
+    fn cancels_at_end_of_input() {
+        let (picked, _) = pick(&["python"], "");
+        assert!(picked.is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    io::{stderr, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{ignore::format_size, log::is_quiet};
+
+/// How often the progress line is redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A single line on `stderr` showing how much of a download is done, only
+/// drawn when `stderr` is a terminal so it never ends up in logs or pipes. The
+/// line is cleared again when the progress is dropped.
+pub struct Progress {
+    label: String,
+    total: Option<u64>,
+    current: u64,
+    enabled: bool,
+    last_draw: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(label: String, total: Option<u64>) -> Self {
+        Progress {
+            label,
+            total,
+            current: 0,
+            enabled: stderr().is_terminal() && !is_quiet(),
+            last_draw: None,
+        }
+    }
+
+    /// Records that `bytes` more have been downloaded.
+    pub fn advance(&mut self, bytes: usize) {
+        self.current += bytes as u64;
+
+        if !self.enabled
+            || self
+                .last_draw
+                .is_some_and(|t| t.elapsed() < REDRAW_INTERVAL)
+        {
+            return;
+        }
+
+        self.last_draw = Some(Instant::now());
+        eprint!("\r\x1b[2K{}", self.line());
+        let _ = stderr().flush();
+    }
+
+    fn line(&self) -> String {
+        match self.total {
+            Some(total) if total > 0 => format!(
+                "{}: {:>3}% ({} / {})",
+                self.label,
+                (self.current * 100 / total).min(100),
+                format_size(self.current),
+                format_size(total)
+            ),
+            _ => format!("{}: {}", self.label, format_size(self.current)),
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.enabled && self.last_draw.is_some() {
+            eprint!("\r\x1b[2K");
+            let _ = stderr().flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Progress;
+
+    #[test]
+    fn shows_percentage_with_known_size() {
+        let mut progress = Progress::new("Downloading".into(), Some(2048));
+        progress.enabled = false;
+        progress.advance(1024);
+        assert_eq!(progress.line(), "Downloading:  50% (1.0 KiB / 2.0 KiB)");
+    }
+
+    #[test]
+    fn shows_bytes_with_unknown_size() {
+        let mut progress = Progress::new("Downloading".into(), None);
+        progress.enabled = false;
+        progress.advance(512);
+        assert_eq!(progress.line(), "Downloading: 512 B");
+    }
+}
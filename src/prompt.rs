@@ -0,0 +1,52 @@
+use std::io::{stderr, stdin, BufRead, Write};
+
+use anyhow::Result;
+
+/// Asks a yes or no question on the terminal, defaulting to no.
+pub fn confirm(question: &str) -> Result<bool> {
+    ask(question, &mut stdin().lock(), &mut stderr())
+}
+
+/// Asks `question` on `output` and reads the answer from `input`, anything but
+/// `y` or `yes` is a no, including no answer at all.
+fn ask(question: &str, input: &mut impl BufRead, output: &mut impl Write) -> Result<bool> {
+    write!(output, "{} [y/N] ", question)?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        // Keep whatever is printed next off the prompt line
+        writeln!(output)?;
+    }
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ask;
+
+    fn answer(input: &str) -> (bool, String) {
+        let mut output = Vec::new();
+        let yes = ask("Proceed?", &mut input.as_bytes(), &mut output).unwrap();
+        (yes, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn accepts_yes() {
+        assert_eq!(answer("y\n"), (true, "Proceed? [y/N] ".into()));
+        assert!(answer(" YES \n").0);
+    }
+
+    #[test]
+    fn defaults_to_no() {
+        assert!(!answer("n\n").0);
+        assert!(!answer("\n").0);
+        assert!(!answer("yep\n").0);
+    }
+
+    #[test]
+    fn end_of_input_is_no() {
+        assert_eq!(answer(""), (false, "Proceed? [y/N] \n".into()));
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+/// The result of tidying a `.gitignore`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Tidied {
+    pub contents: String,
+    /// How many duplicate patterns were removed
+    pub duplicates: usize,
+    /// How many blank lines were removed
+    pub blank_lines: usize,
+    /// Whether `--sort` moved any patterns
+    pub sorted: bool,
+}
+
+/// A line of the file split from its line ending, so lines can be moved
+/// without gluing the last one to another.
+#[derive(Debug, Clone, Copy)]
+struct Line<'a> {
+    text: &'a str,
+    ending: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn split(line: &'a str) -> Self {
+        let text = line.trim_end_matches(['\r', '\n']);
+        Line {
+            text,
+            ending: &line[text.len()..],
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    fn is_pattern(&self) -> bool {
+        !self.is_blank() && !self.text.starts_with('#')
+    }
+
+    fn is_negation(&self) -> bool {
+        self.text.starts_with('!')
+    }
+
+    /// The pattern git sees, trailing whitespace is ignored unless escaped.
+    fn pattern(&self) -> &'a str {
+        let trimmed = self.text.trim_end();
+        if trimmed.ends_with('\\') && trimmed.len() < self.text.len() {
+            &self.text[..=trimmed.len()]
+        } else {
+            trimmed
+        }
+    }
+}
+
+/// Removes duplicate patterns, keeping the first, and collapses runs of three
+/// or more blank lines into one. With `sort` the patterns between comments and
+/// blank lines are sorted too.
+///
+/// Git uses the last pattern that matches a path, so neither can change what
+/// is ignored. A duplicate is kept if a pattern of the opposite kind comes
+/// between it and the previous copy, e.g. `*.log` after `!keep.log` ignores
+/// `keep.log` again. Sorting only reorders runs of patterns that are all
+/// negations or none of them, so a `!pattern` never moves above the pattern
+/// it negates.
+pub fn tidy(contents: &str, sort: bool) -> Tidied {
+    let lines: Vec<Line> = contents.split_inclusive('\n').map(Line::split).collect();
+
+    let (lines, duplicates) = remove_duplicates(lines);
+    let (mut lines, blank_lines) = collapse_blank_lines(lines);
+    let sorted = sort && sort_patterns(&mut lines);
+
+    Tidied {
+        contents: lines.iter().flat_map(|l| [l.text, l.ending]).collect(),
+        duplicates,
+        blank_lines,
+        sorted,
+    }
+}
+
+fn remove_duplicates(lines: Vec<Line>) -> (Vec<Line>, usize) {
+    // Where each pattern was last kept, to see what came after it
+    let mut kept = HashMap::new();
+    let mut last_negation = None;
+    let mut last_pattern = None;
+    let mut removed = 0;
+    let mut result = Vec::with_capacity(lines.len());
+
+    for line in lines {
+        if !line.is_pattern() {
+            result.push(line);
+            continue;
+        }
+
+        let opposite = if line.is_negation() {
+            last_pattern
+        } else {
+            last_negation
+        };
+        let previous = kept.get(line.pattern()).copied();
+        if previous.is_some_and(|previous| opposite.is_none_or(|o| o < previous)) {
+            removed += 1;
+            continue;
+        }
+
+        let index = result.len();
+        kept.insert(line.pattern(), index);
+        if line.is_negation() {
+            last_negation = Some(index);
+        } else {
+            last_pattern = Some(index);
+        }
+        result.push(line);
+    }
+
+    (result, removed)
+}
+
+fn collapse_blank_lines(lines: Vec<Line>) -> (Vec<Line>, usize) {
+    let mut result: Vec<Line> = Vec::with_capacity(lines.len());
+    let mut removed = 0;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let run = lines[i..].iter().take_while(|l| l.is_blank()).count();
+        if run == 0 {
+            result.push(lines[i]);
+            i += 1;
+            continue;
+        }
+
+        let keep = if run >= 3 { 1 } else { run };
+        result.extend_from_slice(&lines[i..i + keep]);
+        // The last line may not end in a newline, keep it that way
+        if keep < run && lines[i + run - 1].ending.is_empty() {
+            if let Some(last) = result.last_mut() {
+                last.ending = "";
+            }
+        }
+        removed += run - keep;
+        i += run;
+    }
+
+    (result, removed)
+}
+
+/// Sorts each run of patterns that are all negations or none of them, without
+/// moving the line endings. Returns whether anything moved.
+fn sort_patterns(lines: &mut [Line]) -> bool {
+    let mut moved = false;
+
+    let mut start = 0;
+    while start < lines.len() {
+        let first = lines[start];
+        let run = lines[start..]
+            .iter()
+            .take_while(|l| l.is_pattern() && l.is_negation() == first.is_negation())
+            .count();
+        if run < 2 {
+            start += 1;
+            continue;
+        }
+
+        let group = &mut lines[start..start + run];
+        let mut texts: Vec<&str> = group.iter().map(|l| l.text).collect();
+        texts.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()).then(a.cmp(b)));
+        for (line, text) in group.iter_mut().zip(texts) {
+            moved |= line.text != text;
+            line.text = text;
+        }
+        start += run;
+    }
+
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tidy;
+
+    #[test]
+    fn removes_duplicates_keeping_the_first() {
+        let tidied = tidy("target/\n*.log\n.env\n*.log\ntarget/\n", false);
+        assert_eq!(tidied.contents, "target/\n*.log\n.env\n");
+        assert_eq!(tidied.duplicates, 2);
+
+        // Trailing whitespace is ignored by git unless escaped
+        let tidied = tidy("*.log\n*.log  \nfoo\\ \nfoo\\  \nfoo\n", false);
+        assert_eq!(tidied.contents, "*.log\nfoo\\ \nfoo\n");
+        assert_eq!(tidied.duplicates, 2);
+    }
+
+    #[test]
+    fn keeps_duplicates_that_undo_a_negation() {
+        let contents = "*.log\n!keep.log\n*.log\n";
+        assert_eq!(tidy(contents, false).contents, contents);
+
+        let contents = "!keep.log\n*.log\n!keep.log\n";
+        assert_eq!(tidy(contents, false).contents, contents);
+
+        // Once nothing of the opposite kind comes between them it's a duplicate
+        let tidied = tidy("*.log\n!keep.log\n*.log\n.env\n*.log\n", false);
+        assert_eq!(tidied.contents, "*.log\n!keep.log\n*.log\n.env\n");
+        assert_eq!(tidied.duplicates, 1);
+
+        let tidied = tidy("!keep.log\n!keep.log\n", false);
+        assert_eq!(tidied.contents, "!keep.log\n");
+    }
+
+    #[test]
+    fn leaves_comments_alone() {
+        let contents = "# Logs\n*.log\n\n# Logs\n*.tmp\n";
+        let tidied = tidy(contents, false);
+        assert_eq!(tidied.contents, contents);
+        assert_eq!((tidied.duplicates, tidied.blank_lines), (0, 0));
+    }
+
+    #[test]
+    fn collapses_blank_lines() {
+        let tidied = tidy("a\n\n\nb\n\n\n\n\nc\n\n\n", false);
+        assert_eq!(tidied.contents, "a\n\n\nb\n\nc\n\n\n");
+        assert_eq!(tidied.blank_lines, 3);
+
+        // Whitespace only lines count as blank
+        let tidied = tidy("a\n \n\t\n  \nb", false);
+        assert_eq!(tidied.contents, "a\n \nb");
+    }
+
+    #[test]
+    fn sorts_within_sections() {
+        let contents = "# Build\ntarget/\nDist/\nbuild/\n\n# Editors\n.vscode/\n.idea/\n";
+        let tidied = tidy(contents, true);
+        assert_eq!(
+            tidied.contents,
+            "# Build\nbuild/\nDist/\ntarget/\n\n# Editors\n.idea/\n.vscode/\n"
+        );
+        assert!(tidied.sorted);
+
+        assert!(!tidy(&tidied.contents, true).sorted);
+        assert!(!tidy(contents, false).sorted);
+    }
+
+    #[test]
+    fn never_sorts_across_negations() {
+        let tidied = tidy("z.log\n*.log\n!b.log\n!a.log\nd/\nc/\n", true);
+        assert_eq!(tidied.contents, "*.log\nz.log\n!a.log\n!b.log\nc/\nd/\n");
+
+        // Sorting `!keep.log` above `*.log` would ignore it again
+        let contents = "*.log\n!keep.log\n";
+        assert_eq!(tidy(contents, true).contents, contents);
+    }
+
+    #[test]
+    fn keeps_line_endings() {
+        let tidied = tidy("b\r\na\r\nb\r\n\r\n\r\n\r\nc", true);
+        assert_eq!(tidied.contents, "a\r\nb\r\n\r\nc");
+
+        // The last line without a newline stays last without one
+        let tidied = tidy("b\na", true);
+        assert_eq!(tidied.contents, "a\nb");
+    }
+
+    #[test]
+    fn tidies_messy_files() {
+        let contents = "\
+# >>> git-ignore >>>
+### Created by https://www.gitignore.io
+
+### rust ###
+target/
+Cargo.lock
+**/*.rs.bk
+### end rust ###
+
+# git-ignore: rust
+# <<< git-ignore <<<
+
+
+
+### rust ###
+target/
+Cargo.lock
+**/*.rs.bk
+### end rust ###
+.env
+node_modules
+.env
+!.env.example
+node_modules
+";
+        let tidied = tidy(contents, true);
+        assert_eq!(
+            tidied.contents,
+            "\
+# >>> git-ignore >>>
+### Created by https://www.gitignore.io
+
+### rust ###
+**/*.rs.bk
+Cargo.lock
+target/
+### end rust ###
+
+# git-ignore: rust
+# <<< git-ignore <<<
+
+### rust ###
+### end rust ###
+.env
+node_modules
+!.env.example
+node_modules
+"
+        );
+        assert_eq!((tidied.duplicates, tidied.blank_lines), (4, 2));
+
+        let again = tidy(&tidied.contents, true);
+        assert_eq!(again.contents, tidied.contents);
+        assert_eq!(
+            (again.duplicates, again.blank_lines, again.sorted),
+            (0, 0, false)
+        );
+    }
+
+    #[test]
+    fn handles_empty_files() {
+        let tidied = tidy("", true);
+        assert_eq!(tidied.contents, "");
+        assert_eq!(
+            (tidied.duplicates, tidied.blank_lines, tidied.sorted),
+            (0, 0, false)
+        );
+    }
+}